@@ -0,0 +1,519 @@
+// Composite availability across dependency chains
+//
+// Each node's own availability comes from its target and replica count: replicas fail
+// independently, so the node is down only when all of them are. A node is up when it and
+// everything it relies on are up, so serial dependencies multiply. Edges that share a
+// `redundancy_group` (set on the connection, or on the targets themselves) are alternatives:
+// the group is down only when every member is. Shared dependencies are treated as independent
+// on each path, which slightly understates availability for diamond-shaped graphs.
+
+use crate::events::{Change, ChangeFeed, ComponentChange};
+use crate::lint::{LintContext, NodeRole};
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::system_graph::{self, EdgeVia, SystemGraph, SystemNode};
+use crate::units::{Measure, SECONDS_PER_DAY};
+use crate::{check_revision, ApiError, Component};
+use chrono::Utc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, State};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * SECONDS_PER_DAY;
+// Per entry point, the nodes worth improving first
+const WEAKEST_PER_ENTRY: usize = 5;
+
+/// A fraction between 0 and 1; also accepts strings such as "99.95%"
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Availability(pub f64);
+
+impl Availability {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let trimmed = input.trim();
+        let (number, scale) = match trimmed.strip_suffix('%') {
+            Some(percent) => (percent.trim(), 100.0),
+            None => (trimmed, 1.0),
+        };
+        let value: f64 = number.parse().map_err(|_| format!("Invalid availability '{}'", input))?;
+        Self::checked(value / scale)
+    }
+
+    fn checked(value: f64) -> Result<Self, String> {
+        if (0.0..=1.0).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(format!("Availability {} must be between 0 and 1 (or 0% and 100%)", value))
+        }
+    }
+}
+
+impl Serialize for Availability {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Availability {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(f64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(n) => Self::checked(n),
+            Raw::Text(s) => Self::parse(&s),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AvailabilityInputs {
+    /// Availability of a single instance, e.g. "99.9%"
+    pub target: Availability,
+    /// Independent instances; falls back to the `replicas` metadata value, then 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<u32>,
+}
+
+impl AvailabilityInputs {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.replicas == Some(0) {
+            return Err(ApiError::InvalidComponentData {
+                details: "Availability replicas must be at least 1".to_string(),
+                source: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Independent instances of a component or element: the typed setting, then a `replicas`
+/// attribute. `None` when neither holds a count
+pub fn replicas(inputs: Option<&AvailabilityInputs>, attributes: Option<&HashMap<String, String>>) -> Option<u32> {
+    inputs
+        .and_then(|i| i.replicas)
+        .or_else(|| attributes?.get("replicas")?.trim().parse().ok())
+}
+
+fn inputs<'a>(ctx: &LintContext<'a>, id: &str) -> Option<&'a AvailabilityInputs> {
+    let state: &'a ProjectState = ctx.state;
+    state.project.components.iter().find(|c| c.id == id).and_then(|c| c.availability.as_ref())
+}
+
+/// `replicas` for a node of the system graph
+pub fn node_replicas(ctx: &LintContext, id: &str) -> Option<u32> {
+    replicas(inputs(ctx, id), ctx.attributes(id))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeAvailability {
+    pub node: SystemNode,
+    pub availability: f64,
+    pub display: String,
+    pub downtime_per_year: Measure,
+}
+
+impl NodeAvailability {
+    fn new(node: &SystemNode, availability: f64) -> Self {
+        Self {
+            node: node.clone(),
+            availability,
+            display: format_percent(availability),
+            downtime_per_year: Measure::duration((1.0 - availability) * SECONDS_PER_YEAR),
+        }
+    }
+}
+
+/// How much an entry point would gain if one node never failed
+#[derive(Debug, Clone, Serialize)]
+pub struct Contribution {
+    pub node: SystemNode,
+    /// The node's own availability, replicas included
+    pub own: f64,
+    pub downtime_saved_per_year: Measure,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryAvailability {
+    #[serde(flatten)]
+    pub composite: NodeAvailability,
+    /// Largest contributors to downtime first
+    pub weakest: Vec<Contribution>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AvailabilityReport {
+    pub project_id: String,
+    pub entry_points: Vec<EntryAvailability>,
+    /// Downtime saved across all entry points if each node were perfect, largest first
+    pub bottlenecks: Vec<Contribution>,
+    /// Nodes without a target, counted as always available
+    pub unspecified: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+fn format_percent(availability: f64) -> String {
+    let percent = format!("{:.4}", availability * 100.0);
+    format!("{}%", percent.trim_end_matches('0').trim_end_matches('.'))
+}
+
+/// One alternative within a redundancy group, and the edges that lead to it
+#[derive(Debug, Clone)]
+pub(crate) struct Member<'a> {
+    pub to: &'a str,
+    pub connection_ids: Vec<&'a str>,
+    /// Reached through a component dependency as well, which no connection failure can cut
+    pub via_dependency: bool,
+}
+
+/// Outgoing edges of every node, grouped so that members of a group back each other up.
+/// Edges without a `redundancy_group` form a group of their own.
+pub(crate) fn redundancy_groups<'a>(ctx: &'a LintContext) -> HashMap<&'a str, Vec<Vec<Member<'a>>>> {
+    let mut groups = HashMap::new();
+    for node in ctx.graph.nodes() {
+        let mut keyed: Vec<(String, Vec<Member>)> = Vec::new();
+        for edge in ctx.graph.outgoing(&node.id) {
+            let connection = match &edge.via {
+                EdgeVia::Connection { connection_id, .. } => {
+                    system_graph::connection(ctx.state, connection_id)
+                }
+                EdgeVia::Dependency => None,
+            };
+            let group = connection
+                .and_then(|c| c.properties.get("redundancy_group"))
+                .or_else(|| ctx.attributes(&edge.to).and_then(|a| a.get("redundancy_group")))
+                .map(|g| g.trim().to_string())
+                .unwrap_or_else(|| format!("\0{}", edge.to));
+            let members = match keyed.iter_mut().position(|(key, _)| *key == group) {
+                Some(i) => &mut keyed[i].1,
+                None => {
+                    keyed.push((group, Vec::new()));
+                    &mut keyed.last_mut().unwrap().1
+                }
+            };
+            let member = match members.iter().position(|m| m.to == edge.to) {
+                Some(i) => &mut members[i],
+                None => {
+                    members.push(Member {
+                        to: &edge.to,
+                        connection_ids: Vec::new(),
+                        via_dependency: false,
+                    });
+                    members.last_mut().unwrap()
+                }
+            };
+            match connection {
+                Some(c) => member.connection_ids.push(&c.id),
+                None => member.via_dependency = true,
+            }
+        }
+        groups.insert(node.id.as_str(), keyed.into_iter().map(|(_, members)| members).collect());
+    }
+    groups
+}
+
+struct Model<'a> {
+    graph: &'a SystemGraph,
+    own: HashMap<&'a str, f64>,
+    // Per node, targets grouped so that members of a group back each other up
+    groups: HashMap<&'a str, Vec<Vec<&'a str>>>,
+}
+
+impl<'a> Model<'a> {
+    fn new(ctx: &'a LintContext, unspecified: &mut Vec<String>) -> Self {
+        let graph = &ctx.graph;
+        let mut own = HashMap::new();
+        for node in graph.nodes() {
+            let attributes = ctx.attributes(&node.id);
+            let attribute = |key: &str| attributes.and_then(|a| a.get(key)).map(|v| v.trim());
+            let inputs = inputs(ctx, &node.id);
+            // Diagram elements can carry a plain `availability` property instead
+            let target = inputs
+                .map(|i| i.target)
+                .or_else(|| attribute("availability").and_then(|a| Availability::parse(a).ok()));
+            let replicas = replicas(inputs, attributes).unwrap_or(1).max(1);
+            let availability = match target {
+                Some(target) => 1.0 - (1.0 - target.0).powi(replicas as i32),
+                None => {
+                    unspecified.push(node.id.clone());
+                    1.0
+                }
+            };
+            own.insert(node.id.as_str(), availability);
+        }
+        let groups = redundancy_groups(ctx)
+            .into_iter()
+            .map(|(node, groups)| (node, groups.into_iter().map(|g| g.into_iter().map(|m| m.to).collect()).collect()))
+            .collect();
+        Self { graph, own, groups }
+    }
+
+    /// Composite availability of `root`, optionally with one node assumed never to fail
+    fn composite(&self, root: &'a str, perfect: Option<&str>, warnings: &mut Vec<String>) -> f64 {
+        let mut memo = HashMap::new();
+        let mut stack = Vec::new();
+        self.visit(root, perfect, &mut memo, &mut stack, warnings)
+    }
+
+    fn visit(
+        &self,
+        node: &'a str,
+        perfect: Option<&str>,
+        memo: &mut HashMap<&'a str, f64>,
+        stack: &mut Vec<&'a str>,
+        warnings: &mut Vec<String>,
+    ) -> f64 {
+        if let Some(&known) = memo.get(node) {
+            return known;
+        }
+        if stack.contains(&node) {
+            let warning = format!("Dependency cycle through {}; the edge closing it was ignored", self.label(node));
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+            return 1.0;
+        }
+        stack.push(node);
+        let mut availability = if perfect == Some(node) { 1.0 } else { self.own[node] };
+        for group in &self.groups[node] {
+            let all_down: f64 = group
+                .iter()
+                .map(|member| 1.0 - self.visit(member, perfect, memo, stack, warnings))
+                .product();
+            availability *= 1.0 - all_down;
+        }
+        stack.pop();
+        memo.insert(node, availability);
+        availability
+    }
+
+    fn reachable(&self, root: &'a str) -> Vec<&'a str> {
+        let mut seen = HashSet::from([root]);
+        let mut order = vec![root];
+        let mut next = 0;
+        while let Some(&node) = order.get(next) {
+            next += 1;
+            for member in self.groups[node].iter().flatten() {
+                if seen.insert(member) {
+                    order.push(member);
+                }
+            }
+        }
+        order
+    }
+
+    fn label(&self, id: &str) -> String {
+        self.graph.node(id).map_or_else(|| id.to_string(), |n| n.label.clone())
+    }
+}
+
+/// Frontends, plus anything that relies on other nodes but that nothing relies on
+pub(crate) fn entry_points<'a>(ctx: &'a LintContext) -> Vec<&'a SystemNode> {
+    ctx.graph
+        .nodes()
+        .iter()
+        .filter(|node| {
+            ctx.role(&node.id) == NodeRole::Frontend
+                || (ctx.graph.incoming(&node.id).next().is_none() && ctx.graph.outgoing(&node.id).next().is_some())
+        })
+        .collect()
+}
+
+pub fn analyze(state: &ProjectState) -> AvailabilityReport {
+    let ctx = LintContext::new(state);
+    let mut unspecified = Vec::new();
+    let model = Model::new(&ctx, &mut unspecified);
+    let mut warnings = Vec::new();
+    let mut saved_total: HashMap<&str, f64> = HashMap::new();
+
+    let mut entries = Vec::new();
+    for entry in entry_points(&ctx) {
+        let composite = model.composite(&entry.id, None, &mut warnings);
+        let mut weakest: Vec<Contribution> = model
+            .reachable(&entry.id)
+            .into_iter()
+            .filter_map(|id| {
+                let gain = model.composite(&entry.id, Some(id), &mut warnings) - composite;
+                if gain <= 0.0 {
+                    return None;
+                }
+                *saved_total.entry(id).or_default() += gain;
+                Some(Contribution {
+                    node: ctx.graph.node(id).cloned()?,
+                    own: model.own[id],
+                    downtime_saved_per_year: Measure::duration(gain * SECONDS_PER_YEAR),
+                })
+            })
+            .collect();
+        weakest.sort_by(|a, b| b.downtime_saved_per_year.value.total_cmp(&a.downtime_saved_per_year.value));
+        weakest.truncate(WEAKEST_PER_ENTRY);
+        entries.push(EntryAvailability {
+            composite: NodeAvailability::new(entry, composite),
+            weakest,
+        });
+    }
+    entries.sort_by(|a, b| a.composite.availability.total_cmp(&b.composite.availability));
+
+    let mut bottlenecks: Vec<Contribution> = saved_total
+        .into_iter()
+        .filter_map(|(id, gain)| {
+            Some(Contribution {
+                node: ctx.graph.node(id).cloned()?,
+                own: model.own[id],
+                downtime_saved_per_year: Measure::duration(gain * SECONDS_PER_YEAR),
+            })
+        })
+        .collect();
+    bottlenecks.sort_by(|a, b| {
+        b.downtime_saved_per_year
+            .value
+            .total_cmp(&a.downtime_saved_per_year.value)
+            .then_with(|| a.node.label.cmp(&b.node.label))
+    });
+
+    AvailabilityReport {
+        project_id: state.project.id.clone(),
+        entry_points: entries,
+        bottlenecks,
+        unspecified,
+        warnings,
+    }
+}
+
+#[tauri::command]
+pub async fn analyze_availability(
+    project_id: String,
+    projects: State<'_, ProjectStore>,
+) -> Result<AvailabilityReport, ApiError> {
+    let report = projects
+        .read(&project_id, analyze)?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })?;
+    log::debug!(
+        "Availability for {}: {} entry points, lowest {}",
+        project_id,
+        report.entry_points.len(),
+        report.entry_points.first().map_or("n/a", |e| e.composite.display.as_str())
+    );
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn set_component_availability(
+    project_id: String,
+    component_id: String,
+    inputs: Option<AvailabilityInputs>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<Component, ApiError> {
+    if let Some(inputs) = &inputs {
+        inputs.validate()?;
+    }
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let project = &mut state.project;
+    let component = project
+        .components
+        .iter_mut()
+        .find(|c| c.id == component_id)
+        .ok_or_else(|| ApiError::ComponentNotFound {
+            component_id: component_id.clone(),
+            project_id: project_id.clone(),
+            source: None,
+        })?;
+    check_revision("component", &component.id, expected_revision, component.revision, &*component)?;
+
+    component.availability = inputs;
+    component.revision += 1;
+    let updated = component.clone();
+    project.revision += 1;
+    project.updated_at = Utc::now();
+    changes.publish(&app, Change::ComponentChanged {
+        project_id: project_id.clone(),
+        change: ComponentChange::Updated,
+        component_id: updated.id.clone(),
+        component: Some(updated.clone()),
+    })?;
+    log::info!("Availability target updated for component {} in project {}", component_id, project_id);
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, component_with, state_with};
+    use crate::{ComponentType, Connection};
+
+    fn targeted(id: &str, kind: ComponentType, deps: &[&str], target: f64, replicas: u32) -> Component {
+        Component {
+            availability: Some(AvailabilityInputs {
+                target: Availability(target),
+                replicas: Some(replicas),
+            }),
+            ..component_with(id, kind, deps, &[])
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn parses_fractions_and_percentages() {
+        assert!(close(Availability::parse("99.9%").unwrap().0, 0.999));
+        assert_eq!(serde_json::from_str::<Availability>("0.995").unwrap().0, 0.995);
+        assert!(serde_json::from_str::<Availability>("99.9").is_err());
+        assert!(Availability::parse("101%").is_err());
+        assert_eq!(format_percent(0.99949), "99.949%");
+        assert_eq!(format_percent(1.0), "100%");
+    }
+
+    #[test]
+    fn serial_dependencies_multiply_and_replicas_combine() {
+        let report = analyze(&state_with(vec![
+            targeted("web", ComponentType::Frontend, &["api"], 0.999, 1),
+            targeted("api", ComponentType::Backend, &["db"], 0.99, 2),
+            targeted("db", ComponentType::Database, &[], 0.995, 1),
+        ]));
+        assert_eq!(report.entry_points.len(), 1);
+        let web = &report.entry_points[0];
+        // api runs two replicas: 1 - 0.01^2
+        assert!(close(web.composite.availability, 0.999 * 0.9999 * 0.995));
+        assert_eq!(web.weakest[0].node.id, "db");
+        assert_eq!(report.bottlenecks[0].node.id, "db");
+        assert_eq!(report.bottlenecks.last().unwrap().node.id, "api");
+        assert!(report.unspecified.is_empty());
+    }
+
+    #[test]
+    fn redundancy_groups_combine_in_parallel() {
+        let mut project = state_with(vec![
+            targeted("web", ComponentType::Frontend, &[], 0.999, 1),
+            targeted("east", ComponentType::Database, &[], 0.99, 1),
+            targeted("west", ComponentType::Database, &[], 0.99, 1),
+            targeted("cache", ComponentType::Service, &[], 0.9, 1),
+        ]);
+        let connection = |id: &str, to: &str, group: Option<&str>| Connection {
+            properties: group
+                .map(|g| HashMap::from([("redundancy_group".to_string(), g.to_string())]))
+                .unwrap_or_default(),
+            ..test_support::connection(id, "web", to)
+        };
+        project.connections = vec![
+            connection("c1", "east", Some("db")),
+            connection("c2", "west", Some("db")),
+            connection("c3", "cache", None),
+        ];
+        let report = analyze(&project);
+        let expected = 0.999 * (1.0 - 0.01 * 0.01) * 0.9;
+        assert!(close(report.entry_points[0].composite.availability, expected));
+        assert_eq!(report.bottlenecks[0].node.id, "cache");
+    }
+}
@@ -0,0 +1,805 @@
+// Single-file .archicomm project bundles
+//
+// A bundle is a zip archive holding a checksummed manifest next to the project,
+// its diagram, connections, transcripts and any referenced audio recordings.
+
+use crate::component_links;
+use crate::containment;
+use crate::dependencies;
+use crate::diagram_patch;
+use crate::diagrams::{self, DiagramSet};
+use crate::events::{Change, ChangeFeed};
+use crate::state::{read_entry, write_entry, ProjectState, ProjectStore};
+use crate::{
+    audio_session_path, save_audio_file, validate_filename, ApiError, Connection, DiagramElement, OperationNames, Project,
+    ProjectTranscript,
+};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+use tempfile::NamedTempFile;
+use zip::write::FileOptions;
+use uuid::Uuid;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub const BUNDLE_EXTENSION: &str = "archicomm";
+pub const BUNDLE_FORMAT: &str = "archicomm-bundle";
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const PROJECT_PATH: &str = "project.json";
+const DIAGRAM_PATH: &str = "diagram.json";
+const CONNECTIONS_PATH: &str = "connections.json";
+const TRANSCRIPTS_PATH: &str = "transcripts.json";
+const VIEWS_PATH: &str = "views.json";
+const AUDIO_DIR: &str = "audio/";
+
+// Upper bounds that keep a hostile archive from exhausting memory
+const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+const MAX_BUNDLE_SIZE: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: String,
+    pub format_version: u32,
+    pub app_version: String,
+    pub project_id: String,
+    pub created_at: DateTime<Utc>,
+    pub entries: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BundleAudio {
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+/// Everything stored in a bundle. Transcript audio paths are bundle-relative (`audio/<file>`).
+#[derive(Debug, Clone)]
+pub struct BundleContents {
+    pub project: Project,
+    pub diagram_elements: Vec<DiagramElement>,
+    pub connections: Vec<Connection>,
+    /// Main diagram metadata and the additional diagrams; optional in older bundles
+    pub diagrams: DiagramSet,
+    pub transcripts: Vec<ProjectTranscript>,
+    pub audio: Vec<BundleAudio>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenedBundle {
+    pub manifest: BundleManifest,
+    pub project: Project,
+    pub diagram_elements: Vec<DiagramElement>,
+    pub connections: Vec<Connection>,
+    pub diagrams: DiagramSet,
+    pub transcripts: Vec<ProjectTranscript>,
+}
+
+fn invalid_bundle(details: impl Into<String>) -> ApiError {
+    ApiError::InvalidBundle {
+        details: details.into(),
+        source: None,
+    }
+}
+
+fn zip_error(context: &str, err: zip::result::ZipError) -> ApiError {
+    ApiError::InvalidBundle {
+        details: format!("{}: {}", context, err),
+        source: Some(Box::new(err)),
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Only the fixed top-level documents and flat audio files are allowed inside a bundle
+fn is_allowed_entry_path(path: &str) -> bool {
+    match path {
+        PROJECT_PATH | DIAGRAM_PATH | CONNECTIONS_PATH | TRANSCRIPTS_PATH | VIEWS_PATH => true,
+        _ => path
+            .strip_prefix(AUDIO_DIR)
+            .map(|name| validate_filename(name).is_ok())
+            .unwrap_or(false),
+    }
+}
+
+/// Appends the `.archicomm` extension unless the path already carries it
+pub fn with_bundle_extension(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case(BUNDLE_EXTENSION) => path.to_path_buf(),
+        _ => {
+            let mut raw = path.as_os_str().to_os_string();
+            raw.push(".");
+            raw.push(BUNDLE_EXTENSION);
+            PathBuf::from(raw)
+        }
+    }
+}
+
+/// Writes a bundle archive and returns the manifest that was embedded in it
+pub fn write_bundle<W: Write + Seek>(writer: W, contents: &BundleContents) -> Result<BundleManifest, ApiError> {
+    let mut transcripts = contents.transcripts.clone();
+    let audio_names: HashSet<&str> = contents.audio.iter().map(|a| a.file_name.as_str()).collect();
+    for transcript in transcripts.iter_mut() {
+        if let Some(audio_path) = transcript.audio_path.as_deref() {
+            let referenced = audio_path.strip_prefix(AUDIO_DIR).unwrap_or(audio_path);
+            if !audio_names.contains(referenced) {
+                return Err(invalid_bundle(format!(
+                    "Transcript {} references audio '{}' that is not part of the bundle",
+                    transcript.id, audio_path
+                )));
+            }
+            transcript.audio_path = Some(format!("{}{}", AUDIO_DIR, referenced));
+        }
+    }
+
+    let mut files: Vec<(String, Vec<u8>)> = vec![
+        (PROJECT_PATH.to_string(), serde_json::to_vec_pretty(&contents.project)?),
+        (DIAGRAM_PATH.to_string(), serde_json::to_vec_pretty(&contents.diagram_elements)?),
+        (CONNECTIONS_PATH.to_string(), serde_json::to_vec_pretty(&contents.connections)?),
+        (VIEWS_PATH.to_string(), serde_json::to_vec_pretty(&contents.diagrams)?),
+        (TRANSCRIPTS_PATH.to_string(), serde_json::to_vec_pretty(&transcripts)?),
+    ];
+    for audio in &contents.audio {
+        validate_filename(&audio.file_name)?;
+        files.push((format!("{}{}", AUDIO_DIR, audio.file_name), audio.data.clone()));
+    }
+
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT.to_string(),
+        format_version: BUNDLE_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        project_id: contents.project.id.clone(),
+        created_at: Utc::now(),
+        entries: files
+            .iter()
+            .map(|(path, data)| BundleEntry {
+                path: path.clone(),
+                size: data.len() as u64,
+                sha256: sha256_hex(data),
+            })
+            .collect(),
+    };
+
+    let mut zip = ZipWriter::new(writer);
+    let json_options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // Audio is already compressed, deflating it again only costs time
+    let audio_options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file(MANIFEST_PATH, json_options)
+        .map_err(|e| zip_error("Failed to start manifest entry", e))?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+
+    for (path, data) in &files {
+        let options = if path.starts_with(AUDIO_DIR) { audio_options } else { json_options };
+        zip.start_file(path.as_str(), options)
+            .map_err(|e| zip_error("Failed to start bundle entry", e))?;
+        zip.write_all(data)?;
+    }
+
+    zip.finish().map_err(|e| zip_error("Failed to finalize bundle", e))?;
+    Ok(manifest)
+}
+
+fn read_archive_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<Vec<u8>, ApiError> {
+    let entry = archive
+        .by_name(path)
+        .map_err(|e| zip_error(&format!("Missing bundle entry '{}'", path), e))?;
+    if entry.size() > MAX_ENTRY_SIZE {
+        return Err(invalid_bundle(format!(
+            "Bundle entry '{}' is too large: {} bytes (max {})",
+            path,
+            entry.size(),
+            MAX_ENTRY_SIZE
+        )));
+    }
+
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn parse_entry<T: DeserializeOwned>(path: &str, data: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(data).map_err(|e| ApiError::InvalidBundle {
+        details: format!("Malformed bundle entry '{}': {}", path, e),
+        source: Some(Box::new(e)),
+    })
+}
+
+/// Reads a bundle archive, verifying the manifest checksums before anything is parsed
+pub fn read_bundle<R: Read + Seek>(reader: R) -> Result<(BundleManifest, BundleContents), ApiError> {
+    let mut archive = ZipArchive::new(reader).map_err(|e| zip_error("Not a valid bundle archive", e))?;
+
+    let mut total_size: u64 = 0;
+    let mut archive_paths = HashSet::new();
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .map_err(|e| zip_error("Failed to read bundle entry", e))?;
+        total_size = total_size.saturating_add(entry.size());
+        if !entry.is_dir() {
+            archive_paths.insert(entry.name().to_string());
+        }
+    }
+    if total_size > MAX_BUNDLE_SIZE {
+        return Err(invalid_bundle(format!(
+            "Bundle is too large when extracted: {} bytes (max {})",
+            total_size, MAX_BUNDLE_SIZE
+        )));
+    }
+
+    let manifest: BundleManifest = parse_entry(MANIFEST_PATH, &read_archive_entry(&mut archive, MANIFEST_PATH)?)?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(invalid_bundle(format!("Unknown bundle format '{}'", manifest.format)));
+    }
+    if manifest.format_version == 0 || manifest.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(invalid_bundle(format!(
+            "Unsupported bundle format version {} (this build reads up to {})",
+            manifest.format_version, BUNDLE_FORMAT_VERSION
+        )));
+    }
+
+    let mut listed_paths = HashSet::new();
+    for entry in &manifest.entries {
+        if !is_allowed_entry_path(&entry.path) {
+            return Err(invalid_bundle(format!("Manifest lists an invalid entry path '{}'", entry.path)));
+        }
+        if !listed_paths.insert(entry.path.as_str()) {
+            return Err(invalid_bundle(format!("Manifest lists '{}' more than once", entry.path)));
+        }
+    }
+    if let Some(unlisted) = archive_paths
+        .iter()
+        .find(|path| path.as_str() != MANIFEST_PATH && !listed_paths.contains(path.as_str()))
+    {
+        return Err(invalid_bundle(format!("Bundle contains entry '{}' that is not in the manifest", unlisted)));
+    }
+    for required in [PROJECT_PATH, DIAGRAM_PATH, CONNECTIONS_PATH, TRANSCRIPTS_PATH] {
+        if !listed_paths.contains(required) {
+            return Err(invalid_bundle(format!("Manifest is missing required entry '{}'", required)));
+        }
+    }
+
+    let mut verified = Vec::with_capacity(manifest.entries.len());
+    for entry in &manifest.entries {
+        let data = read_archive_entry(&mut archive, &entry.path)?;
+        if data.len() as u64 != entry.size {
+            return Err(invalid_bundle(format!(
+                "Size mismatch for '{}': manifest says {} bytes, found {}",
+                entry.path,
+                entry.size,
+                data.len()
+            )));
+        }
+        if sha256_hex(&data) != entry.sha256.to_ascii_lowercase() {
+            return Err(invalid_bundle(format!("Checksum mismatch for '{}'", entry.path)));
+        }
+        verified.push((entry.path.clone(), data));
+    }
+
+    let document = |path: &str| -> &[u8] {
+        verified
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, data)| data.as_slice())
+            .unwrap_or_default()
+    };
+    let project: Project = parse_entry(PROJECT_PATH, document(PROJECT_PATH))?;
+    let diagram_elements: Vec<DiagramElement> = parse_entry(DIAGRAM_PATH, document(DIAGRAM_PATH))?;
+    let connections: Vec<Connection> = parse_entry(CONNECTIONS_PATH, document(CONNECTIONS_PATH))?;
+    let transcripts: Vec<ProjectTranscript> = parse_entry(TRANSCRIPTS_PATH, document(TRANSCRIPTS_PATH))?;
+    let diagrams: DiagramSet = if listed_paths.contains(VIEWS_PATH) {
+        parse_entry(VIEWS_PATH, document(VIEWS_PATH))?
+    } else {
+        DiagramSet::default()
+    };
+
+    check_diagram(&diagram_elements, &connections)?;
+    for view in &diagrams.views {
+        check_diagram(&view.elements, &view.connections)?;
+    }
+
+    if project.id != manifest.project_id {
+        return Err(invalid_bundle(format!(
+            "Manifest project id '{}' does not match bundled project '{}'",
+            manifest.project_id, project.id
+        )));
+    }
+
+    let audio: Vec<BundleAudio> = verified
+        .iter()
+        .filter_map(|(path, data)| {
+            path.strip_prefix(AUDIO_DIR).map(|name| BundleAudio {
+                file_name: name.to_string(),
+                data: data.clone(),
+            })
+        })
+        .collect();
+    for transcript in &transcripts {
+        if let Some(audio_path) = transcript.audio_path.as_deref() {
+            if !listed_paths.contains(audio_path) || !audio_path.starts_with(AUDIO_DIR) {
+                return Err(invalid_bundle(format!(
+                    "Transcript {} references missing audio '{}'",
+                    transcript.id, audio_path
+                )));
+            }
+        }
+    }
+
+    Ok((
+        manifest,
+        BundleContents {
+            project,
+            diagram_elements,
+            connections,
+            diagrams,
+            transcripts,
+            audio,
+        },
+    ))
+}
+
+// Only what the app itself never saves. Links to a removed component and connections left
+// pointing at it are normal saved states; reconciling and the linter report those
+fn check_diagram(elements: &[DiagramElement], connections: &[Connection]) -> Result<(), ApiError> {
+    let invalid = |details| invalid_bundle(format!("Invalid diagram: {}", details));
+    diagram_patch::check_elements(elements).map_err(invalid)?;
+    diagram_patch::check_connection_ids(connections).map_err(invalid)
+}
+
+/// Atomically writes a bundle next to its final location
+pub fn save_bundle_to_path(path: &Path, contents: &BundleContents) -> Result<BundleManifest, ApiError> {
+    let parent = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut temp_file = NamedTempFile::new_in(&parent).map_err(|e| ApiError::FileSystemError {
+        operation: OperationNames::FILE_WRITE.to_string(),
+        details: format!("Failed to create temporary bundle file: {}", e),
+        source: Some(Box::new(e)),
+    })?;
+    let manifest = write_bundle(temp_file.as_file_mut(), contents)?;
+    temp_file.as_file_mut().flush()?;
+
+    temp_file.persist(path).map_err(|e| ApiError::FileSystemError {
+        operation: OperationNames::FILE_PERSIST.to_string(),
+        details: format!("Failed to persist bundle to '{}': {}", path.display(), e),
+        source: Some(Box::new(e)),
+    })?;
+    Ok(manifest)
+}
+
+pub fn open_bundle_from_path(path: &Path) -> Result<(BundleManifest, BundleContents), ApiError> {
+    let file = fs::File::open(path).map_err(|e| ApiError::FileSystemError {
+        operation: OperationNames::BUNDLE_IO.to_string(),
+        details: format!("Failed to open bundle '{}': {}", path.display(), e),
+        source: Some(Box::new(e)),
+    })?;
+    read_bundle(file)
+}
+
+// Pulls referenced recordings off disk. Recordings that no longer exist, or that live outside
+// the audio session directory, are dropped with a warning
+fn collect_transcript_audio(transcripts: &mut [ProjectTranscript]) -> Result<Vec<BundleAudio>, ApiError> {
+    let mut audio: Vec<BundleAudio> = Vec::new();
+    for transcript in transcripts.iter_mut() {
+        let Some(source) = transcript.audio_path.clone() else { continue };
+        let source_path = Path::new(&source);
+        let data = match audio_session_path(&source).and_then(|path| Ok(fs::read(path)?)) {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("Skipping audio for transcript {} ({}): {}", transcript.id, source, e);
+                transcript.audio_path = None;
+                continue;
+            }
+        };
+
+        let base_name = source_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("{}.audio", transcript.id));
+        let mut file_name = base_name.clone();
+        let mut suffix = 1;
+        while audio.iter().any(|a| a.file_name == file_name) {
+            file_name = format!("{}-{}", suffix, base_name);
+            suffix += 1;
+        }
+
+        transcript.audio_path = Some(file_name.clone());
+        audio.push(BundleAudio { file_name, data });
+    }
+    Ok(audio)
+}
+
+#[tauri::command]
+pub async fn save_project_bundle(
+    project_id: String,
+    path: String,
+    projects: State<'_, ProjectStore>,
+) -> Result<String, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut contents = {
+        let state = read_entry(&entry)?;
+        BundleContents {
+            project: state.project.clone(),
+            diagram_elements: containment::parents_first(&state.diagram).into_iter().cloned().collect(),
+            connections: state.connections.clone(),
+            diagrams: diagrams::diagram_set(&state),
+            transcripts: state.transcripts.clone(),
+            audio: Vec::new(),
+        }
+    };
+    contents.audio = collect_transcript_audio(&mut contents.transcripts)?;
+
+    let target = with_bundle_extension(Path::new(&path));
+    let manifest = save_bundle_to_path(&target, &contents)?;
+
+    log::info!(
+        "Project bundle saved: {} ({} entries) -> {}",
+        project_id,
+        manifest.entries.len(),
+        target.display()
+    );
+    Ok(target.to_string_lossy().to_string())
+}
+
+// Recordings copied out of a bundle that didn't open after all; nothing refers to them
+fn discard_audio(paths: &[String]) {
+    for path in paths {
+        if let Err(e) = fs::remove_file(path) {
+            log::warn!("Failed to remove unused audio {}: {}", path, e);
+        }
+    }
+}
+
+fn already_open(project_id: &str) -> ApiError {
+    ApiError::InvalidProjectData {
+        details: format!(
+            "Project {} is already open; open the bundle with overwrite to replace it",
+            project_id
+        ),
+        source: None,
+    }
+}
+
+// Moves every revision of the replacement past the copy it replaces, so a client still
+// holding a revision of the old copy gets a conflict instead of a chance match
+fn supersede(current: &ProjectState, replacement: &mut ProjectState) {
+    replacement.project.revision = replacement.project.revision.max(current.project.revision) + 1;
+    replacement.diagram_revision = replacement.diagram_revision.max(current.diagram_revision) + 1;
+    for view in &mut replacement.views {
+        let old = current.views.iter().find(|v| v.id == view.id).map_or(0, |v| v.diagram_revision);
+        view.diagram_revision = view.diagram_revision.max(old) + 1;
+    }
+    for component in &mut replacement.project.components {
+        let old = current.project.components.iter().find(|c| c.id == component.id).map_or(0, |c| c.revision);
+        component.revision = component.revision.max(old) + 1;
+    }
+}
+
+/// Opening a bundle of a project that is already loaded replaces it only with `overwrite`
+#[tauri::command]
+pub async fn open_project_bundle(
+    path: String,
+    overwrite: Option<bool>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<OpenedBundle, ApiError> {
+    let (manifest, mut contents) = open_bundle_from_path(Path::new(&path))?;
+    let project_id = contents.project.id.clone();
+    // Checked again when the project is stored; this only saves copying audio for nothing
+    if projects.get(&project_id)?.is_some() && !overwrite.unwrap_or(false) {
+        return Err(already_open(&project_id));
+    }
+
+    // Recordings go to the audio session directory so they behave like freshly saved files,
+    // under fresh names so they never replace a recording another transcript uses
+    let mut written = Vec::new();
+    for transcript in contents.transcripts.iter_mut() {
+        let Some(bundled) = transcript.audio_path.take() else { continue };
+        let file_name = bundled.strip_prefix(AUDIO_DIR).unwrap_or(&bundled);
+        let Some(audio) = contents.audio.iter().find(|a| a.file_name == file_name) else {
+            log::warn!("Bundle has no audio {} for transcript {}", file_name, transcript.id);
+            continue;
+        };
+        let unique_name = format!("{}-{}", Uuid::new_v4(), file_name);
+        match save_audio_file(unique_name, audio.data.clone(), None).await {
+            Ok(saved) => {
+                written.push(saved.clone());
+                transcript.audio_path = Some(saved);
+            }
+            Err(e) => {
+                discard_audio(&written);
+                return Err(e);
+            }
+        }
+    }
+
+    // Bundles written before dependencies were id-based reference components by name
+    let migration = dependencies::migrate_dependencies(&mut contents.project);
+    if !migration.is_empty() {
+        log::info!(
+            "Migrated dependencies in bundle {}: {} rewritten, {} dropped",
+            path,
+            migration.rewritten,
+            migration.dropped.len()
+        );
+    }
+
+    let mut state = ProjectState::new(contents.project.clone());
+    state.diagram = contents.diagram_elements.clone();
+    state.connections = contents.connections.clone();
+    state.transcripts = contents.transcripts.clone();
+    state.main_diagram = contents.diagrams.main.clone();
+    state.views = contents.diagrams.views.clone();
+    let issues = component_links::find_issues(&state)
+        .iter()
+        .filter(|issue| matches!(issue, component_links::LinkIssue::MissingComponent { .. }))
+        .count();
+    if issues > 0 {
+        log::warn!("Bundle {} links {} elements to missing components; reconcile to fix them", path, issues);
+    }
+    let mut opened = Some(state);
+    let (entry, added) = projects.get_or_insert_with(&project_id, || opened.take().expect("only made once"))?;
+    let project = match opened {
+        // Loaded in the meantime, or replaced on purpose: write into the entry other commands
+        // already hold so their writes aren't lost with a swapped out one
+        Some(mut state) => {
+            let current = if overwrite.unwrap_or(false) {
+                write_entry(&entry)
+            } else {
+                Err(already_open(&project_id))
+            };
+            let mut current = match current {
+                Ok(current) => current,
+                Err(e) => {
+                    discard_audio(&written);
+                    return Err(e);
+                }
+            };
+            supersede(&current, &mut state);
+            *current = state;
+            current.project.clone()
+        }
+        None => contents.project.clone(),
+    };
+    let change = if added {
+        Change::ProjectCreated { project: project.clone() }
+    } else {
+        Change::ProjectUpdated { project: project.clone() }
+    };
+    changes.publish(&app, change)?;
+
+    log::info!("Project bundle opened: {} from {}", project_id, path);
+    Ok(OpenedBundle {
+        manifest,
+        project,
+        diagram_elements: contents.diagram_elements,
+        connections: contents.connections,
+        diagrams: contents.diagrams,
+        transcripts: contents.transcripts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component_links::LinkIssue;
+    use crate::diagrams::{Diagram, DiagramKind};
+    use crate::test_support;
+    use crate::{Component, ComponentStatus, Position, ProjectStatus, TranscriptionSegment};
+    use std::io::Cursor;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("bundles")
+            .join(name)
+    }
+
+    fn expect_invalid(result: Result<(BundleManifest, BundleContents), ApiError>, needle: &str) {
+        match result {
+            Err(ApiError::InvalidBundle { details, .. }) => {
+                assert!(details.contains(needle), "unexpected details: {}", details)
+            }
+            Err(other) => panic!("Expected InvalidBundle, got {:?}", other),
+            Ok(_) => panic!("Expected InvalidBundle containing '{}'", needle),
+        }
+    }
+
+    fn sample_contents() -> BundleContents {
+        let project = Project {
+            id: "proj-1".into(),
+            name: "Outbox".into(),
+            description: "Kafka outbox pattern".into(),
+            status: ProjectStatus::Review,
+            components: vec![Component {
+                name: "Orders".into(),
+                description: "Writes orders".into(),
+                status: ComponentStatus::Done,
+                ..test_support::component("comp-1")
+            }],
+            tags: vec!["events".into()],
+            ..test_support::project()
+        };
+        BundleContents {
+            project,
+            diagram_elements: vec![DiagramElement {
+                position: Position { x: 10.0, y: 20.0 },
+                ..test_support::element("el-1")
+            }],
+            connections: vec![],
+            diagrams: DiagramSet::default(),
+            transcripts: vec![ProjectTranscript {
+                id: "tr-1".into(),
+                text: "orders publish to kafka".into(),
+                segments: vec![TranscriptionSegment {
+                    text: "orders publish to kafka".into(),
+                    start: 0.0,
+                    end: 1.5,
+                    confidence: None,
+                }],
+                audio_path: Some("note.wav".into()),
+                created_at: Utc::now(),
+            }],
+            audio: vec![BundleAudio {
+                file_name: "note.wav".into(),
+                data: b"RIFF fake wav".to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn opens_v1_fixture() {
+        let (manifest, contents) = open_bundle_from_path(&fixture("valid-v1.archicomm")).unwrap();
+        assert_eq!(manifest.format_version, 1);
+        assert_eq!(contents.project.name, "Payments Platform");
+        assert_eq!(contents.project.components.len(), 2);
+        assert_eq!(contents.diagram_elements.len(), 2);
+        assert_eq!(contents.connections.len(), 1);
+        assert_eq!(contents.transcripts[0].audio_path.as_deref(), Some("audio/standup.wav"));
+        assert_eq!(contents.audio[0].file_name, "standup.wav");
+        assert_eq!(contents.audio[0].data, b"RIFF-fixture-audio");
+    }
+
+    #[test]
+    fn rejects_tampered_fixtures() {
+        expect_invalid(open_bundle_from_path(&fixture("tampered-checksum.archicomm")), "Checksum mismatch");
+        expect_invalid(open_bundle_from_path(&fixture("unlisted-entry.archicomm")), "not in the manifest");
+        expect_invalid(open_bundle_from_path(&fixture("path-traversal.archicomm")), "invalid entry path");
+        expect_invalid(open_bundle_from_path(&fixture("future-version.archicomm")), "Unsupported bundle format version");
+        expect_invalid(open_bundle_from_path(&fixture("not-a-zip.archicomm")), "Not a valid bundle archive");
+    }
+
+    #[test]
+    fn opens_fixtures_with_stale_links() {
+        let (_, contents) = open_bundle_from_path(&fixture("dangling-connection.archicomm")).unwrap();
+        assert_eq!(contents.connections[0].target_id, "e-gone");
+
+        let (_, contents) = open_bundle_from_path(&fixture("unknown-component.archicomm")).unwrap();
+        let mut state = ProjectState::new(contents.project);
+        state.views = contents.diagrams.views;
+        let stale = component_links::find_issues(&state).into_iter().any(|issue| match issue {
+            LinkIssue::MissingComponent { element_id, component_id, .. } => {
+                element_id == "v-api" && component_id == "c-ghost"
+            }
+            _ => false,
+        });
+        assert!(stale);
+    }
+
+    #[test]
+    fn rejects_connections_the_app_never_saves() {
+        let mut contents = sample_contents();
+        contents.connections = vec![test_support::connection("loop", "el-1", "el-1")];
+        let mut buffer = Cursor::new(Vec::new());
+        write_bundle(&mut buffer, &contents).unwrap();
+        buffer.set_position(0);
+        expect_invalid(read_bundle(buffer), "connection loop points back at its own source el-1");
+    }
+
+    #[test]
+    fn round_trips_through_archive() {
+        let mut contents = sample_contents();
+        contents.diagrams.views.push(Diagram {
+            id: "view-1".into(),
+            name: "Containers".into(),
+            kind: DiagramKind::Container,
+            elements: contents.diagram_elements.clone(),
+            connections: vec![],
+            diagram_revision: 0,
+        });
+        let mut buffer = Cursor::new(Vec::new());
+        let written = write_bundle(&mut buffer, &contents).unwrap();
+        assert_eq!(written.entries.len(), 6);
+
+        buffer.set_position(0);
+        let (manifest, restored) = read_bundle(buffer).unwrap();
+        assert_eq!(manifest.entries, written.entries);
+        assert_eq!(restored.project.id, "proj-1");
+        assert_eq!(restored.project.components[0].name, "Orders");
+        assert_eq!(restored.diagram_elements[0].position.x, 10.0);
+        assert_eq!(restored.diagrams.views[0].kind, DiagramKind::Container);
+        assert_eq!(restored.transcripts[0].audio_path.as_deref(), Some("audio/note.wav"));
+        assert_eq!(restored.audio[0].data, b"RIFF fake wav");
+    }
+
+    #[test]
+    fn refuses_transcript_without_bundled_audio() {
+        let mut contents = sample_contents();
+        contents.audio.clear();
+        let result = write_bundle(Cursor::new(Vec::new()), &contents);
+        assert!(matches!(result, Err(ApiError::InvalidBundle { .. })));
+    }
+
+    #[test]
+    fn replacements_move_past_every_revision() {
+        let mut current = ProjectState::new(Project {
+            revision: 7,
+            components: vec![Component { revision: 4, ..test_support::component("comp-1") }],
+            ..test_support::project()
+        });
+        current.diagram_revision = 12;
+        let contents = sample_contents();
+        let mut replacement = ProjectState::new(contents.project);
+        supersede(&current, &mut replacement);
+        assert_eq!(replacement.project.revision, 8);
+        assert_eq!(replacement.diagram_revision, 13);
+        assert_eq!(replacement.project.components[0].revision, 5);
+    }
+
+    #[test]
+    fn saves_atomically_with_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = with_bundle_extension(&dir.path().join("design"));
+        assert_eq!(target.extension().unwrap(), BUNDLE_EXTENSION);
+        assert_eq!(with_bundle_extension(&target), target);
+
+        save_bundle_to_path(&target, &sample_contents()).unwrap();
+        let (_, restored) = open_bundle_from_path(&target).unwrap();
+        assert_eq!(restored.project.name, "Outbox");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn only_packs_recordings_from_the_audio_session_dir() {
+        let mut secret = tempfile::NamedTempFile::new().unwrap();
+        secret.write_all(b"private key").unwrap();
+        let recording = crate::get_audio_session_dir().unwrap().join(format!("{}.wav", Uuid::new_v4()));
+        fs::write(&recording, b"RIFF session audio").unwrap();
+        let transcript = |id: &str, path: &Path| ProjectTranscript {
+            id: id.into(),
+            text: String::new(),
+            segments: vec![],
+            audio_path: Some(path.to_string_lossy().to_string()),
+            created_at: Utc::now(),
+        };
+        let mut transcripts = vec![transcript("ok", &recording), transcript("leak", secret.path())];
+
+        let audio = collect_transcript_audio(&mut transcripts).unwrap();
+        assert_eq!(audio.len(), 1);
+        assert_eq!(audio[0].data, b"RIFF session audio");
+        assert!(transcripts[0].audio_path.is_some());
+        assert_eq!(transcripts[1].audio_path, None);
+        assert!(audio_session_path(&secret.path().to_string_lossy()).is_err());
+        fs::remove_file(recording).unwrap();
+    }
+}
@@ -0,0 +1,400 @@
+// Back-of-envelope capacity estimation
+//
+// Components carry typed `CapacityInputs`. A component with users generates traffic of its
+// own; every edge then forwards the caller's traffic to whatever it relies on, multiplied by
+// the connection's `calls_per_request` property (1 for plain dependencies). Traffic is pushed
+// through the graph in topological order; edges inside a dependency cycle are not followed.
+
+use crate::events::{Change, ChangeFeed, ComponentChange};
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::system_graph::{self, EdgeVia, SystemGraph};
+use crate::units::{DataSize, Measure, TimeSpan, SECONDS_PER_DAY};
+use crate::{check_revision, ApiError, Component};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use tauri::{AppHandle, State};
+
+// Average-to-peak multiplier when a component doesn't set its own
+const DEFAULT_PEAK_FACTOR: f64 = 2.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CapacityInputs {
+    pub daily_active_users: Option<f64>,
+    /// Requests each active user makes per day
+    pub requests_per_user: Option<f64>,
+    /// Size of one request or stored record, e.g. "2 KB"
+    pub payload_size: Option<DataSize>,
+    /// Reads per write, e.g. 10 for a 10:1 read-heavy workload. Without it, each request a
+    /// component originates counts as a write and inherited traffic keeps its caller's mix.
+    pub read_write_ratio: Option<f64>,
+    /// How long written data is kept, e.g. "5 y"
+    pub retention: Option<TimeSpan>,
+    /// Peak-to-average multiplier for this component's own traffic
+    pub peak_factor: Option<f64>,
+}
+
+impl CapacityInputs {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let numbers = [
+            ("daily_active_users", self.daily_active_users),
+            ("requests_per_user", self.requests_per_user),
+            ("read_write_ratio", self.read_write_ratio),
+            ("peak_factor", self.peak_factor),
+        ];
+        for (field, value) in numbers {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                return Err(ApiError::InvalidComponentData {
+                    details: format!("Capacity input {} must be a non-negative number", field),
+                    source: None,
+                });
+            }
+        }
+        if self.peak_factor.is_some_and(|p| p < 1.0) {
+            return Err(ApiError::InvalidComponentData {
+                details: "Capacity input peak_factor must be at least 1".to_string(),
+                source: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn own_qps(&self) -> f64 {
+        self.daily_active_users.unwrap_or(0.0) * self.requests_per_user.unwrap_or(0.0) / SECONDS_PER_DAY
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentEstimate {
+    pub id: String,
+    pub label: String,
+    /// Requests per second
+    pub qps: Measure,
+    pub peak_qps: Measure,
+    pub read_qps: Measure,
+    pub write_qps: Measure,
+    /// Bytes per second
+    pub bandwidth: Measure,
+    pub peak_bandwidth: Measure,
+    /// Bytes written per day
+    pub storage_per_day: Measure,
+    /// Bytes held over the component's retention period, if it has one
+    pub retained_storage: Option<Measure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapacityReport {
+    pub project_id: String,
+    /// Nodes that receive traffic, busiest first
+    pub estimates: Vec<ComponentEstimate>,
+    pub warnings: Vec<String>,
+}
+
+// Traffic arriving at a node, before its own inputs refine it
+#[derive(Debug, Clone, Copy, Default)]
+struct Load {
+    qps: f64,
+    peak_qps: f64,
+    write_qps: f64,
+    bytes_per_second: f64,
+}
+
+impl Load {
+    fn add_scaled(&mut self, other: &Load, factor: f64) {
+        self.qps += other.qps * factor;
+        self.peak_qps += other.peak_qps * factor;
+        self.write_qps += other.write_qps * factor;
+        self.bytes_per_second += other.bytes_per_second * factor;
+    }
+}
+
+fn calls_per_request(state: &ProjectState, connection_id: &str) -> f64 {
+    system_graph::connection(state, connection_id)
+        .and_then(|c| c.properties.get("calls_per_request"))
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .unwrap_or(1.0)
+}
+
+pub fn estimate(state: &ProjectState) -> CapacityReport {
+    let graph = SystemGraph::from_state(state);
+    let inputs: HashMap<&str, &CapacityInputs> = state
+        .project
+        .components
+        .iter()
+        .filter_map(|c| c.capacity.as_ref().map(|i| (c.id.as_str(), i)))
+        .collect();
+    let mut warnings = Vec::new();
+
+    // One multiplier per caller/callee pair; a diagram connection overrides the plain dependency
+    let mut fan_out: HashMap<(&str, &str), f64> = HashMap::new();
+    for edge in graph.edges() {
+        let key = (edge.from.as_str(), edge.to.as_str());
+        match &edge.via {
+            EdgeVia::Dependency => {
+                fan_out.entry(key).or_insert(1.0);
+            }
+            EdgeVia::Connection { connection_id, .. } => {
+                fan_out.insert(key, calls_per_request(state, connection_id));
+            }
+        }
+    }
+
+    let mut pending: HashMap<&str, usize> = graph.nodes().iter().map(|n| (n.id.as_str(), 0)).collect();
+    for (_, to) in fan_out.keys() {
+        *pending.entry(to).or_default() += 1;
+    }
+    let mut ready: VecDeque<&str> = graph
+        .nodes()
+        .iter()
+        .map(|n| n.id.as_str())
+        .filter(|id| pending[id] == 0)
+        .collect();
+
+    let mut incoming: HashMap<&str, Load> = HashMap::new();
+    let mut resolved: HashMap<&str, Load> = HashMap::new();
+    let mut order = Vec::new();
+    while let Some(id) = ready.pop_front().or_else(|| {
+        // Only nodes waiting on a cycle remain: break the cycle open at one of its members
+        let stuck = graph
+            .nodes()
+            .iter()
+            .map(|n| n.id.as_str())
+            .find(|id| !resolved.contains_key(id) && graph.reaches(id, id))?;
+        warnings.push(format!(
+            "{} is part of a dependency cycle; traffic inside the cycle is not counted",
+            graph.node(stuck).map_or(stuck, |n| n.label.as_str())
+        ));
+        Some(stuck)
+    }) {
+        if resolved.contains_key(id) {
+            continue;
+        }
+
+        let mut load = incoming.get(id).copied().unwrap_or_default();
+        if let Some(inputs) = inputs.get(id) {
+            let own = inputs.own_qps();
+            if own == 0.0 && (inputs.daily_active_users.is_some() != inputs.requests_per_user.is_some()) {
+                warnings.push(format!(
+                    "{} needs both daily_active_users and requests_per_user to generate traffic",
+                    graph.node(id).map_or(id, |n| n.label.as_str())
+                ));
+            }
+            load.qps += own;
+            load.peak_qps += own * inputs.peak_factor.unwrap_or(DEFAULT_PEAK_FACTOR);
+
+            // Inherited traffic is re-shaped by the component's own workload description
+            if let Some(ratio) = inputs.read_write_ratio {
+                load.write_qps = load.qps / (1.0 + ratio);
+            } else if own > 0.0 {
+                load.write_qps += own;
+            }
+            if let Some(payload) = inputs.payload_size {
+                load.bytes_per_second = load.qps * payload.0;
+            }
+        }
+        resolved.insert(id, load);
+        order.push(id);
+
+        for edge in graph.outgoing(id) {
+            let to = edge.to.as_str();
+            if resolved.contains_key(to) {
+                continue;
+            }
+            let Some(factor) = fan_out.remove(&(id, to)) else { continue };
+            incoming.entry(to).or_default().add_scaled(&load, factor);
+            if let Some(count) = pending.get_mut(to) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(to);
+                }
+            }
+        }
+    }
+
+    let mut estimates: Vec<ComponentEstimate> = order
+        .into_iter()
+        .filter_map(|id| {
+            let load = resolved[id];
+            if load.qps <= 0.0 {
+                return None;
+            }
+            let bytes_per_request = load.bytes_per_second / load.qps;
+            let storage_per_day = load.write_qps * SECONDS_PER_DAY * bytes_per_request;
+            let retention = inputs.get(id).and_then(|i| i.retention);
+            Some(ComponentEstimate {
+                id: id.to_string(),
+                label: graph.node(id).map_or_else(|| id.to_string(), |n| n.label.clone()),
+                qps: Measure::rate(load.qps),
+                peak_qps: Measure::rate(load.peak_qps),
+                read_qps: Measure::rate(load.qps - load.write_qps),
+                write_qps: Measure::rate(load.write_qps),
+                bandwidth: Measure::throughput(load.bytes_per_second),
+                peak_bandwidth: Measure::throughput(load.peak_qps * bytes_per_request),
+                storage_per_day: Measure::size(storage_per_day),
+                retained_storage: retention.map(|r| Measure::size(storage_per_day * r.0 / SECONDS_PER_DAY)),
+            })
+        })
+        .collect();
+    estimates.sort_by(|a, b| b.qps.value.total_cmp(&a.qps.value).then_with(|| a.label.cmp(&b.label)));
+
+    CapacityReport {
+        project_id: state.project.id.clone(),
+        estimates,
+        warnings,
+    }
+}
+
+#[tauri::command]
+pub async fn estimate_capacity(
+    project_id: String,
+    projects: State<'_, ProjectStore>,
+) -> Result<CapacityReport, ApiError> {
+    let report = projects.read(&project_id, estimate)?.ok_or_else(|| ApiError::ProjectNotFound {
+        project_id: project_id.clone(),
+        source: None,
+    })?;
+    log::debug!("Capacity estimate for {} covers {} components", project_id, report.estimates.len());
+    Ok(report)
+}
+
+/// Sets or clears (`inputs: None`) a component's estimation inputs
+#[tauri::command]
+pub async fn set_component_capacity(
+    project_id: String,
+    component_id: String,
+    inputs: Option<CapacityInputs>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<Component, ApiError> {
+    if let Some(inputs) = &inputs {
+        inputs.validate()?;
+    }
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let project = &mut state.project;
+    let component = project
+        .components
+        .iter_mut()
+        .find(|c| c.id == component_id)
+        .ok_or_else(|| ApiError::ComponentNotFound {
+            component_id: component_id.clone(),
+            project_id: project_id.clone(),
+            source: None,
+        })?;
+    check_revision("component", &component.id, expected_revision, component.revision, &*component)?;
+
+    component.capacity = inputs;
+    component.revision += 1;
+    let updated = component.clone();
+    project.revision += 1;
+    project.updated_at = Utc::now();
+    changes.publish(&app, Change::ComponentChanged {
+        project_id: project_id.clone(),
+        change: ComponentChange::Updated,
+        component_id: updated.id.clone(),
+        component: Some(updated.clone()),
+    })?;
+    log::info!("Capacity inputs updated for component {} in project {}", component_id, project_id);
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, component_with, state_with};
+    use crate::{ComponentType, Connection};
+
+    fn sized(id: &str, deps: &[&str], capacity: Option<CapacityInputs>) -> Component {
+        Component {
+            capacity,
+            ..component_with(id, ComponentType::Service, deps, &[])
+        }
+    }
+
+    fn find<'a>(report: &'a CapacityReport, id: &str) -> &'a ComponentEstimate {
+        report.estimates.iter().find(|e| e.id == id).unwrap()
+    }
+
+    #[test]
+    fn spreads_load_from_users_to_storage() {
+        let inputs: CapacityInputs = serde_json::from_value(serde_json::json!({
+            "daily_active_users": 10_000_000.0,
+            "requests_per_user": 10.0,
+            "payload_size": "1 KB",
+            "read_write_ratio": 4.0,
+        }))
+        .unwrap();
+        let db_inputs = CapacityInputs {
+            retention: Some(TimeSpan::parse("1 y").unwrap()),
+            ..Default::default()
+        };
+        let mut state = state_with(vec![
+            sized("api", &["cache", "db"], Some(inputs)),
+            sized("cache", &[], None),
+            sized("db", &[], Some(db_inputs)),
+        ]);
+        state.connections = vec![Connection {
+            properties: HashMap::from([("calls_per_request".to_string(), "3".to_string())]),
+            ..test_support::connection("c1", "api", "cache")
+        }];
+
+        let report = estimate(&state);
+        assert!(report.warnings.is_empty());
+
+        // 10M users * 10 requests / 86400 s
+        let api = find(&report, "api");
+        assert!((api.qps.value - 1157.407).abs() < 0.01);
+        assert_eq!(api.qps.display, "1.16 K req/s");
+        assert!((api.peak_qps.value - 2.0 * api.qps.value).abs() < 1e-9);
+        assert!((api.write_qps.value - 231.48).abs() < 0.01);
+        assert_eq!(api.bandwidth.display, "1.16 MB/s");
+
+        let cache = find(&report, "cache");
+        assert!((cache.qps.value - 3.0 * api.qps.value).abs() < 1e-6);
+        assert_eq!(report.estimates[0].id, "cache");
+
+        // 20M writes a day of 1 KB each, kept for a year
+        let db = find(&report, "db");
+        assert_eq!(db.storage_per_day.display, "20 GB");
+        assert_eq!(db.retained_storage.as_ref().unwrap().display, "7.3 TB");
+    }
+
+    #[test]
+    fn reports_incomplete_inputs_and_cycles() {
+        let partial = CapacityInputs {
+            daily_active_users: Some(1000.0),
+            ..Default::default()
+        };
+        let origin = CapacityInputs {
+            daily_active_users: Some(86_400.0),
+            requests_per_user: Some(1.0),
+            ..Default::default()
+        };
+        // The store sits downstream of the cycle and must not be mistaken for part of it
+        let state = state_with(vec![
+            sized("store", &[], None),
+            sized("ui", &["a"], Some(origin)),
+            sized("a", &["b", "store"], Some(partial)),
+            sized("b", &["a"], None),
+        ]);
+        let report = estimate(&state);
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings.iter().any(|w| w.contains("dependency cycle")));
+        assert!(report.warnings.iter().any(|w| w.contains("needs both")));
+        assert_eq!(find(&report, "ui").qps.value, 1.0);
+        assert_eq!(find(&report, "a").qps.value, 1.0);
+        assert_eq!(find(&report, "store").qps.value, 1.0);
+    }
+
+    #[test]
+    fn validates_inputs() {
+        assert!(CapacityInputs { peak_factor: Some(0.5), ..Default::default() }.validate().is_err());
+        assert!(CapacityInputs { daily_active_users: Some(-1.0), ..Default::default() }.validate().is_err());
+        assert!(serde_json::from_str::<CapacityInputs>(r#"{"payload_size": "3 furlongs"}"#).is_err());
+        assert!(serde_json::from_str::<CapacityInputs>(r#"{"dau": 5}"#).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+// Links between components and the diagram elements that draw them
+//
+// An element stands for a component through its `component_id`, and the same component can
+// appear on any number of diagrams. Components are the source of truth when reconciling: a
+// component no diagram shows gets an element on the main diagram, an element whose component
+// is gone is removed, and an unlinked element is linked to the component with its name, or
+// to a new component when there is none. An unlinked element with no label or name has
+// nothing to go by, so it is reported and left alone, as are containers, which only group
+// elements.
+
+use crate::containment;
+use crate::diagram_patch::{apply_patch, commit_patch, DiagramOp};
+use crate::diagrams::{on_diagram, MAIN_DIAGRAM_ID};
+use crate::events::{Change, ChangeFeed, ComponentChange};
+use crate::geometry::{Bounds, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::{ApiError, Component, ComponentStatus, ComponentType, DiagramElement, Position};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+// Gap between a new element and the rest of the diagram, and between new elements
+const SPACING: f64 = 40.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LinkIssue {
+    /// No diagram shows the component
+    ComponentWithoutElement { component_id: String },
+    /// The element points at a component that no longer exists
+    MissingComponent {
+        diagram_id: String,
+        element_id: String,
+        component_id: String,
+    },
+    /// The element isn't linked to any component
+    UnlinkedElement { diagram_id: String, element_id: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconcileReport {
+    pub issues: Vec<LinkIssue>,
+    /// Whether the issues were fixed or only reported
+    pub fixed: bool,
+    /// Components made for unlinked elements
+    pub created_components: Vec<String>,
+    /// Elements added for components no diagram showed
+    pub created_elements: Vec<String>,
+    /// Issues a fix couldn't settle: unlinked elements without a label or name
+    pub unfixed: Vec<LinkIssue>,
+}
+
+pub fn element_type(component_type: &ComponentType) -> &'static str {
+    match component_type {
+        ComponentType::Frontend => "frontend",
+        ComponentType::Backend => "backend",
+        ComponentType::Database => "database",
+        ComponentType::Api => "api",
+        ComponentType::Service => "service",
+        ComponentType::Integration => "integration",
+    }
+}
+
+fn component_type(element_type: &str) -> ComponentType {
+    match element_type.to_ascii_lowercase().as_str() {
+        "frontend" => ComponentType::Frontend,
+        "backend" => ComponentType::Backend,
+        "database" => ComponentType::Database,
+        "api" => ComponentType::Api,
+        "integration" => ComponentType::Integration,
+        _ => ComponentType::Service,
+    }
+}
+
+/// Main diagram first, then the views, as `(diagram id, elements)`
+fn all_diagrams(state: &ProjectState) -> impl Iterator<Item = (&str, &[DiagramElement])> {
+    std::iter::once((MAIN_DIAGRAM_ID, state.diagram.as_slice()))
+        .chain(state.views.iter().map(|view| (view.id.as_str(), view.elements.as_slice())))
+}
+
+/// An element for `component`, placed in the row below everything else on the diagram
+pub fn element_for(diagram: &[DiagramElement], component: &Component, slot: usize) -> DiagramElement {
+    let bounds: Vec<Bounds> = diagram.iter().map(Bounds::of).collect();
+    let left = bounds.iter().map(|b| b.x).fold(f64::INFINITY, f64::min);
+    let bottom = bounds.iter().map(|b| b.y + b.height).fold(f64::NEG_INFINITY, f64::max);
+    let (x, y) = if bounds.is_empty() { (0.0, 0.0) } else { (left, bottom + SPACING) };
+    DiagramElement {
+        id: Uuid::new_v4().to_string(),
+        element_type: element_type(&component.component_type).to_string(),
+        position: Position {
+            x: x + slot as f64 * (DEFAULT_WIDTH + SPACING),
+            y,
+        },
+        width: DEFAULT_WIDTH,
+        height: DEFAULT_HEIGHT,
+        rotation: 0.0,
+        z_index: 0,
+        parent_id: None,
+        component_id: Some(component.id.clone()),
+        properties: HashMap::from([("label".to_string(), component.name.clone())]),
+    }
+}
+
+/// Deletes every element on the diagram that draws `component_id`
+pub fn remove_element_ops(diagram: &[DiagramElement], component_id: &str) -> Vec<DiagramOp> {
+    diagram
+        .iter()
+        .filter(|e| e.component_id.as_deref() == Some(component_id))
+        .map(|e| DiagramOp::DeleteElement { id: e.id.clone() })
+        .collect()
+}
+
+pub fn find_issues(state: &ProjectState) -> Vec<LinkIssue> {
+    let components: HashSet<&str> = state.project.components.iter().map(|c| c.id.as_str()).collect();
+    let mut shown = HashSet::new();
+    let mut issues = Vec::new();
+    for (diagram_id, elements) in all_diagrams(state) {
+        for element in elements {
+            match element.component_id.as_deref() {
+                Some(id) if components.contains(id) => {
+                    shown.insert(id);
+                }
+                Some(id) => issues.push(LinkIssue::MissingComponent {
+                    diagram_id: diagram_id.to_string(),
+                    element_id: element.id.clone(),
+                    component_id: id.to_string(),
+                }),
+                None if containment::is_container(elements, &element.id) => {}
+                None => issues.push(LinkIssue::UnlinkedElement {
+                    diagram_id: diagram_id.to_string(),
+                    element_id: element.id.clone(),
+                }),
+            }
+        }
+    }
+    let unshown = state.project.components.iter().filter(|c| !shown.contains(c.id.as_str()));
+    issues.extend(unshown.map(|c| LinkIssue::ComponentWithoutElement { component_id: c.id.clone() }));
+    issues
+}
+
+/// What fixing a set of issues takes: new components, then one patch per diagram
+#[derive(Debug, Default)]
+pub struct Fixes {
+    pub components: Vec<Component>,
+    pub patches: Vec<(String, Vec<DiagramOp>)>,
+    pub unfixed: Vec<LinkIssue>,
+}
+
+// Unlike `label()`, no fallback to the element type, which would give every unlabeled
+// element of a type the same component
+fn given_name(element: &DiagramElement) -> Option<&str> {
+    ["label", "name"]
+        .into_iter()
+        .filter_map(|key| element.properties.get(key))
+        .map(|name| name.trim())
+        .find(|name| !name.is_empty())
+}
+
+fn link_op(element_id: &str, component_id: &str) -> DiagramOp {
+    DiagramOp::UpdateElement {
+        id: element_id.to_string(),
+        element_type: None,
+        width: None,
+        height: None,
+        rotation: None,
+        z_index: None,
+        parent_id: None,
+        component_id: Some(Some(component_id.to_string())),
+        properties: HashMap::new(),
+    }
+}
+
+pub fn plan_fixes(state: &ProjectState, issues: &[LinkIssue]) -> Fixes {
+    let mut fixes = Fixes::default();
+    let mut by_name: HashMap<String, String> =
+        state.project.components.iter().map(|c| (c.name.to_lowercase(), c.id.clone())).collect();
+    let mut newly_shown = HashSet::new();
+    let patch = |fixes: &mut Fixes, diagram_id: &str, op: DiagramOp| {
+        match fixes.patches.iter_mut().find(|(id, _)| id == diagram_id) {
+            Some((_, ops)) => ops.push(op),
+            None => fixes.patches.push((diagram_id.to_string(), vec![op])),
+        }
+    };
+
+    for issue in issues {
+        match issue {
+            LinkIssue::MissingComponent { diagram_id, element_id, .. } => {
+                patch(&mut fixes, diagram_id, DiagramOp::DeleteElement { id: element_id.clone() });
+            }
+            LinkIssue::UnlinkedElement { diagram_id, element_id } => {
+                let Some((_, elements)) = all_diagrams(state).find(|(id, _)| id == diagram_id) else {
+                    continue;
+                };
+                let Some(element) = elements.iter().find(|e| &e.id == element_id) else {
+                    continue;
+                };
+                let Some(name) = given_name(element).map(String::from) else {
+                    fixes.unfixed.push(issue.clone());
+                    continue;
+                };
+                let component_id = by_name.entry(name.to_lowercase()).or_insert_with(|| {
+                    let component = Component {
+                        id: Uuid::new_v4().to_string(),
+                        name: name.clone(),
+                        component_type: component_type(&element.element_type),
+                        description: String::new(),
+                        dependencies: Vec::new(),
+                        status: ComponentStatus::NotStarted,
+                        metadata: HashMap::new(),
+                        revision: 1,
+                        capacity: None,
+                        availability: None,
+                    };
+                    let id = component.id.clone();
+                    fixes.components.push(component);
+                    id
+                });
+                newly_shown.insert(component_id.clone());
+                patch(&mut fixes, diagram_id, link_op(element_id, component_id));
+            }
+            LinkIssue::ComponentWithoutElement { .. } => {}
+        }
+    }
+
+    // Linking an element may already have put the component on a diagram
+    let mut slot = 0;
+    for issue in issues {
+        let LinkIssue::ComponentWithoutElement { component_id } = issue else {
+            continue;
+        };
+        if newly_shown.contains(component_id) {
+            continue;
+        }
+        let Some(component) = state.project.components.iter().find(|c| &c.id == component_id) else {
+            continue;
+        };
+        let element = element_for(&state.diagram, component, slot);
+        slot += 1;
+        patch(&mut fixes, MAIN_DIAGRAM_ID, DiagramOp::AddElement { element });
+    }
+    fixes
+}
+
+#[tauri::command]
+pub async fn reconcile_components(
+    project_id: String,
+    fix: Option<bool>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<ReconcileReport, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let issues = find_issues(&state);
+    let fix = fix.unwrap_or(false) && !issues.is_empty();
+    let mut report = ReconcileReport {
+        issues,
+        fixed: fix,
+        created_components: Vec::new(),
+        created_elements: Vec::new(),
+        unfixed: Vec::new(),
+    };
+    if !fix {
+        return Ok(report);
+    }
+
+    let fixes = plan_fixes(&state, &report.issues);
+    // Every patch is tried on a copy of the diagrams first, so a fix that can't be applied
+    // leaves both the components and the diagrams as they were
+    let mut trial = ProjectState::new(state.project.clone());
+    trial.project.components.extend(fixes.components.iter().cloned());
+    trial.diagram = state.diagram.clone();
+    trial.connections = state.connections.clone();
+    trial.views = state.views.clone();
+    for (diagram_id, ops) in &fixes.patches {
+        on_diagram(&mut trial, Some(diagram_id), |trial| apply_patch(trial, ops))?;
+    }
+
+    report.unfixed = fixes.unfixed;
+    if !fixes.components.is_empty() {
+        state.project.revision += 1;
+        state.project.updated_at = Utc::now();
+    }
+    for component in fixes.components {
+        report.created_components.push(component.id.clone());
+        state.project.components.push(component.clone());
+        changes.publish(&app, Change::ComponentChanged {
+            project_id: project_id.clone(),
+            change: ComponentChange::Added,
+            component_id: component.id.clone(),
+            component: Some(component),
+        })?;
+    }
+    for (diagram_id, ops) in fixes.patches {
+        report.created_elements.extend(ops.iter().filter_map(|op| match op {
+            DiagramOp::AddElement { element } => Some(element.id.clone()),
+            _ => None,
+        }));
+        let diagram_id = Some(diagram_id.as_str());
+        on_diagram(&mut state, diagram_id, |state| commit_patch(state, diagram_id, ops, &app, &changes))?;
+    }
+    log::info!(
+        "Reconciled components in project {}: {} issues, {} components and {} elements created",
+        project_id,
+        report.issues.len(),
+        report.created_components.len(),
+        report.created_elements.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagrams;
+    use crate::test_support::{self, component_with, diagram_state, state_with, strings};
+
+    fn named(id: &str, name: &str) -> Component {
+        Component {
+            name: name.into(),
+            ..component_with(id, ComponentType::Database, &[], &[])
+        }
+    }
+
+    fn labelled(id: &str, label: &str, component_id: Option<&str>) -> DiagramElement {
+        DiagramElement {
+            component_id: component_id.map(String::from),
+            properties: strings(&[("label", label)]),
+            ..test_support::element(id)
+        }
+    }
+
+    fn fix(state: &mut ProjectState) {
+        let issues = find_issues(state);
+        let fixes = plan_fixes(state, &issues);
+        state.project.components.extend(fixes.components);
+        for (diagram_id, ops) in fixes.patches {
+            on_diagram(state, Some(&diagram_id), |state| apply_patch(state, &ops)).unwrap();
+        }
+    }
+
+    #[test]
+    fn reports_each_kind_of_issue() {
+        let mut state = state_with(vec![named("db", "Orders DB"), named("cache", "Cache")]);
+        state.diagram = vec![
+            labelled("a", "Orders DB", Some("db")),
+            labelled("b", "Gone", Some("old")),
+            labelled("c", "Web", None),
+        ];
+        assert_eq!(
+            find_issues(&state),
+            vec![
+                LinkIssue::MissingComponent {
+                    diagram_id: "main".into(),
+                    element_id: "b".into(),
+                    component_id: "old".into(),
+                },
+                LinkIssue::UnlinkedElement {
+                    diagram_id: "main".into(),
+                    element_id: "c".into(),
+                },
+                LinkIssue::ComponentWithoutElement { component_id: "cache".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn fixing_links_by_name_and_fills_the_gaps() {
+        let mut state = state_with(vec![named("db", "Orders DB"), named("cache", "Cache")]);
+        state.diagram = vec![
+            labelled("a", "orders db", None),
+            labelled("b", "Gone", Some("old")),
+            labelled("c", "Web", None),
+        ];
+        fix(&mut state);
+
+        assert!(find_issues(&state).is_empty());
+        let linked = |id: &str| state.diagram.iter().find(|e| e.id == id).and_then(|e| e.component_id.clone());
+        assert_eq!(linked("a").as_deref(), Some("db"));
+        assert!(state.diagram.iter().all(|e| e.id != "b"));
+        let web = state.project.components.iter().find(|c| c.name == "Web").unwrap();
+        assert_eq!(linked("c").as_ref(), Some(&web.id));
+        let cache = state.diagram.iter().find(|e| e.component_id.as_deref() == Some("cache")).unwrap();
+        assert_eq!(cache.element_type, "database");
+        assert!(cache.position.y > 80.0);
+    }
+
+    #[test]
+    fn unlabeled_elements_are_reported_not_named_after_their_type() {
+        let blank = labelled("blank", "  ", None);
+        let mut state = diagram_state(vec![test_support::element("bare"), blank, labelled("c", "Web", None)], vec![]);
+        let issues = find_issues(&state);
+        let fixes = plan_fixes(&state, &issues);
+        assert_eq!(fixes.components.len(), 1);
+        assert_eq!(fixes.components[0].name, "Web");
+        assert_eq!(fixes.unfixed, issues[..2]);
+
+        fix(&mut state);
+        assert_eq!(find_issues(&state), issues[..2]);
+        assert!(state.project.components.iter().all(|c| c.name != "service"));
+    }
+
+    #[test]
+    fn a_component_shown_on_any_diagram_counts() {
+        let mut state = state_with(vec![named("db", "Orders DB")]);
+        let view = diagrams::create(&mut state, "Containers", diagrams::DiagramKind::Container).unwrap();
+        let ops = vec![DiagramOp::AddElement { element: labelled("a", "Orders DB", Some("db")) }];
+        on_diagram(&mut state, Some(&view.id), |state| apply_patch(state, &ops)).unwrap();
+        assert!(find_issues(&state).is_empty());
+
+        let ops = remove_element_ops(&state.views[0].elements, "db");
+        on_diagram(&mut state, Some(&view.id), |state| apply_patch(state, &ops)).unwrap();
+        assert_eq!(find_issues(&state), vec![LinkIssue::ComponentWithoutElement { component_id: "db".into() }]);
+    }
+}
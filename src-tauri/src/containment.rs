@@ -0,0 +1,382 @@
+// Nested groups and containers
+//
+// An element's `parent_id` names the group or container it sits inside: a VPC, a region, a
+// bounded context. Positions stay absolute, but moving a container moves everything inside it,
+// so generated moves go through `move_ops`, which walks parents first and skips children that
+// already land where they should by following their parent.
+//
+// Grouping adds a container sized around the selection and re-parents the selection into it;
+// ungrouping hands the children to the container's own parent and deletes the container.
+
+use crate::diagram_patch::{check_diagram_revision, commit_patch, DiagramOp};
+use crate::diagrams::on_diagram;
+use crate::events::ChangeFeed;
+use crate::geometry::Bounds;
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::{ApiError, DiagramElement, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+// Room left between a container's edge and the children it wraps
+pub const CONTAINER_PADDING: f64 = 20.0;
+
+/// Ids of the element's ancestors, nearest first
+pub fn ancestors<'a>(diagram: &'a [DiagramElement], id: &str) -> Vec<&'a str> {
+    let parents: HashMap<&str, &str> = diagram
+        .iter()
+        .filter_map(|e| Some((e.id.as_str(), e.parent_id.as_deref()?)))
+        .collect();
+    let mut chain = Vec::new();
+    let mut current = id;
+    while let Some(&parent) = parents.get(current) {
+        // Patches never create cycles, but imported data might contain one
+        if chain.contains(&parent) || parent == id {
+            break;
+        }
+        chain.push(parent);
+        current = parent;
+    }
+    chain
+}
+
+/// Indices of everything inside the element, at any depth
+pub fn descendants(diagram: &[DiagramElement], id: &str) -> Vec<usize> {
+    let mut found: Vec<usize> = Vec::new();
+    let mut seen = HashSet::from([id]);
+    let mut queue = vec![id];
+    while let Some(parent) = queue.pop() {
+        for (i, element) in diagram.iter().enumerate() {
+            if element.parent_id.as_deref() == Some(parent) && seen.insert(element.id.as_str()) {
+                found.push(i);
+                queue.push(&element.id);
+            }
+        }
+    }
+    found.sort_unstable();
+    found
+}
+
+pub fn is_container(diagram: &[DiagramElement], id: &str) -> bool {
+    diagram.iter().any(|e| e.parent_id.as_deref() == Some(id))
+}
+
+/// Box around a set of element boxes, grown by the container padding
+pub fn wrap(bounds: impl IntoIterator<Item = Bounds>) -> Option<Bounds> {
+    let (left, top, right, bottom) = bounds.into_iter().fold(
+        (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        |(l, t, r, b), bounds| {
+            (
+                l.min(bounds.x),
+                t.min(bounds.y),
+                r.max(bounds.x + bounds.width),
+                b.max(bounds.y + bounds.height),
+            )
+        },
+    );
+    left.is_finite().then_some(Bounds {
+        x: left - CONTAINER_PADDING,
+        y: top - CONTAINER_PADDING,
+        width: right - left + 2.0 * CONTAINER_PADDING,
+        height: bottom - top + 2.0 * CONTAINER_PADDING,
+    })
+}
+
+/// Elements ordered so every container comes before what's inside it, otherwise as drawn
+pub fn parents_first(diagram: &[DiagramElement]) -> Vec<&DiagramElement> {
+    let mut order: Vec<(usize, &DiagramElement)> = diagram.iter().map(|e| (ancestors(diagram, &e.id).len(), e)).collect();
+    order.sort_by_key(|&(depth, _)| depth);
+    order.into_iter().map(|(_, e)| e).collect()
+}
+
+/// Moves that put elements at the given top-left positions, parents first; children not
+/// listed follow their parent
+pub fn move_ops(state: &ProjectState, targets: &HashMap<String, Position>) -> Vec<DiagramOp> {
+    // How far each element has been carried so far, which its children inherit
+    let mut shifts: HashMap<&str, (f64, f64)> = HashMap::new();
+    let mut ops = Vec::new();
+    for element in parents_first(&state.diagram) {
+        let (dx, dy) = element
+            .parent_id
+            .as_deref()
+            .and_then(|parent| shifts.get(parent))
+            .copied()
+            .unwrap_or((0.0, 0.0));
+        let carried = Position {
+            x: element.position.x + dx,
+            y: element.position.y + dy,
+        };
+        let target = targets.get(&element.id).cloned().unwrap_or_else(|| carried.clone());
+        if target != carried {
+            ops.push(DiagramOp::MoveElement {
+                id: element.id.clone(),
+                position: target.clone(),
+            });
+        }
+        shifts.insert(&element.id, (target.x - element.position.x, target.y - element.position.y));
+    }
+    ops
+}
+
+fn common_parent(state: &ProjectState, selected: &[&DiagramElement]) -> Result<Option<String>, ApiError> {
+    let parent = selected[0].parent_id.clone();
+    if selected.iter().any(|e| e.parent_id != parent) {
+        return Err(ApiError::InvalidProjectData {
+            details: "Elements in different containers can't be grouped together".to_string(),
+            source: None,
+        });
+    }
+    if let Some(parent) = &parent {
+        if !state.diagram.iter().any(|e| &e.id == parent) {
+            log::warn!("Grouping elements whose container {} no longer exists", parent);
+            return Ok(None);
+        }
+    }
+    Ok(parent)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GroupOptions {
+    /// Element type of the new container, e.g. `vpc` or `bounded_context`
+    pub element_type: String,
+    pub properties: HashMap<String, String>,
+}
+
+impl Default for GroupOptions {
+    fn default() -> Self {
+        Self {
+            element_type: "group".to_string(),
+            properties: HashMap::new(),
+        }
+    }
+}
+
+/// Operations that wrap the elements in a new container, drawn just below the lowest of them
+pub fn group_ops(
+    state: &ProjectState,
+    container_id: &str,
+    element_ids: &[String],
+    options: &GroupOptions,
+) -> Result<Vec<DiagramOp>, ApiError> {
+    let unknown: Vec<&str> = element_ids
+        .iter()
+        .filter(|id| !state.diagram.iter().any(|e| &e.id == *id))
+        .map(String::as_str)
+        .collect();
+    if element_ids.is_empty() || !unknown.is_empty() {
+        return Err(ApiError::InvalidProjectData {
+            details: format!("Select existing elements to group (unknown: {})", unknown.join(", ")),
+            source: None,
+        });
+    }
+    let selected: Vec<&DiagramElement> = state.diagram.iter().filter(|e| element_ids.contains(&e.id)).collect();
+    let parent_id = common_parent(state, &selected)?;
+    let bounds = wrap(selected.iter().map(|e| Bounds::of(e))).expect("selection is not empty");
+
+    let container = DiagramElement {
+        id: container_id.to_string(),
+        element_type: options.element_type.clone(),
+        position: Position { x: bounds.x, y: bounds.y },
+        width: bounds.width,
+        height: bounds.height,
+        rotation: 0.0,
+        z_index: selected.iter().map(|e| e.z_index).min().unwrap_or(0).saturating_sub(1),
+        parent_id,
+        component_id: None,
+        properties: options.properties.clone(),
+    };
+    let mut ops = vec![DiagramOp::AddElement { element: container }];
+    ops.extend(selected.iter().map(|e| reparent(&e.id, Some(container_id.to_string()))));
+    Ok(ops)
+}
+
+/// Operations that move a container's children to its parent and delete the container
+pub fn ungroup_ops(state: &ProjectState, container_id: &str) -> Result<Vec<DiagramOp>, ApiError> {
+    let container = state
+        .diagram
+        .iter()
+        .find(|e| e.id == container_id)
+        .ok_or_else(|| ApiError::InvalidProjectData {
+            details: format!("Unknown diagram element: {}", container_id),
+            source: None,
+        })?;
+    let mut ops: Vec<DiagramOp> = state
+        .diagram
+        .iter()
+        .filter(|e| e.parent_id.as_deref() == Some(container_id))
+        .map(|e| reparent(&e.id, container.parent_id.clone()))
+        .collect();
+    ops.push(DiagramOp::DeleteElement {
+        id: container_id.to_string(),
+    });
+    Ok(ops)
+}
+
+fn reparent(id: &str, parent_id: Option<String>) -> DiagramOp {
+    DiagramOp::UpdateElement {
+        id: id.to_string(),
+        element_type: None,
+        width: None,
+        height: None,
+        rotation: None,
+        z_index: None,
+        parent_id: Some(parent_id),
+        component_id: None,
+        properties: HashMap::new(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupResult {
+    pub diagram_revision: u64,
+    /// The new container, when grouping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    pub inverse: Vec<DiagramOp>,
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn group_elements(
+    project_id: String,
+    diagram_id: Option<String>,
+    element_ids: Vec<String>,
+    options: Option<GroupOptions>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<GroupResult, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let diagram_id = diagram_id.as_deref();
+    on_diagram(&mut state, diagram_id, |state| {
+        check_diagram_revision(state, diagram_id, expected_revision)?;
+        let container_id = Uuid::new_v4().to_string();
+        let ops = group_ops(state, &container_id, &element_ids, &options.unwrap_or_default())?;
+        let inverse = commit_patch(state, diagram_id, ops, &app, &changes)?;
+        log::info!("Grouped {} elements into {} in project {}", element_ids.len(), container_id, project_id);
+        Ok(GroupResult {
+            diagram_revision: state.diagram_revision,
+            container_id: Some(container_id),
+            inverse,
+        })
+    })
+}
+
+#[tauri::command]
+pub async fn ungroup_elements(
+    project_id: String,
+    diagram_id: Option<String>,
+    container_id: String,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<GroupResult, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let diagram_id = diagram_id.as_deref();
+    on_diagram(&mut state, diagram_id, |state| {
+        check_diagram_revision(state, diagram_id, expected_revision)?;
+        let ops = ungroup_ops(state, &container_id)?;
+        let inverse = commit_patch(state, diagram_id, ops, &app, &changes)?;
+        log::info!("Ungrouped container {} in project {}", container_id, project_id);
+        Ok(GroupResult {
+            diagram_revision: state.diagram_revision,
+            container_id: None,
+            inverse,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagram_patch::apply_patch;
+    use crate::test_support::{diagram_state, element_at};
+
+    fn inside(parent: &str, element: DiagramElement) -> DiagramElement {
+        DiagramElement {
+            parent_id: Some(parent.into()),
+            ..element
+        }
+    }
+
+    fn position<'a>(state: &'a ProjectState, id: &str) -> &'a Position {
+        &state.diagram.iter().find(|e| e.id == id).unwrap().position
+    }
+
+    #[test]
+    fn group_then_ungroup_round_trips() {
+        let mut state = diagram_state(vec![element_at("a", 0.0, 0.0), element_at("b", 200.0, 100.0)], vec![]);
+        let ops = group_ops(&state, "vpc", &["a".into(), "b".into()], &GroupOptions::default()).unwrap();
+        apply_patch(&mut state, &ops).unwrap();
+
+        let vpc = state.diagram.iter().find(|e| e.id == "vpc").unwrap();
+        assert_eq!(vpc.position, Position { x: -20.0, y: -20.0 });
+        assert_eq!((vpc.width, vpc.height, vpc.z_index), (340.0, 190.0, -1));
+        assert!(state.diagram.iter().filter(|e| e.id != "vpc").all(|e| e.parent_id.as_deref() == Some("vpc")));
+
+        let ops = ungroup_ops(&state, "vpc").unwrap();
+        apply_patch(&mut state, &ops).unwrap();
+        assert_eq!(state.diagram.len(), 2);
+        assert!(state.diagram.iter().all(|e| e.parent_id.is_none()));
+
+        // Grouping across containers is refused
+        state.diagram[0].parent_id = Some("b".into());
+        assert!(group_ops(&state, "x", &["a".into(), "b".into()], &GroupOptions::default()).is_err());
+    }
+
+    #[test]
+    fn moving_a_container_carries_its_children() {
+        let mut state = diagram_state(
+            vec![
+                element_at("region", 0.0, 0.0),
+                inside("region", element_at("vpc", 10.0, 10.0)),
+                inside("vpc", element_at("svc", 20.0, 20.0)),
+            ],
+            vec![],
+        );
+        let undo = apply_patch(
+            &mut state,
+            &[DiagramOp::MoveElement {
+                id: "region".into(),
+                position: Position { x: 100.0, y: 50.0 },
+            }],
+        )
+        .unwrap();
+        assert_eq!(position(&state, "svc"), &Position { x: 120.0, y: 70.0 });
+        apply_patch(&mut state, &undo).unwrap();
+        assert_eq!(position(&state, "svc"), &Position { x: 20.0, y: 20.0 });
+
+        // Only the parent needs a move when the children keep their place inside it
+        let targets = HashMap::from([
+            ("vpc".to_string(), Position { x: 60.0, y: 10.0 }),
+            ("svc".to_string(), Position { x: 70.0, y: 20.0 }),
+        ]);
+        let ops = move_ops(&state, &targets);
+        assert_eq!(ops.len(), 1);
+        apply_patch(&mut state, &ops).unwrap();
+        assert_eq!(position(&state, "svc"), &Position { x: 70.0, y: 20.0 });
+    }
+
+    #[test]
+    fn patches_reject_containment_cycles() {
+        let elements = vec![element_at("outer", 0.0, 0.0), inside("outer", element_at("inner", 0.0, 0.0))];
+        let mut state = diagram_state(elements, vec![]);
+        assert!(apply_patch(&mut state, &[reparent("outer", Some("inner".into()))]).is_err());
+        assert!(apply_patch(&mut state, &[reparent("outer", Some("outer".into()))]).is_err());
+        assert!(apply_patch(&mut state, &[reparent("outer", Some("nope".into()))]).is_err());
+
+        // Deleting a container hands its children up, and undo puts them back
+        let undo = apply_patch(&mut state, &[DiagramOp::DeleteElement { id: "outer".into() }]).unwrap();
+        assert_eq!(state.diagram[0].parent_id, None);
+        apply_patch(&mut state, &undo).unwrap();
+        let inner = state.diagram.iter().find(|e| e.id == "inner").unwrap();
+        assert_eq!(inner.parent_id.as_deref(), Some("outer"));
+        assert_eq!(ancestors(&state.diagram, "inner"), ["outer"]);
+    }
+}
@@ -0,0 +1,483 @@
+// Monthly cost estimates per component
+//
+// Prices come from a JSON pricing table. The bundled table is copied to `<app data>/pricing.json`
+// the first time it's needed so users can edit it; that copy wins from then on. A component's
+// cost is read from its type's defaults in the table plus these metadata values:
+// `instance_size`, `replicas`, `storage_gb` and `egress_gb` (per month).
+
+use crate::availability;
+use crate::state::ProjectStore;
+use crate::{ApiError, Component, ComponentType, Project};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+
+const BUNDLED_PRICING: &str = include_str!("../resources/pricing.json");
+const USER_PRICING_FILE: &str = "pricing.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TypePricing {
+    /// Instance size used when a component doesn't set `instance_size`; none means no compute
+    pub instance_size: Option<String>,
+    /// Flat monthly fee, e.g. a managed gateway
+    pub monthly_base: f64,
+    /// Overrides the table-wide storage price
+    pub storage_gb_month: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PricingTable {
+    pub currency: String,
+    pub hours_per_month: f64,
+    /// Hourly price per instance size
+    pub instance_hourly: HashMap<String, f64>,
+    pub storage_gb_month: f64,
+    pub egress_gb: f64,
+    #[serde(default)]
+    pub component_types: HashMap<ComponentType, TypePricing>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CostLine {
+    pub item: String,
+    pub quantity: f64,
+    pub unit: String,
+    pub unit_price: f64,
+    pub monthly: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentCost {
+    pub component_id: String,
+    pub name: String,
+    pub component_type: ComponentType,
+    pub monthly: f64,
+    pub lines: Vec<CostLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeCost {
+    pub component_type: ComponentType,
+    pub monthly: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CostReport {
+    pub project_id: String,
+    pub currency: String,
+    pub monthly_total: f64,
+    /// Most expensive first
+    pub components: Vec<ComponentCost>,
+    pub by_type: Vec<TypeCost>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentCostChange {
+    pub component_id: String,
+    pub name: String,
+    /// `None` when the component only exists on one side
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+    pub delta: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CostDiff {
+    pub currency: String,
+    pub before_total: f64,
+    pub after_total: f64,
+    pub delta: f64,
+    /// Components whose cost changed, largest change first
+    pub changes: Vec<ComponentCostChange>,
+    pub warnings: Vec<String>,
+}
+
+/// One side of a cost comparison: a loaded project, or the JSON from `export_project_data`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CostSnapshot {
+    Project { project_id: String },
+    Export { data: String },
+}
+
+fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+pub fn parse_pricing(input: &str) -> Result<PricingTable, ApiError> {
+    let invalid = |details: String| ApiError::SerializationError {
+        operation: "parse pricing table".to_string(),
+        details,
+        source: None,
+    };
+    let mut table: PricingTable = serde_json::from_str(input).map_err(|e| ApiError::SerializationError {
+        operation: "parse pricing table".to_string(),
+        details: e.to_string(),
+        source: Some(Box::new(e)),
+    })?;
+    let prices = [table.hours_per_month, table.storage_gb_month, table.egress_gb]
+        .into_iter()
+        .chain(table.instance_hourly.values().copied())
+        .chain(table.component_types.values().flat_map(|t| [Some(t.monthly_base), t.storage_gb_month]).flatten());
+    if prices.into_iter().any(|p| !p.is_finite() || p < 0.0) {
+        return Err(invalid("Prices must be non-negative numbers".to_string()));
+    }
+
+    // Sizes are matched the way components spell them: trimmed and lowercase
+    let mut instance_hourly = HashMap::new();
+    for (size, hourly) in table.instance_hourly.drain() {
+        let key = size.trim().to_lowercase();
+        if instance_hourly.insert(key, hourly).is_some() {
+            return Err(invalid(format!("Instance size '{}' is listed more than once", size.trim())));
+        }
+    }
+    table.instance_hourly = instance_hourly;
+    for defaults in table.component_types.values_mut() {
+        if let Some(size) = &mut defaults.instance_size {
+            *size = size.trim().to_lowercase();
+        }
+    }
+    Ok(table)
+}
+
+pub fn bundled_pricing() -> PricingTable {
+    parse_pricing(BUNDLED_PRICING).expect("bundled pricing table is valid")
+}
+
+pub fn user_pricing_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path_resolver().app_data_dir().map(|dir| dir.join(USER_PRICING_FILE))
+}
+
+/// The user's table, seeding it from the bundled one if it doesn't exist yet
+pub fn load_pricing(path: Option<&Path>) -> Result<PricingTable, ApiError> {
+    let Some(path) = path else {
+        return Ok(bundled_pricing());
+    };
+    if path.exists() {
+        return parse_pricing(&fs::read_to_string(path)?);
+    }
+    let seeded = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, BUNDLED_PRICING));
+    if let Err(e) = seeded {
+        log::warn!("Could not write pricing table to {}: {}", path.display(), e);
+    }
+    Ok(bundled_pricing())
+}
+
+pub fn component_cost(component: &Component, pricing: &PricingTable, warnings: &mut Vec<String>) -> ComponentCost {
+    let defaults = pricing.component_types.get(&component.component_type).cloned().unwrap_or_default();
+    let mut number = |key: &str| -> f64 {
+        let Some(raw) = component.metadata.get(key) else { return 0.0 };
+        match raw.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() && n >= 0.0 => n,
+            _ => {
+                warnings.push(format!("{}: {} '{}' is not a non-negative number; ignored", component.name, key, raw));
+                0.0
+            }
+        }
+    };
+    let storage_gb = number("storage_gb");
+    let egress_gb = number("egress_gb");
+    let replicas = match availability::replicas(component.availability.as_ref(), Some(&component.metadata)) {
+        Some(replicas) => replicas as f64,
+        None => {
+            if let Some(raw) = component.metadata.get("replicas") {
+                warnings.push(format!("{}: replicas '{}' is not a whole number; counted as 1", component.name, raw));
+            }
+            1.0
+        }
+    };
+
+    let mut lines = Vec::new();
+    let mut line = |item: &str, quantity: f64, unit: &str, unit_price: f64| {
+        if quantity > 0.0 && unit_price > 0.0 {
+            lines.push(CostLine {
+                item: item.to_string(),
+                quantity,
+                unit: unit.to_string(),
+                unit_price,
+                monthly: round_cents(quantity * unit_price),
+            });
+        }
+    };
+
+    let size = component
+        .metadata
+        .get("instance_size")
+        .map(|s| s.trim().to_lowercase())
+        .or(defaults.instance_size);
+    if let Some(size) = size {
+        match pricing.instance_hourly.get(&size) {
+            Some(&hourly) => line(
+                &format!("compute ({})", size),
+                replicas * pricing.hours_per_month,
+                "instance-hours",
+                hourly,
+            ),
+            None => warnings.push(format!("{}: no price for instance size '{}'", component.name, size)),
+        }
+    }
+    line("base", 1.0, "month", defaults.monthly_base);
+    line("storage", storage_gb, "GB-month", defaults.storage_gb_month.unwrap_or(pricing.storage_gb_month));
+    line("egress", egress_gb, "GB", pricing.egress_gb);
+
+    ComponentCost {
+        component_id: component.id.clone(),
+        name: component.name.clone(),
+        component_type: component.component_type.clone(),
+        monthly: round_cents(lines.iter().map(|l| l.monthly).sum()),
+        lines,
+    }
+}
+
+pub fn estimate(project: &Project, pricing: &PricingTable) -> CostReport {
+    let mut warnings = Vec::new();
+    let mut components: Vec<ComponentCost> =
+        project.components.iter().map(|c| component_cost(c, pricing, &mut warnings)).collect();
+    components.sort_by(|a, b| b.monthly.total_cmp(&a.monthly).then_with(|| a.name.cmp(&b.name)));
+
+    let mut by_type: Vec<TypeCost> = Vec::new();
+    for cost in &components {
+        match by_type.iter_mut().find(|t| t.component_type == cost.component_type) {
+            Some(total) => total.monthly = round_cents(total.monthly + cost.monthly),
+            None => by_type.push(TypeCost {
+                component_type: cost.component_type.clone(),
+                monthly: cost.monthly,
+            }),
+        }
+    }
+    by_type.sort_by(|a, b| b.monthly.total_cmp(&a.monthly));
+
+    CostReport {
+        project_id: project.id.clone(),
+        currency: pricing.currency.clone(),
+        monthly_total: round_cents(components.iter().map(|c| c.monthly).sum()),
+        components,
+        by_type,
+        warnings,
+    }
+}
+
+pub fn diff(before: &CostReport, after: &CostReport) -> CostDiff {
+    let mut changes: Vec<ComponentCostChange> = Vec::new();
+    for cost in &before.components {
+        let other = after.components.iter().find(|c| c.component_id == cost.component_id);
+        changes.push(ComponentCostChange {
+            component_id: cost.component_id.clone(),
+            name: other.map_or(&cost.name, |o| &o.name).clone(),
+            before: Some(cost.monthly),
+            after: other.map(|o| o.monthly),
+            delta: round_cents(other.map_or(0.0, |o| o.monthly) - cost.monthly),
+        });
+    }
+    for cost in &after.components {
+        if !before.components.iter().any(|c| c.component_id == cost.component_id) {
+            changes.push(ComponentCostChange {
+                component_id: cost.component_id.clone(),
+                name: cost.name.clone(),
+                before: None,
+                after: Some(cost.monthly),
+                delta: cost.monthly,
+            });
+        }
+    }
+    changes.retain(|c| c.delta != 0.0 || c.before.is_none() != c.after.is_none());
+    changes.sort_by(|a, b| b.delta.abs().total_cmp(&a.delta.abs()).then_with(|| a.name.cmp(&b.name)));
+
+    CostDiff {
+        currency: after.currency.clone(),
+        before_total: before.monthly_total,
+        after_total: after.monthly_total,
+        delta: round_cents(after.monthly_total - before.monthly_total),
+        changes,
+        warnings: before.warnings.iter().chain(&after.warnings).cloned().collect(),
+    }
+}
+
+fn snapshot_project(snapshot: CostSnapshot, projects: &ProjectStore) -> Result<Project, ApiError> {
+    match snapshot {
+        CostSnapshot::Project { project_id } => projects
+            .read(&project_id, |state| state.project.clone())?
+            .ok_or(ApiError::ProjectNotFound { project_id, source: None }),
+        CostSnapshot::Export { data } => {
+            #[derive(Deserialize)]
+            struct Export {
+                project: Project,
+            }
+            serde_json::from_str::<Export>(&data)
+                .map(|export| export.project)
+                .map_err(|e| ApiError::InvalidProjectData {
+                    details: format!("Snapshot is not a project export: {}", e),
+                    source: Some(Box::new(e)),
+                })
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn estimate_costs(
+    project_id: String,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+) -> Result<CostReport, ApiError> {
+    let pricing = load_pricing(user_pricing_path(&app).as_deref())?;
+    let report = projects
+        .read(&project_id, |state| estimate(&state.project, &pricing))?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })?;
+    log::debug!("Estimated {} {} per month for project {}", report.monthly_total, report.currency, project_id);
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn compare_costs(
+    before: CostSnapshot,
+    after: CostSnapshot,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+) -> Result<CostDiff, ApiError> {
+    let pricing = load_pricing(user_pricing_path(&app).as_deref())?;
+    let before = estimate(&snapshot_project(before, &projects)?, &pricing);
+    let after = estimate(&snapshot_project(after, &projects)?, &pricing);
+    let diff = diff(&before, &after);
+    log::debug!("Cost difference between snapshots: {} {}", diff.delta, diff.currency);
+    Ok(diff)
+}
+
+#[tauri::command]
+pub async fn get_pricing_table(app: AppHandle) -> Result<PricingTable, ApiError> {
+    load_pricing(user_pricing_path(&app).as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::availability::{Availability, AvailabilityInputs};
+    use crate::test_support::{component_with, project_with};
+
+    #[test]
+    fn prices_compute_storage_and_egress() {
+        let pricing = bundled_pricing();
+        let report = estimate(
+            &project_with(vec![
+                component_with("db", ComponentType::Database, &[], &[("replicas", "2"), ("storage_gb", "100")]),
+                component_with("web", ComponentType::Frontend, &[], &[("egress_gb", "50")]),
+                component_with("jobs", ComponentType::Service, &[], &[("instance_size", "huge")]),
+            ]),
+            &pricing,
+        );
+        let db = &report.components[0];
+        assert_eq!(db.component_id, "db");
+        // 2 large instances for 730 h, plus 100 GB of database storage
+        assert_eq!(db.monthly, round_cents(2.0 * 730.0 * 0.0832) + 11.5);
+        let web = report.components.iter().find(|c| c.component_id == "web").unwrap();
+        assert_eq!(web.monthly, 1.0 + 4.5);
+        assert_eq!(report.monthly_total, round_cents(db.monthly + web.monthly));
+        assert_eq!(report.by_type[0].component_type, ComponentType::Database);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("huge"));
+    }
+
+    #[test]
+    fn typed_replicas_take_precedence() {
+        let pricing = bundled_pricing();
+        let mut warnings = Vec::new();
+        let single = component_cost(&component_with("api", ComponentType::Backend, &[], &[]), &pricing, &mut warnings);
+        let typed = Component {
+            availability: Some(AvailabilityInputs {
+                target: Availability(0.999),
+                replicas: Some(3),
+            }),
+            ..component_with("api", ComponentType::Backend, &[], &[("replicas", "2")])
+        };
+        let tripled = component_cost(&typed, &pricing, &mut warnings);
+        assert_eq!(tripled.lines[0].quantity, 3.0 * single.lines[0].quantity);
+
+        let unreadable = component_with("api", ComponentType::Backend, &[], &[("replicas", "lots")]);
+        component_cost(&unreadable, &pricing, &mut warnings);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn diffs_two_snapshots() {
+        let pricing = bundled_pricing();
+        let before = estimate(
+            &project_with(vec![
+                component_with("api", ComponentType::Backend, &[], &[]),
+                component_with("old", ComponentType::Service, &[], &[]),
+            ]),
+            &pricing,
+        );
+        let after = estimate(
+            &project_with(vec![
+                component_with("api", ComponentType::Backend, &[], &[("replicas", "3")]),
+                component_with("new", ComponentType::Integration, &[], &[]),
+            ]),
+            &pricing,
+        );
+        let diff = diff(&before, &after);
+        assert_eq!(diff.delta, round_cents(diff.after_total - diff.before_total));
+        assert_eq!(
+            diff.changes.iter().map(|c| c.component_id.as_str()).collect::<Vec<_>>(),
+            ["api", "old", "new"]
+        );
+        assert_eq!(diff.changes[1].after, None);
+        assert_eq!(diff.changes[2].before, None);
+    }
+
+    #[test]
+    fn seeds_and_reads_the_user_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(USER_PRICING_FILE);
+        load_pricing(Some(&path)).unwrap();
+        assert!(path.exists());
+
+        let mut edited = bundled_pricing();
+        edited.currency = "EUR".into();
+        fs::write(&path, serde_json::to_string(&edited).unwrap()).unwrap();
+        assert_eq!(load_pricing(Some(&path)).unwrap().currency, "EUR");
+
+        fs::write(&path, "{\"currency\": \"EUR\"}").unwrap();
+        assert!(load_pricing(Some(&path)).is_err());
+    }
+
+    #[test]
+    fn normalises_sizes_and_checks_type_prices() {
+        let mut table = bundled_pricing();
+        table.instance_hourly = HashMap::from([("M5.Large ".to_string(), 0.1)]);
+        table.component_types = HashMap::from([(
+            ComponentType::Service,
+            TypePricing { instance_size: Some("M5.LARGE".into()), ..Default::default() },
+        )]);
+        let parsed = parse_pricing(&serde_json::to_string(&table).unwrap()).unwrap();
+        let report = estimate(
+            &project_with(vec![
+                component_with("a", ComponentType::Service, &[], &[]),
+                component_with("b", ComponentType::Backend, &[], &[("instance_size", "m5.large")]),
+            ]),
+            &parsed,
+        );
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert!(report.components.iter().all(|c| c.monthly == round_cents(730.0 * 0.1)));
+
+        table.instance_hourly.insert("m5.large".into(), 0.2);
+        assert!(parse_pricing(&serde_json::to_string(&table).unwrap()).is_err());
+
+        table.instance_hourly.remove("m5.large");
+        table.component_types.get_mut(&ComponentType::Service).unwrap().monthly_base = -5.0;
+        assert!(parse_pricing(&serde_json::to_string(&table).unwrap()).is_err());
+        let service = table.component_types.get_mut(&ComponentType::Service).unwrap();
+        service.monthly_base = 0.0;
+        service.storage_gb_month = Some(-0.1);
+        assert!(parse_pricing(&serde_json::to_string(&table).unwrap()).is_err());
+    }
+}
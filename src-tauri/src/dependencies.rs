@@ -0,0 +1,210 @@
+// Component dependency integrity
+//
+// `Component.dependencies` holds ids of other components in the same project.
+// Older data referenced components by name; `migrate_dependencies` rewrites those
+// references to ids when a project is loaded.
+
+use crate::{ApiError, Component, Project};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Trims and de-duplicates requested dependencies, rejecting self references and unknown ids
+pub fn validate_dependencies(
+    project: &Project,
+    component_id: &str,
+    dependencies: Vec<String>,
+) -> Result<Vec<String>, ApiError> {
+    let known: HashSet<&str> = project.components.iter().map(|c| c.id.as_str()).collect();
+
+    let mut seen = HashSet::new();
+    let mut validated = Vec::new();
+    let mut unknown_ids = Vec::new();
+    for dependency in dependencies {
+        let dependency = dependency.trim().to_string();
+        if dependency.is_empty() || !seen.insert(dependency.clone()) {
+            continue;
+        }
+        if dependency == component_id {
+            return Err(ApiError::InvalidComponentData {
+                details: format!("Component {} cannot depend on itself", component_id),
+                source: None,
+            });
+        }
+        if known.contains(dependency.as_str()) {
+            validated.push(dependency);
+        } else {
+            unknown_ids.push(dependency);
+        }
+    }
+
+    if !unknown_ids.is_empty() {
+        return Err(ApiError::UnknownDependency {
+            component_id: component_id.to_string(),
+            unknown_ids,
+            source: None,
+        });
+    }
+    Ok(validated)
+}
+
+/// Drops references to a removed component and returns the components that changed
+pub fn remove_dependency_references(project: &mut Project, removed_id: &str) -> Vec<Component> {
+    let mut changed = Vec::new();
+    for component in project.components.iter_mut() {
+        let before = component.dependencies.len();
+        component.dependencies.retain(|d| d != removed_id);
+        if component.dependencies.len() < before {
+            component.revision += 1;
+            changed.push(component.clone());
+        }
+    }
+    changed
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DroppedDependency {
+    pub component_id: String,
+    pub reference: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DependencyMigration {
+    /// Name references rewritten to component ids
+    pub rewritten: usize,
+    /// References that matched no component, or more than one by name
+    pub dropped: Vec<DroppedDependency>,
+}
+
+impl DependencyMigration {
+    pub fn is_empty(&self) -> bool {
+        self.rewritten == 0 && self.dropped.is_empty()
+    }
+}
+
+/// Rewrites name-based dependencies to ids and drops references that cannot be resolved
+pub fn migrate_dependencies(project: &mut Project) -> DependencyMigration {
+    let ids: HashSet<String> = project.components.iter().map(|c| c.id.clone()).collect();
+    let mut by_name: HashMap<String, Vec<String>> = HashMap::new();
+    for component in &project.components {
+        by_name
+            .entry(component.name.trim().to_lowercase())
+            .or_default()
+            .push(component.id.clone());
+    }
+
+    let mut migration = DependencyMigration::default();
+    for component in project.components.iter_mut() {
+        let mut resolved: Vec<String> = Vec::new();
+        for reference in &component.dependencies {
+            let target = if ids.contains(reference) {
+                Some(reference.clone())
+            } else {
+                match by_name.get(&reference.trim().to_lowercase()).map(Vec::as_slice) {
+                    Some([id]) => {
+                        migration.rewritten += 1;
+                        Some(id.clone())
+                    }
+                    _ => None,
+                }
+            };
+            match target {
+                Some(id) if id != component.id => {
+                    if !resolved.contains(&id) {
+                        resolved.push(id);
+                    }
+                }
+                _ => migration.dropped.push(DroppedDependency {
+                    component_id: component.id.clone(),
+                    reference: reference.clone(),
+                }),
+            }
+        }
+        component.dependencies = resolved;
+    }
+
+    for dropped in &migration.dropped {
+        log::warn!(
+            "Dropped unresolved dependency '{}' of component {} in project {}",
+            dropped.reference,
+            dropped.component_id,
+            project.id
+        );
+    }
+    migration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{component_with, project_with};
+    use crate::ComponentType;
+
+    // Names matter here: older data referenced components by them
+    fn named(id: &str, name: &str, dependencies: &[&str]) -> Component {
+        Component {
+            name: name.into(),
+            ..component_with(id, ComponentType::Service, dependencies, &[])
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_and_self_dependencies() {
+        let project = project_with(vec![named("ui", "UI", &[]), named("api", "API", &[])]);
+
+        let ok = validate_dependencies(&project, "ui", vec!["api".into(), " api ".into()]).unwrap();
+        assert_eq!(ok, ["api"]);
+
+        match validate_dependencies(&project, "ui", vec!["api".into(), "API Gateway".into()]) {
+            Err(ApiError::UnknownDependency { component_id, unknown_ids, .. }) => {
+                assert_eq!(component_id, "ui");
+                assert_eq!(unknown_ids, ["API Gateway"]);
+            }
+            other => panic!("Expected UnknownDependency, got {:?}", other),
+        }
+        assert!(matches!(
+            validate_dependencies(&project, "ui", vec!["ui".into()]),
+            Err(ApiError::InvalidComponentData { .. })
+        ));
+    }
+
+    #[test]
+    fn removal_cleans_up_dangling_references() {
+        let mut project = project_with(vec![
+            named("ui", "UI", &["api"]),
+            named("api", "API", &["db"]),
+            named("worker", "Worker", &["db", "api"]),
+        ]);
+        project.components.retain(|c| c.id != "db");
+
+        let changed = remove_dependency_references(&mut project, "db");
+        assert_eq!(changed.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), ["api", "worker"]);
+        assert!(project.components[1].dependencies.is_empty());
+        assert_eq!(project.components[2].dependencies, ["api"]);
+        assert_eq!(project.components[2].revision, 2);
+        assert_eq!(project.components[0].revision, 1);
+    }
+
+    #[test]
+    fn migrates_name_references_to_ids() {
+        let mut project = project_with(vec![
+            named("ui", "User Interface", &["api gateway", "api"]),
+            named("api", "API Gateway", &["Database", "User Service"]),
+            named("db", "Database", &[]),
+        ]);
+
+        let migration = migrate_dependencies(&mut project);
+        assert_eq!(migration.rewritten, 2);
+        assert_eq!(
+            migration.dropped,
+            [DroppedDependency {
+                component_id: "api".into(),
+                reference: "User Service".into(),
+            }]
+        );
+        assert_eq!(project.components[0].dependencies, ["api"]);
+        assert_eq!(project.components[1].dependencies, ["db"]);
+
+        // Already-migrated data is left alone
+        assert!(migrate_dependencies(&mut project).is_empty());
+    }
+}
@@ -0,0 +1,332 @@
+// Dependency graph analysis over `Component.dependencies`
+//
+// Edges point from a component to the components it depends on. Strongly connected
+// components are found with Tarjan's algorithm, which emits each SCC only after every
+// SCC it depends on, so layering can be computed in a single pass over its output.
+
+use crate::events::{Change, ChangeFeed};
+use crate::state::ProjectStore;
+use crate::{ApiError, Project};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use tauri::{AppHandle, State};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyAnalysis {
+    pub project_id: String,
+    pub is_acyclic: bool,
+    /// Strongly connected components with more than one member, i.e. circular dependencies
+    pub cycles: Vec<Vec<String>>,
+    /// Build/deploy order: layer 0 has no dependencies, each later layer depends only on
+    /// earlier ones. Members of a cycle share a layer.
+    pub layers: Vec<Vec<String>>,
+}
+
+pub struct DependencyGraph<'a> {
+    ids: Vec<&'a str>,
+    edges: Vec<Vec<usize>>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    /// References to unknown components are ignored; `dependencies::validate_dependencies` guards writes
+    pub fn new(project: &'a Project) -> Self {
+        let index: HashMap<&str, usize> = project
+            .components
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.as_str(), i))
+            .collect();
+        let edges = project
+            .components
+            .iter()
+            .map(|c| {
+                let mut targets: Vec<usize> = c.dependencies.iter().filter_map(|d| index.get(d.as_str()).copied()).collect();
+                targets.sort_unstable();
+                targets.dedup();
+                targets
+            })
+            .collect();
+        Self {
+            ids: project.components.iter().map(|c| c.id.as_str()).collect(),
+            edges,
+        }
+    }
+
+    /// Strongly connected components, dependencies before their dependents. Iterative, so a
+    /// long dependency chain can't overflow the stack
+    fn tarjan(&self) -> Vec<Vec<usize>> {
+        let n = self.ids.len();
+        let mut next_index = 0;
+        let mut index: Vec<Option<usize>> = vec![None; n];
+        let mut low = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut sccs = Vec::new();
+
+        for root in 0..n {
+            if index[root].is_some() {
+                continue;
+            }
+            // Each frame is a node and the next of its edges to follow
+            let mut calls = vec![(root, 0)];
+            while let Some((v, next)) = calls.pop() {
+                if next == 0 {
+                    index[v] = Some(next_index);
+                    low[v] = next_index;
+                    next_index += 1;
+                    stack.push(v);
+                    on_stack[v] = true;
+                }
+                if let Some(&w) = self.edges[v].get(next) {
+                    calls.push((v, next + 1));
+                    match index[w] {
+                        None => calls.push((w, 0)),
+                        Some(w_index) if on_stack[w] => low[v] = low[v].min(w_index),
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+
+                if Some(low[v]) == index[v] {
+                    let mut scc = Vec::new();
+                    while let Some(w) = stack.pop() {
+                        on_stack[w] = false;
+                        scc.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    scc.sort_unstable();
+                    sccs.push(scc);
+                }
+                // Back in the caller, which takes the lowest index its child reached
+                if let Some(&(parent, _)) = calls.last() {
+                    low[parent] = low[parent].min(low[v]);
+                }
+            }
+        }
+        sccs
+    }
+
+    fn is_cyclic(&self, scc: &[usize]) -> bool {
+        scc.len() > 1 || self.edges[scc[0]].contains(&scc[0])
+    }
+
+    pub fn analyze(&self, project_id: &str) -> DependencyAnalysis {
+        let sccs = self.tarjan();
+        let mut scc_of = vec![0; self.ids.len()];
+        for (i, scc) in sccs.iter().enumerate() {
+            for &v in scc {
+                scc_of[v] = i;
+            }
+        }
+
+        // Every SCC a given one depends on was emitted before it
+        let mut scc_layer = vec![0usize; sccs.len()];
+        for (i, scc) in sccs.iter().enumerate() {
+            scc_layer[i] = scc
+                .iter()
+                .flat_map(|&v| self.edges[v].iter())
+                .map(|&w| scc_of[w])
+                .filter(|&j| j != i)
+                .map(|j| scc_layer[j] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+
+        let layer_count = scc_layer.iter().max().map_or(0, |&m| m + 1);
+        let mut layers: Vec<Vec<usize>> = vec![Vec::new(); layer_count];
+        for (v, &scc) in scc_of.iter().enumerate() {
+            layers[scc_layer[scc]].push(v);
+        }
+
+        let mut cycles: Vec<Vec<String>> = sccs
+            .iter()
+            .filter(|scc| self.is_cyclic(scc))
+            .map(|scc| self.names(scc))
+            .collect();
+        cycles.sort();
+
+        DependencyAnalysis {
+            project_id: project_id.to_string(),
+            is_acyclic: cycles.is_empty(),
+            cycles,
+            layers: layers.iter().map(|layer| self.names(layer)).collect(),
+        }
+    }
+
+    /// A cycle closed by making `component_id` depend on `dependency_id`, as a path
+    /// starting and ending at `component_id`
+    pub fn cycle_through(&self, component_id: &str, dependency_id: &str) -> Option<Vec<String>> {
+        let start = self.ids.iter().position(|id| *id == component_id)?;
+        let target = self.ids.iter().position(|id| *id == dependency_id)?;
+
+        // Breadth-first from the new dependency back to the component gives the shortest cycle
+        let mut previous: Vec<Option<usize>> = vec![None; self.ids.len()];
+        let mut visited = vec![false; self.ids.len()];
+        let mut queue = VecDeque::from([target]);
+        visited[target] = true;
+        while let Some(v) = queue.pop_front() {
+            if v == start {
+                let mut path = vec![start];
+                let mut current = v;
+                while let Some(p) = previous[current] {
+                    path.push(p);
+                    current = p;
+                }
+                path.reverse();
+                path.insert(0, start);
+                return Some(self.names(&path));
+            }
+            for &w in &self.edges[v] {
+                if !visited[w] {
+                    visited[w] = true;
+                    previous[w] = Some(v);
+                    queue.push_back(w);
+                }
+            }
+        }
+        None
+    }
+
+    fn names(&self, nodes: &[usize]) -> Vec<String> {
+        nodes.iter().map(|&v| self.ids[v].to_string()).collect()
+    }
+}
+
+/// First cycle that replacing `component_id`'s dependencies with `new_dependencies` would
+/// introduce. Dependencies the component already had are not re-reported.
+pub fn find_new_cycle(project: &Project, component_id: &str, new_dependencies: &[String]) -> Option<Vec<String>> {
+    let existing = &project.components.iter().find(|c| c.id == component_id)?.dependencies;
+    let graph = DependencyGraph::new(project);
+    new_dependencies
+        .iter()
+        .filter(|d| !existing.contains(d))
+        .find_map(|d| graph.cycle_through(component_id, d))
+}
+
+/// Under `CyclePolicy::Warn` the update goes through and open windows are told about the cycle
+pub fn warn_cycle(
+    app: &AppHandle,
+    changes: &ChangeFeed,
+    project_id: &str,
+    component_id: &str,
+    cycle: Vec<String>,
+) -> Result<(), ApiError> {
+    log::warn!("Dependency cycle introduced in project {}: {}", project_id, cycle.join(" -> "));
+    changes.publish(app, Change::DependencyCycleWarning {
+        project_id: project_id.to_string(),
+        component_id: component_id.to_string(),
+        cycle,
+    })?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn analyze_dependencies(
+    project_id: String,
+    projects: State<'_, ProjectStore>,
+) -> Result<DependencyAnalysis, ApiError> {
+    let analysis = projects
+        .read(&project_id, |state| DependencyGraph::new(&state.project).analyze(&project_id))?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })?;
+    log::debug!(
+        "Dependency analysis for {}: {} layers, {} cycles",
+        project_id,
+        analysis.layers.len(),
+        analysis.cycles.len()
+    );
+    Ok(analysis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{component, component_with, project_with};
+    use crate::{Component, ComponentType};
+
+    fn graph(edges: &[(&str, &[&str])]) -> Project {
+        project_with(
+            edges
+                .iter()
+                .map(|(id, deps)| component_with(id, ComponentType::Service, deps, &[]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn layers_acyclic_graph_in_build_order() {
+        let project = graph(&[
+            ("ui", &["api"]),
+            ("api", &["db", "auth"]),
+            ("auth", &["db"]),
+            ("db", &[]),
+            ("docs", &[]),
+        ]);
+        let analysis = DependencyGraph::new(&project).analyze("p");
+        assert!(analysis.is_acyclic);
+        assert_eq!(
+            analysis.layers,
+            vec![vec!["db", "docs"], vec!["auth"], vec!["api"], vec!["ui"]]
+        );
+    }
+
+    #[test]
+    fn reports_cycles_and_keeps_them_in_one_layer() {
+        let project = graph(&[
+            ("a", &["b"]),
+            ("b", &["c"]),
+            ("c", &["a", "db"]),
+            ("db", &[]),
+            ("ui", &["a"]),
+        ]);
+        let analysis = DependencyGraph::new(&project).analyze("p");
+        assert!(!analysis.is_acyclic);
+        assert_eq!(analysis.cycles, vec![vec!["a", "b", "c"]]);
+        assert_eq!(analysis.layers, vec![vec!["db"], vec!["a", "b", "c"], vec!["ui"]]);
+    }
+
+    #[test]
+    fn finds_cycle_closed_by_new_dependency() {
+        let project = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &[]), ("d", &[])]);
+        let graph = DependencyGraph::new(&project);
+        assert_eq!(graph.cycle_through("c", "a").unwrap(), ["c", "a", "b", "c"]);
+        assert!(graph.cycle_through("c", "d").is_none());
+        assert!(graph.cycle_through("a", "c").is_none());
+
+        // Only newly added edges are checked
+        assert_eq!(
+            find_new_cycle(&project, "c", &["d".into(), "a".into()]).unwrap(),
+            ["c", "a", "b", "c"]
+        );
+        assert!(find_new_cycle(&project, "a", &["b".into(), "d".into()]).is_none());
+    }
+
+    #[test]
+    fn analyzes_long_chains_without_recursing() {
+        let ids: Vec<String> = (0..100_000).map(|i| format!("c{}", i)).collect();
+        let mut project = project_with(
+            ids.iter()
+                .enumerate()
+                .map(|(i, id)| Component {
+                    dependencies: ids.get(i + 1).into_iter().cloned().collect(),
+                    ..component(id)
+                })
+                .collect(),
+        );
+        // Closing the chain makes the whole thing one cycle
+        project.components[99_999].dependencies = vec!["c0".into()];
+        let analysis = DependencyGraph::new(&project).analyze("p");
+        assert_eq!(analysis.cycles.len(), 1);
+        assert_eq!(analysis.cycles[0].len(), 100_000);
+
+        project.components[99_999].dependencies.clear();
+        let analysis = DependencyGraph::new(&project).analyze("p");
+        assert!(analysis.is_acyclic);
+        assert_eq!(analysis.layers.len(), 100_000);
+        assert_eq!(analysis.layers[0], ["c99999"]);
+    }
+}
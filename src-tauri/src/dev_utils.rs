@@ -1,7 +1,7 @@
 // Development utilities for testing and debugging
 #![allow(dead_code)]
 
-use crate::{Project, Component, ComponentType, ComponentStatus, ProjectStatus};
+use crate::{Project, Component, ComponentType, ComponentStatus, ProjectSettings, ProjectStatus};
 use chrono::Utc;
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -12,42 +12,58 @@ pub fn create_sample_project() -> Project {
     metadata.insert("framework".to_string(), "React".to_string());
     metadata.insert("language".to_string(), "TypeScript".to_string());
 
+    // Dependencies reference component ids, so they are generated up front
+    let api_gateway_id = Uuid::new_v4().to_string();
+    let database_id = Uuid::new_v4().to_string();
+
     let components = vec![
         Component {
             id: Uuid::new_v4().to_string(),
             name: "User Interface".to_string(),
             component_type: ComponentType::Frontend,
             description: "React-based user interface with Tailwind CSS styling".to_string(),
-            dependencies: vec!["API Gateway".to_string()],
+            dependencies: vec![api_gateway_id.clone()],
             status: ComponentStatus::InProgress,
             metadata: metadata.clone(),
+            revision: 1,
+            capacity: None,
+            availability: None,
         },
         Component {
-            id: Uuid::new_v4().to_string(),
+            id: api_gateway_id,
             name: "API Gateway".to_string(),
             component_type: ComponentType::Api,
             description: "RESTful API for handling client requests".to_string(),
-            dependencies: vec!["Database".to_string()],
+            dependencies: vec![database_id.clone()],
             status: ComponentStatus::Done,
             metadata: HashMap::new(),
+            revision: 1,
+            capacity: None,
+            availability: None,
         },
         Component {
-            id: Uuid::new_v4().to_string(),
+            id: database_id.clone(),
             name: "Database".to_string(),
             component_type: ComponentType::Database,
             description: "PostgreSQL database for data persistence".to_string(),
             dependencies: vec![],
             status: ComponentStatus::Done,
             metadata: HashMap::new(),
+            revision: 1,
+            capacity: None,
+            availability: None,
         },
         Component {
             id: Uuid::new_v4().to_string(),
             name: "Authentication Service".to_string(),
             component_type: ComponentType::Service,
             description: "JWT-based authentication and authorization service".to_string(),
-            dependencies: vec!["Database".to_string()],
+            dependencies: vec![database_id],
             status: ComponentStatus::Testing,
             metadata: HashMap::new(),
+            revision: 1,
+            capacity: None,
+            availability: None,
         },
     ];
 
@@ -59,11 +75,16 @@ pub fn create_sample_project() -> Project {
         updated_at: Utc::now(),
         status: ProjectStatus::InProgress,
         components,
+        tags: vec!["sample".to_string()],
+        revision: 1,
+        settings: ProjectSettings::default(),
     }
 }
 
 /// Create multiple sample projects for testing
 pub fn create_sample_projects() -> Vec<Project> {
+    let user_service_id = Uuid::new_v4().to_string();
+
     vec![
         create_sample_project(),
         Project {
@@ -82,17 +103,38 @@ pub fn create_sample_projects() -> Vec<Project> {
                     dependencies: vec![],
                     status: ComponentStatus::NotStarted,
                     metadata: HashMap::new(),
+                    revision: 1,
+                    capacity: None,
+                    availability: None,
+                },
+                Component {
+                    id: user_service_id.clone(),
+                    name: "User Service".to_string(),
+                    component_type: ComponentType::Service,
+                    description: "Customer accounts and profiles".to_string(),
+                    dependencies: vec![],
+                    status: ComponentStatus::NotStarted,
+                    metadata: HashMap::new(),
+                    revision: 1,
+                    capacity: None,
+                    availability: None,
                 },
                 Component {
                     id: Uuid::new_v4().to_string(),
                     name: "Payment Gateway".to_string(),
                     component_type: ComponentType::Integration,
                     description: "Integration with external payment providers".to_string(),
-                    dependencies: vec!["User Service".to_string()],
+                    dependencies: vec![user_service_id],
                     status: ComponentStatus::NotStarted,
                     metadata: HashMap::new(),
+                    revision: 1,
+                    capacity: None,
+                    availability: None,
                 },
             ],
+            tags: vec!["sample".to_string(), "microservices".to_string()],
+            revision: 1,
+            settings: ProjectSettings::default(),
         },
     ]
 }
@@ -116,4 +158,20 @@ mod tests {
         assert_eq!(projects.len(), 2);
         assert!(projects.iter().all(|p| !p.id.is_empty()));
     }
+
+    #[test]
+    fn test_sample_dependencies_reference_component_ids() {
+        for project in create_sample_projects() {
+            for component in &project.components {
+                for dependency in &component.dependencies {
+                    assert!(
+                        project.components.iter().any(|c| &c.id == dependency),
+                        "{} depends on unknown id {}",
+                        component.name,
+                        dependency
+                    );
+                }
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,758 @@
+// Incremental diagram edits
+//
+// A patch is a batch of element and connection operations applied in order to a copy of the
+// diagram; the copy replaces the real one only if every operation succeeds. Each applied patch
+// bumps the diagram revision and yields the inverse operations, so undo is just another patch.
+// Layout, arranging, routing and grouping all produce patches, so none of them needs an undo of
+// its own.
+
+use crate::containment;
+use crate::diagrams::{on_diagram, read_diagram, MAIN_DIAGRAM_ID};
+use crate::events::{Change, ChangeFeed, EventSink};
+use crate::latency::ConnectionLatency;
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::{check_revision, ApiError, Component, Connection, DiagramElement, Position};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, State};
+
+// Distinguishes a field set to null (clear it) from one that was left out (keep it)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DiagramOp {
+    AddElement {
+        element: DiagramElement,
+    },
+    /// Property values of `null` remove the property
+    UpdateElement {
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        element_type: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        width: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        height: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rotation: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        z_index: Option<i32>,
+        /// `null` takes the element out of its container
+        #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+        parent_id: Option<Option<String>>,
+        /// `null` unlinks the element from its component
+        #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+        component_id: Option<Option<String>>,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        properties: HashMap<String, Option<String>>,
+    },
+    MoveElement {
+        id: String,
+        position: Position,
+    },
+    /// Also deletes the element's connections
+    DeleteElement {
+        id: String,
+    },
+    AddConnection {
+        connection: Connection,
+    },
+    UpdateConnection {
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        connection_type: Option<String>,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        properties: HashMap<String, Option<String>>,
+        #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+        latency: Option<Option<ConnectionLatency>>,
+        /// An empty list draws the connection straight again
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        waypoints: Option<Vec<Position>>,
+    },
+    DeleteConnection {
+        id: String,
+    },
+}
+
+impl DiagramOp {
+    fn name(&self) -> &'static str {
+        match self {
+            DiagramOp::AddElement { .. } => "add_element",
+            DiagramOp::UpdateElement { .. } => "update_element",
+            DiagramOp::MoveElement { .. } => "move_element",
+            DiagramOp::DeleteElement { .. } => "delete_element",
+            DiagramOp::AddConnection { .. } => "add_connection",
+            DiagramOp::UpdateConnection { .. } => "update_connection",
+            DiagramOp::DeleteConnection { .. } => "delete_connection",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchResult {
+    pub diagram_revision: u64,
+    /// Applying these as a patch undoes this one
+    pub inverse: Vec<DiagramOp>,
+}
+
+/// Sent back with a revision conflict so the client can rebase its edits
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagramSnapshot {
+    pub diagram_revision: u64,
+    pub elements: Vec<DiagramElement>,
+    pub connections: Vec<Connection>,
+}
+
+fn apply_properties(
+    target: &mut HashMap<String, String>,
+    changes: HashMap<String, Option<String>>,
+) -> HashMap<String, Option<String>> {
+    let mut previous = HashMap::new();
+    for (key, value) in changes {
+        let old = match value {
+            Some(value) => target.insert(key.clone(), value),
+            None => target.remove(&key),
+        };
+        previous.insert(key, old);
+    }
+    previous
+}
+
+fn endpoint_exists(state: &ProjectState, id: &str) -> bool {
+    state.diagram.iter().any(|e| e.id == id) || state.project.components.iter().any(|c| c.id == id)
+}
+
+// A parent must be an existing element that isn't the child itself or inside it
+fn check_parent(state: &ProjectState, id: &str, parent: Option<&str>) -> Result<(), String> {
+    let Some(parent) = parent else {
+        return Ok(());
+    };
+    if !state.diagram.iter().any(|e| e.id == parent) {
+        return Err(format!("container {} of element {} not found", parent, id));
+    }
+    if parent == id || containment::ancestors(&state.diagram, parent).contains(&id) {
+        return Err(format!("element {} can't be placed inside itself", id));
+    }
+    Ok(())
+}
+
+fn check_size(id: &str, sizes: impl IntoIterator<Item = f64>) -> Result<(), String> {
+    if sizes.into_iter().any(|size| !size.is_finite() || size <= 0.0) {
+        return Err(format!("element {} needs a positive width and height", id));
+    }
+    Ok(())
+}
+
+/// Checks a diagram that replaces the current one wholesale: ids are unique, sizes are usable
+/// and every container exists without one ending up inside itself
+pub fn check_elements(elements: &[DiagramElement]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for element in elements {
+        if !ids.insert(element.id.as_str()) {
+            return Err(format!("id {} is used more than once", element.id));
+        }
+        check_size(&element.id, [element.width, element.height])?;
+    }
+    for element in elements {
+        let Some(parent) = element.parent_id.as_deref() else {
+            continue;
+        };
+        if !ids.contains(parent)
+            || containment::ancestors(elements, &element.id).len() != containment::ancestors(elements, parent).len() + 1
+        {
+            return Err(format!("element {} has a missing or circular container '{}'", element.id, parent));
+        }
+    }
+    Ok(())
+}
+
+/// Unique ids and two distinct ends, whatever those ends are
+pub fn check_connection_ids(connections: &[Connection]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for connection in connections {
+        if !ids.insert(connection.id.as_str()) {
+            return Err(format!("connection id {} is used more than once", connection.id));
+        }
+        if connection.source_id == connection.target_id {
+            return Err(format!("connection {} points back at its own source {}", connection.id, connection.source_id));
+        }
+    }
+    Ok(())
+}
+
+/// A whole set of connections for one diagram: unique ids, distinct ends, and both ends of
+/// every added or changed connection on existing elements. One saved before can be left
+/// pointing at a removed component, which the linter reports
+pub fn check_connections(state: &ProjectState, connections: &[Connection]) -> Result<(), String> {
+    check_connection_ids(connections)?;
+    for connection in connections.iter().filter(|c| !state.connections.contains(c)) {
+        for endpoint in [&connection.source_id, &connection.target_id] {
+            if !endpoint_exists(state, endpoint) {
+                return Err(format!("connection {} points at unknown element {}", connection.id, endpoint));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes connections left pointing at elements that are gone, as deleting an element does.
+/// Returns whether any were removed
+pub fn drop_dangling_connections(state: &mut ProjectState) -> bool {
+    let before = state.connections.len();
+    let connections = std::mem::take(&mut state.connections);
+    state.connections = connections
+        .into_iter()
+        .filter(|c| endpoint_exists(state, &c.source_id) && endpoint_exists(state, &c.target_id))
+        .collect();
+    state.connections.len() != before
+}
+
+fn check_component(components: &[Component], id: &str, component_id: Option<&str>) -> Result<(), String> {
+    match component_id {
+        Some(component_id) if !components.iter().any(|c| c.id == component_id) => {
+            Err(format!("component {} of element {} not found", component_id, id))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Every element linked to a component must link to one that exists. Only checked for links
+/// that are new since `saved`: an element can outlive its component, and reconciling reports it
+pub fn check_component_links(
+    elements: &[DiagramElement],
+    saved: &[DiagramElement],
+    components: &[Component],
+) -> Result<(), String> {
+    elements
+        .iter()
+        .filter(|e| !saved.iter().any(|s| s.id == e.id && s.component_id == e.component_id))
+        .try_for_each(|e| check_component(components, &e.id, e.component_id.as_deref()))
+}
+
+/// Applies one operation and returns the operations that undo it, in the order to apply them
+fn apply_op(state: &mut ProjectState, op: DiagramOp) -> Result<Vec<DiagramOp>, String> {
+    let element_index = |state: &ProjectState, id: &str| {
+        state.diagram.iter().position(|e| e.id == id).ok_or(format!("element {} not found", id))
+    };
+    let connection_index = |state: &ProjectState, id: &str| {
+        state.connections.iter().position(|c| c.id == id).ok_or(format!("connection {} not found", id))
+    };
+
+    match op {
+        DiagramOp::AddElement { element } => {
+            if endpoint_exists(state, &element.id) {
+                return Err(format!("id {} is already in use", element.id));
+            }
+            check_size(&element.id, [element.width, element.height])?;
+            check_component(&state.project.components, &element.id, element.component_id.as_deref())?;
+            check_parent(state, &element.id, element.parent_id.as_deref())?;
+            let id = element.id.clone();
+            state.diagram.push(element);
+            Ok(vec![DiagramOp::DeleteElement { id }])
+        }
+        DiagramOp::UpdateElement {
+            id,
+            element_type,
+            width,
+            height,
+            rotation,
+            z_index,
+            parent_id,
+            component_id,
+            properties,
+        } => {
+            check_size(&id, [width, height].into_iter().flatten())?;
+            if rotation.is_some_and(|r| !r.is_finite()) {
+                return Err(format!("element {} has an invalid rotation", id));
+            }
+            let index = element_index(state, &id)?;
+            if let Some(parent) = &parent_id {
+                check_parent(state, &id, parent.as_deref())?;
+            }
+            if let Some(component) = &component_id {
+                check_component(&state.project.components, &id, component.as_deref())?;
+            }
+            let element = &mut state.diagram[index];
+            Ok(vec![DiagramOp::UpdateElement {
+                element_type: element_type.map(|t| std::mem::replace(&mut element.element_type, t)),
+                width: width.map(|w| std::mem::replace(&mut element.width, w)),
+                height: height.map(|h| std::mem::replace(&mut element.height, h)),
+                rotation: rotation.map(|r| std::mem::replace(&mut element.rotation, r)),
+                z_index: z_index.map(|z| std::mem::replace(&mut element.z_index, z)),
+                parent_id: parent_id.map(|p| std::mem::replace(&mut element.parent_id, p)),
+                component_id: component_id.map(|c| std::mem::replace(&mut element.component_id, c)),
+                properties: apply_properties(&mut element.properties, properties),
+                id,
+            }])
+        }
+        // Whatever is inside the element moves with it, and routes to any of them are dropped
+        // since their bends no longer line up
+        DiagramOp::MoveElement { id, position } => {
+            let index = element_index(state, &id)?;
+            let (dx, dy) = (position.x - state.diagram[index].position.x, position.y - state.diagram[index].position.y);
+            let children = containment::descendants(&state.diagram, &id);
+            for &child in &children {
+                state.diagram[child].position.x += dx;
+                state.diagram[child].position.y += dy;
+            }
+            let position = std::mem::replace(&mut state.diagram[index].position, position);
+            let mut inverse = vec![DiagramOp::MoveElement { id: id.clone(), position }];
+            if dx == 0.0 && dy == 0.0 {
+                return Ok(inverse);
+            }
+            let moved: Vec<&str> = children.iter().map(|&i| state.diagram[i].id.as_str()).chain([id.as_str()]).collect();
+            for connection in &mut state.connections {
+                let touches = moved.contains(&connection.source_id.as_str()) || moved.contains(&connection.target_id.as_str());
+                if touches && !connection.waypoints.is_empty() {
+                    inverse.push(DiagramOp::UpdateConnection {
+                        id: connection.id.clone(),
+                        connection_type: None,
+                        properties: HashMap::new(),
+                        latency: None,
+                        waypoints: Some(std::mem::take(&mut connection.waypoints)),
+                    });
+                }
+            }
+            Ok(inverse)
+        }
+        // Children of a deleted container move up to its parent
+        DiagramOp::DeleteElement { id } => {
+            let element = state.diagram.remove(element_index(state, &id)?);
+            let mut inverse = vec![DiagramOp::AddElement { element: element.clone() }];
+            for child in state.diagram.iter_mut().filter(|e| e.parent_id.as_deref() == Some(id.as_str())) {
+                child.parent_id = element.parent_id.clone();
+                inverse.push(DiagramOp::UpdateElement {
+                    id: child.id.clone(),
+                    element_type: None,
+                    width: None,
+                    height: None,
+                    rotation: None,
+                    z_index: None,
+                    parent_id: Some(Some(id.clone())),
+                    component_id: None,
+                    properties: HashMap::new(),
+                });
+            }
+            let (attached, kept) = std::mem::take(&mut state.connections)
+                .into_iter()
+                .partition(|c| c.source_id == id || c.target_id == id);
+            state.connections = kept;
+            inverse.extend(attached.into_iter().map(|connection| DiagramOp::AddConnection { connection }));
+            Ok(inverse)
+        }
+        DiagramOp::AddConnection { connection } => {
+            if state.connections.iter().any(|c| c.id == connection.id) {
+                return Err(format!("connection id {} is already in use", connection.id));
+            }
+            if connection.source_id == connection.target_id {
+                let (id, source) = (&connection.id, &connection.source_id);
+                return Err(format!("connection {} points back at its own source {}", id, source));
+            }
+            for endpoint in [&connection.source_id, &connection.target_id] {
+                if !endpoint_exists(state, endpoint) {
+                    return Err(format!("connection {} points at unknown element {}", connection.id, endpoint));
+                }
+            }
+            let id = connection.id.clone();
+            state.connections.push(connection);
+            Ok(vec![DiagramOp::DeleteConnection { id }])
+        }
+        DiagramOp::UpdateConnection {
+            id,
+            connection_type,
+            properties,
+            latency,
+            waypoints,
+        } => {
+            let index = connection_index(state, &id)?;
+            let connection = &mut state.connections[index];
+            let connection_type = connection_type.map(|t| std::mem::replace(&mut connection.connection_type, t));
+            let properties = apply_properties(&mut connection.properties, properties);
+            let latency = latency.map(|l| std::mem::replace(&mut connection.latency, l));
+            let waypoints = waypoints.map(|w| std::mem::replace(&mut connection.waypoints, w));
+            Ok(vec![DiagramOp::UpdateConnection {
+                id,
+                connection_type,
+                properties,
+                latency,
+                waypoints,
+            }])
+        }
+        DiagramOp::DeleteConnection { id } => {
+            let connection = state.connections.remove(connection_index(state, &id)?);
+            Ok(vec![DiagramOp::AddConnection { connection }])
+        }
+    }
+}
+
+/// All or nothing: on error `state` is left untouched. An empty patch changes nothing, not
+/// even the revision
+pub fn apply_patch(state: &mut ProjectState, ops: &[DiagramOp]) -> Result<Vec<DiagramOp>, ApiError> {
+    if ops.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Only the diagram is kept aside for a rollback; the rest of the project is never touched
+    let diagram = state.diagram.clone();
+    let connections = state.connections.clone();
+    let mut inverse = Vec::new();
+    for (index, op) in ops.iter().enumerate() {
+        let name = op.name();
+        match apply_op(state, op.clone()) {
+            Ok(undo) => inverse.push(undo),
+            Err(details) => {
+                state.diagram = diagram;
+                state.connections = connections;
+                return Err(ApiError::InvalidProjectData {
+                    details: format!("Patch operation {} ({}): {}", index, name, details),
+                    source: None,
+                });
+            }
+        }
+    }
+    state.diagram_revision += 1;
+    Ok(inverse.into_iter().rev().flatten().collect())
+}
+
+/// Rejects edits made against an older diagram, returning the current one to rebase on
+pub fn check_diagram_revision(
+    state: &ProjectState,
+    diagram_id: Option<&str>,
+    expected_revision: Option<u64>,
+) -> Result<(), ApiError> {
+    if expected_revision.is_some_and(|expected| expected != state.diagram_revision) {
+        let current = DiagramSnapshot {
+            diagram_revision: state.diagram_revision,
+            elements: state.diagram.clone(),
+            connections: state.connections.clone(),
+        };
+        let id = diagram_id.unwrap_or(MAIN_DIAGRAM_ID);
+        check_revision("diagram", id, expected_revision, state.diagram_revision, &current)?;
+    }
+    Ok(())
+}
+
+/// A copy of one diagram, for edits too slow to compute under the project lock. Apply the
+/// result with `check_diagram_revision` against the copy's revision, so nothing edited in the
+/// meantime is overwritten
+pub fn snapshot_diagram(
+    projects: &ProjectStore,
+    project_id: &str,
+    diagram_id: Option<&str>,
+    expected_revision: Option<u64>,
+) -> Result<ProjectState, ApiError> {
+    projects
+        .read(project_id, |state| {
+            read_diagram(state, diagram_id, |view| -> Result<ProjectState, ApiError> {
+                let mut snapshot = ProjectState::new(state.project.clone());
+                snapshot.diagram = view.elements.to_vec();
+                snapshot.connections = view.connections.to_vec();
+                snapshot.diagram_revision = view.diagram_revision;
+                check_diagram_revision(&snapshot, diagram_id, expected_revision)?;
+                Ok(snapshot)
+            })?
+        })?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.to_string(),
+            source: None,
+        })?
+}
+
+/// Takes back a patch that was never announced, revision included
+pub fn revert_patch(state: &mut ProjectState, inverse: &[DiagramOp], revision: u64) -> Result<(), ApiError> {
+    apply_patch(state, inverse)?;
+    state.diagram_revision = revision;
+    Ok(())
+}
+
+/// Applies a patch and announces it to every window; returns the inverse operations. Empty
+/// patches are not announced, and a patch that can't be announced is taken back
+pub fn commit_patch(
+    state: &mut ProjectState,
+    diagram_id: Option<&str>,
+    ops: Vec<DiagramOp>,
+    sink: &impl EventSink,
+    changes: &ChangeFeed,
+) -> Result<Vec<DiagramOp>, ApiError> {
+    if ops.is_empty() {
+        return Ok(Vec::new());
+    }
+    let revision = state.diagram_revision;
+    let inverse = apply_patch(state, &ops)?;
+    let change = Change::DiagramPatched {
+        project_id: state.project.id.clone(),
+        diagram_id: diagram_id.unwrap_or(MAIN_DIAGRAM_ID).to_string(),
+        diagram_revision: state.diagram_revision,
+        ops,
+    };
+    if let Err(e) = changes.publish(sink, change) {
+        revert_patch(state, &inverse, revision)?;
+        return Err(e);
+    }
+    Ok(inverse)
+}
+
+#[tauri::command]
+pub async fn patch_diagram(
+    project_id: String,
+    diagram_id: Option<String>,
+    ops: Vec<DiagramOp>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<PatchResult, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let diagram_id = diagram_id.as_deref();
+    on_diagram(&mut state, diagram_id, |state| {
+        check_diagram_revision(state, diagram_id, expected_revision)?;
+        let op_count = ops.len();
+        let inverse = commit_patch(state, diagram_id, ops, &app, &changes)?;
+        log::debug!(
+            "Applied {} diagram operations to project {} (diagram {}, revision {})",
+            op_count,
+            project_id,
+            diagram_id.unwrap_or(MAIN_DIAGRAM_ID),
+            state.diagram_revision
+        );
+        Ok(PatchResult {
+            diagram_revision: state.diagram_revision,
+            inverse,
+        })
+    })
+}
+
+#[tauri::command]
+pub async fn get_diagram_snapshot(
+    project_id: String,
+    diagram_id: Option<String>,
+    projects: State<'_, ProjectStore>,
+) -> Result<DiagramSnapshot, ApiError> {
+    projects
+        .read(&project_id, |state| {
+            read_diagram(state, diagram_id.as_deref(), |view| DiagramSnapshot {
+                diagram_revision: view.diagram_revision,
+                elements: view.elements.to_vec(),
+                connections: view.connections.to_vec(),
+            })
+        })?
+        .ok_or(ApiError::ProjectNotFound { project_id, source: None })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ChangeEvent;
+    use crate::test_support::{self, connection, diagram_state, element, strings};
+    use std::panic::AssertUnwindSafe;
+
+    fn state() -> ProjectState {
+        let labelled = |id: &str| DiagramElement {
+            properties: strings(&[("label", &id.to_uppercase())]),
+            ..element(id)
+        };
+        diagram_state(vec![labelled("a"), labelled("b")], vec![connection("ab", "a", "b")])
+    }
+
+    fn ids<T>(items: &[T], id: impl Fn(&T) -> &str) -> Vec<&str> {
+        items.iter().map(id).collect()
+    }
+
+    #[test]
+    fn applies_ops_and_inverse_restores() {
+        let mut state = state();
+        let ops: Vec<DiagramOp> = serde_json::from_value(serde_json::json!([
+            {"op": "add_element", "element": {"id": "c", "element_type": "database", "position": {"x": 5.0, "y": 5.0}, "properties": {}}},
+            {"op": "add_connection", "connection": {"id": "bc", "source_id": "b", "target_id": "c", "connection_type": "sql", "properties": {}}},
+            {"op": "move_element", "id": "a", "position": {"x": 10.0, "y": 20.0}},
+            {"op": "update_element", "id": "b", "properties": {"label": null, "team": "core"}},
+            {"op": "update_connection", "id": "ab", "latency": {"p50": "5ms"}},
+            {"op": "delete_element", "id": "a"}
+        ]))
+        .unwrap();
+
+        let inverse = apply_patch(&mut state, &ops).unwrap();
+        assert_eq!(state.diagram_revision, 1);
+        assert_eq!(ids(&state.diagram, |e| &e.id), ["b", "c"]);
+        assert_eq!(ids(&state.connections, |c| &c.id), ["bc"]);
+        assert_eq!(state.diagram[0].properties.get("team").map(String::as_str), Some("core"));
+        assert!(!state.diagram[0].properties.contains_key("label"));
+
+        apply_patch(&mut state, &inverse).unwrap();
+        let original = self::state();
+        assert_eq!(state.diagram_revision, 2);
+        assert_eq!(ids(&state.diagram, |e| &e.id), ["b", "a"]);
+        assert_eq!(ids(&state.connections, |c| &c.id), ["ab"]);
+        assert_eq!(state.connections[0], original.connections[0]);
+        let b = state.diagram.iter().find(|e| e.id == "b").unwrap();
+        assert_eq!(b.properties, original.diagram[1].properties);
+        let a = state.diagram.iter().find(|e| e.id == "a").unwrap();
+        assert_eq!((a.position.x, a.position.y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_whole_patch_on_bad_op() {
+        let mut state = state();
+        let ops = vec![
+            DiagramOp::MoveElement {
+                id: "a".into(),
+                position: Position { x: 1.0, y: 1.0 },
+            },
+            DiagramOp::AddConnection {
+                connection: connection("bz", "b", "zzz"),
+            },
+        ];
+        let error = apply_patch(&mut state, &ops).unwrap_err().to_string();
+        assert!(error.contains("Patch operation 1 (add_connection)"), "{}", error);
+        assert_eq!(state.diagram[0].position.x, 0.0);
+        assert_eq!(state.diagram_revision, 0);
+
+        let duplicate = vec![DiagramOp::AddElement { element: element("b") }];
+        assert!(apply_patch(&mut state, &duplicate).is_err());
+        let flat = vec![DiagramOp::AddElement {
+            element: DiagramElement { height: 0.0, ..element("c") },
+        }];
+        assert!(apply_patch(&mut state, &flat).is_err());
+        let missing = vec![DiagramOp::DeleteConnection { id: "nope".into() }];
+        assert!(apply_patch(&mut state, &missing).is_err());
+        let unknown = vec![DiagramOp::AddElement {
+            element: DiagramElement {
+                component_id: Some("ghost".into()),
+                ..element("c")
+            },
+        }];
+        assert!(apply_patch(&mut state, &unknown).is_err());
+        let link = vec![DiagramOp::UpdateElement {
+            id: "a".into(),
+            element_type: None,
+            width: None,
+            height: None,
+            rotation: None,
+            z_index: None,
+            parent_id: None,
+            component_id: Some(Some("ghost".into())),
+            properties: HashMap::new(),
+        }];
+        assert!(apply_patch(&mut state, &link).is_err());
+        assert_eq!(ids(&state.diagram, |e| &e.id), ["a", "b"]);
+        assert_eq!(ids(&state.connections, |c| &c.id), ["ab"]);
+
+        assert!(apply_patch(&mut state, &[]).unwrap().is_empty());
+        assert_eq!(state.diagram_revision, 0);
+    }
+
+    #[test]
+    fn checks_whole_diagrams() {
+        let nested = |id: &str, parent: &str| DiagramElement {
+            parent_id: Some(parent.into()),
+            ..element(id)
+        };
+        assert!(check_elements(&[element("a"), nested("b", "a"), nested("c", "b")]).is_ok());
+        let duplicate = check_elements(&[element("a"), element("a")]).unwrap_err();
+        assert!(duplicate.contains("more than once"), "{}", duplicate);
+        let circular = check_elements(&[nested("a", "b"), nested("b", "a")]).unwrap_err();
+        assert!(circular.contains("circular"), "{}", circular);
+        assert!(check_elements(&[nested("a", "gone")]).is_err());
+        assert!(check_elements(&[DiagramElement { width: f64::NAN, ..element("a") }]).is_err());
+
+        let linked = DiagramElement {
+            component_id: Some("c1".into()),
+            ..element("a")
+        };
+        let components = [test_support::component("c1")];
+        assert!(check_component_links(std::slice::from_ref(&linked), &[], &components).is_ok());
+        let error = check_component_links(std::slice::from_ref(&linked), &[], &[]).unwrap_err();
+        assert_eq!(error, "component c1 of element a not found");
+        // A link saved before its component went stays as it is
+        assert!(check_component_links(std::slice::from_ref(&linked), std::slice::from_ref(&linked), &[]).is_ok());
+        let relinked = DiagramElement {
+            component_id: Some("c2".into()),
+            ..linked.clone()
+        };
+        assert!(check_component_links(&[relinked], &[linked], &[]).is_err());
+    }
+
+    #[test]
+    fn revision_conflicts_name_the_diagram() {
+        let state = state();
+        assert!(check_diagram_revision(&state, None, Some(0)).is_ok());
+        for (diagram_id, expected) in [(None, MAIN_DIAGRAM_ID), (Some("v1"), "v1")] {
+            match check_diagram_revision(&state, diagram_id, Some(3)) {
+                Err(ApiError::RevisionConflict { entity_id, .. }) => assert_eq!(entity_id, expected),
+                other => panic!("Expected RevisionConflict, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn checks_whole_connection_sets() {
+        let state = state();
+        assert!(check_connections(&state, &[connection("ab", "a", "b"), connection("ba", "b", "a")]).is_ok());
+        let duplicate = check_connections(&state, &[connection("ab", "a", "b"), connection("ab", "b", "a")]).unwrap_err();
+        assert!(duplicate.contains("more than once"), "{}", duplicate);
+        assert!(check_connections(&state, &[connection("aa", "a", "a")]).is_err());
+        let dangling = check_connections(&state, &[connection("ax", "a", "x")]).unwrap_err();
+        assert_eq!(dangling, "connection ax points at unknown element x");
+
+        // Only what's added or changed has to point somewhere
+        let mut stale = state.clone();
+        stale.diagram.retain(|e| e.id != "b");
+        assert!(check_connections(&stale, &[connection("ab", "a", "b")]).is_ok());
+        assert!(check_connections(&stale, &[connection("ab", "b", "a")]).is_err());
+    }
+
+    #[test]
+    fn patches_refuse_what_whole_sets_refuse() {
+        let mut state = state();
+        let ops = vec![DiagramOp::AddConnection {
+            connection: connection("aa", "a", "a"),
+        }];
+        let error = apply_patch(&mut state, &ops).unwrap_err().to_string();
+        assert!(error.contains("connection aa points back at its own source a"), "{}", error);
+        assert_eq!(ids(&state.connections, |c| &c.id), ["ab"]);
+        assert!(check_connections(&state, &state.connections).is_ok());
+    }
+
+    #[test]
+    fn unannounced_patches_are_taken_back() {
+        struct Panicking;
+        impl EventSink for Panicking {
+            fn emit_change(&self, _: &ChangeEvent) {
+                panic!("window went away");
+            }
+        }
+        let changes = ChangeFeed::default();
+        let deleted = Change::ProjectDeleted { project_id: "p".into() };
+        let poisoned = std::panic::catch_unwind(AssertUnwindSafe(|| changes.publish(&Panicking, deleted)));
+        assert!(poisoned.is_err());
+
+        let mut state = state();
+        let ops = vec![
+            DiagramOp::MoveElement {
+                id: "a".into(),
+                position: Position { x: 5.0, y: 5.0 },
+            },
+            DiagramOp::DeleteElement { id: "b".into() },
+        ];
+        assert!(commit_patch(&mut state, None, ops, &Panicking, &changes).is_err());
+        assert_eq!(state.diagram, self::state().diagram);
+        assert_eq!(ids(&state.connections, |c| &c.id), ["ab"]);
+        assert_eq!(state.diagram_revision, 0);
+    }
+
+    #[test]
+    fn drops_connections_to_removed_elements() {
+        let mut state = state();
+        assert!(!drop_dangling_connections(&mut state));
+        state.diagram.retain(|e| e.id != "b");
+        assert!(drop_dangling_connections(&mut state));
+        assert!(state.connections.is_empty());
+    }
+}
@@ -0,0 +1,422 @@
+// Multiple named diagrams per project
+//
+// Every project has a main diagram, the one lint, capacity and the other analyses read, plus
+// any number of further views: a C4 context diagram, a container diagram per system, a
+// deployment picture. Each view has its own elements, connections and revision, and its
+// elements can stand for the same shared components as the main diagram's.
+//
+// Diagram editing code works on `state.diagram` and `state.connections`. To edit a view it is
+// swapped into those slots for the duration of the edit, under the project's write lock, so
+// patches, layout, routing and grouping all work on any diagram unchanged.
+
+use crate::containment;
+use crate::events::{Change, ChangeFeed};
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::{ApiError, Connection, DiagramElement};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+pub const MAIN_DIAGRAM_ID: &str = "main";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagramKind {
+    #[default]
+    Freeform,
+    /// C4 level 1
+    SystemContext,
+    /// C4 level 2
+    Container,
+    /// C4 level 3
+    Component,
+    /// C4 level 4
+    Code,
+    Deployment,
+}
+
+/// Name and kind of the main diagram, whose contents live directly on the project state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagramMeta {
+    pub name: String,
+    #[serde(default)]
+    pub kind: DiagramKind,
+}
+
+impl Default for DiagramMeta {
+    fn default() -> Self {
+        Self {
+            name: "Main".to_string(),
+            kind: DiagramKind::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagram {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub kind: DiagramKind,
+    #[serde(default)]
+    pub elements: Vec<DiagramElement>,
+    #[serde(default)]
+    pub connections: Vec<Connection>,
+    #[serde(default)]
+    pub diagram_revision: u64,
+}
+
+/// Every diagram of a project as stored in a bundle
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiagramSet {
+    pub main: DiagramMeta,
+    pub views: Vec<Diagram>,
+}
+
+/// One diagram's elements and connections, borrowed from the project state
+#[derive(Debug, Clone, Copy)]
+pub struct DiagramContents<'a> {
+    pub elements: &'a [DiagramElement],
+    pub connections: &'a [Connection],
+    pub diagram_revision: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiagramSummary {
+    pub id: String,
+    pub name: String,
+    pub kind: DiagramKind,
+    pub element_count: usize,
+    pub connection_count: usize,
+    pub diagram_revision: u64,
+}
+
+/// The main diagram's details plus every other diagram, ready to be written out; containers come
+/// before their contents so they can be read back in one pass
+pub fn diagram_set(state: &ProjectState) -> DiagramSet {
+    DiagramSet {
+        main: state.main_diagram.clone(),
+        views: state
+            .views
+            .iter()
+            .map(|view| Diagram {
+                elements: containment::parents_first(&view.elements).into_iter().cloned().collect(),
+                ..view.clone()
+            })
+            .collect(),
+    }
+}
+
+pub fn summaries(state: &ProjectState) -> Vec<DiagramSummary> {
+    let main = DiagramSummary {
+        id: MAIN_DIAGRAM_ID.to_string(),
+        name: state.main_diagram.name.clone(),
+        kind: state.main_diagram.kind,
+        element_count: state.diagram.len(),
+        connection_count: state.connections.len(),
+        diagram_revision: state.diagram_revision,
+    };
+    std::iter::once(main)
+        .chain(state.views.iter().map(|view| DiagramSummary {
+            id: view.id.clone(),
+            name: view.name.clone(),
+            kind: view.kind,
+            element_count: view.elements.len(),
+            connection_count: view.connections.len(),
+            diagram_revision: view.diagram_revision,
+        }))
+        .collect()
+}
+
+fn unknown_diagram(diagram_id: &str) -> ApiError {
+    ApiError::InvalidProjectData {
+        details: format!("Unknown diagram: {}", diagram_id),
+        source: None,
+    }
+}
+
+/// Index into `state.views`, or None for the main diagram
+fn view_index(state: &ProjectState, diagram_id: Option<&str>) -> Result<Option<usize>, ApiError> {
+    match diagram_id {
+        None | Some(MAIN_DIAGRAM_ID) => Ok(None),
+        Some(id) => state
+            .views
+            .iter()
+            .position(|v| v.id == id)
+            .map(Some)
+            .ok_or_else(|| unknown_diagram(id)),
+    }
+}
+
+fn swap_view(state: &mut ProjectState, index: usize) {
+    let view = &mut state.views[index];
+    std::mem::swap(&mut state.diagram, &mut view.elements);
+    std::mem::swap(&mut state.connections, &mut view.connections);
+    std::mem::swap(&mut state.diagram_revision, &mut view.diagram_revision);
+}
+
+/// Runs an edit against the chosen diagram (the main one when `diagram_id` is None)
+pub fn on_diagram<T>(
+    state: &mut ProjectState,
+    diagram_id: Option<&str>,
+    edit: impl FnOnce(&mut ProjectState) -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let Some(index) = view_index(state, diagram_id)? else {
+        return edit(state);
+    };
+    swap_view(state, index);
+    let result = edit(state);
+    swap_view(state, index);
+    result
+}
+
+/// Runs a read against the chosen diagram's contents, borrowed where they are
+pub fn read_diagram<T>(
+    state: &ProjectState,
+    diagram_id: Option<&str>,
+    read: impl FnOnce(DiagramContents) -> T,
+) -> Result<T, ApiError> {
+    let contents = match view_index(state, diagram_id)? {
+        None => DiagramContents {
+            elements: &state.diagram,
+            connections: &state.connections,
+            diagram_revision: state.diagram_revision,
+        },
+        Some(index) => {
+            let view = &state.views[index];
+            DiagramContents {
+                elements: &view.elements,
+                connections: &view.connections,
+                diagram_revision: view.diagram_revision,
+            }
+        }
+    };
+    Ok(read(contents))
+}
+
+fn check_name(state: &ProjectState, name: &str, except: Option<&str>) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::InvalidProjectData {
+            details: "Diagram name cannot be empty".to_string(),
+            source: None,
+        });
+    }
+    let taken = summaries(state)
+        .iter()
+        .any(|d| Some(d.id.as_str()) != except && d.name.eq_ignore_ascii_case(name));
+    if taken {
+        return Err(ApiError::InvalidProjectData {
+            details: format!("A diagram named '{}' already exists", name),
+            source: None,
+        });
+    }
+    Ok(name.to_string())
+}
+
+pub fn create(state: &mut ProjectState, name: &str, kind: DiagramKind) -> Result<DiagramSummary, ApiError> {
+    let name = check_name(state, name, None)?;
+    state.views.push(Diagram {
+        id: Uuid::new_v4().to_string(),
+        name,
+        kind,
+        elements: Vec::new(),
+        connections: Vec::new(),
+        diagram_revision: 0,
+    });
+    Ok(summaries(state).pop().expect("view was just added"))
+}
+
+pub fn rename(state: &mut ProjectState, diagram_id: &str, name: &str) -> Result<(), ApiError> {
+    let name = check_name(state, name, Some(diagram_id))?;
+    match view_index(state, Some(diagram_id))? {
+        None => state.main_diagram.name = name,
+        Some(index) => state.views[index].name = name,
+    }
+    Ok(())
+}
+
+/// Copies a diagram, main or not, into a new view
+pub fn duplicate(state: &mut ProjectState, diagram_id: &str, name: Option<&str>) -> Result<DiagramSummary, ApiError> {
+    let (source_name, kind, elements, connections) = match view_index(state, Some(diagram_id))? {
+        None => (
+            state.main_diagram.name.clone(),
+            state.main_diagram.kind,
+            state.diagram.clone(),
+            state.connections.clone(),
+        ),
+        Some(index) => {
+            let view = &state.views[index];
+            (view.name.clone(), view.kind, view.elements.clone(), view.connections.clone())
+        }
+    };
+    let name = check_name(state, name.unwrap_or(&format!("{} (copy)", source_name)), None)?;
+    state.views.push(Diagram {
+        id: Uuid::new_v4().to_string(),
+        name,
+        kind,
+        elements,
+        connections,
+        diagram_revision: 0,
+    });
+    Ok(summaries(state).pop().expect("view was just added"))
+}
+
+pub fn delete(state: &mut ProjectState, diagram_id: &str) -> Result<Diagram, ApiError> {
+    match view_index(state, Some(diagram_id))? {
+        None => Err(ApiError::InvalidProjectData {
+            details: "The main diagram can't be deleted".to_string(),
+            source: None,
+        }),
+        Some(index) => Ok(state.views.remove(index)),
+    }
+}
+
+// Applies a change to the project's diagram list and tells every window about it
+fn change_diagrams<T>(
+    project_id: &str,
+    app: &AppHandle,
+    projects: &ProjectStore,
+    changes: &ChangeFeed,
+    change: impl FnOnce(&mut ProjectState) -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let entry = projects.require(project_id)?;
+    let mut state = write_entry(&entry)?;
+    let result = change(&mut state)?;
+    changes.publish(app, Change::DiagramsChanged {
+        project_id: project_id.to_string(),
+        diagrams: summaries(&state),
+    })?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn list_diagrams(project_id: String, projects: State<'_, ProjectStore>) -> Result<Vec<DiagramSummary>, ApiError> {
+    projects
+        .read(&project_id, summaries)?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })
+}
+
+#[tauri::command]
+pub async fn create_diagram(
+    project_id: String,
+    name: String,
+    kind: Option<DiagramKind>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<DiagramSummary, ApiError> {
+    let summary = change_diagrams(&project_id, &app, &projects, &changes, |state| {
+        create(state, &name, kind.unwrap_or_default())
+    })?;
+    log::info!("Diagram {} ({}) created in project {}", summary.name, summary.id, project_id);
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn rename_diagram(
+    project_id: String,
+    diagram_id: String,
+    name: String,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<(), ApiError> {
+    change_diagrams(&project_id, &app, &projects, &changes, |state| rename(state, &diagram_id, &name))?;
+    log::info!("Diagram {} in project {} renamed to {}", diagram_id, project_id, name.trim());
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn duplicate_diagram(
+    project_id: String,
+    diagram_id: String,
+    name: Option<String>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<DiagramSummary, ApiError> {
+    let summary = change_diagrams(&project_id, &app, &projects, &changes, |state| {
+        duplicate(state, &diagram_id, name.as_deref())
+    })?;
+    log::info!("Diagram {} duplicated as {} in project {}", diagram_id, summary.id, project_id);
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn delete_diagram(
+    project_id: String,
+    diagram_id: String,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<(), ApiError> {
+    let deleted = change_diagrams(&project_id, &app, &projects, &changes, |state| delete(state, &diagram_id))?;
+    log::info!("Diagram {} ({}) deleted from project {}", deleted.name, diagram_id, project_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagram_patch::{apply_patch, DiagramOp};
+    use crate::test_support::{self, diagram_state};
+    use crate::Position;
+
+    fn state() -> ProjectState {
+        diagram_state(vec![test_support::element("api")], vec![])
+    }
+
+    fn names(state: &ProjectState) -> Vec<String> {
+        summaries(state).into_iter().map(|d| d.name).collect()
+    }
+
+    #[test]
+    fn creates_renames_and_deletes_views() {
+        let mut state = state();
+        let context = create(&mut state, "Context", DiagramKind::SystemContext).unwrap();
+        assert_eq!(context.kind, DiagramKind::SystemContext);
+        assert!(create(&mut state, " context ", DiagramKind::Freeform).is_err());
+        assert!(create(&mut state, "  ", DiagramKind::Freeform).is_err());
+
+        rename(&mut state, MAIN_DIAGRAM_ID, "Overview").unwrap();
+        rename(&mut state, &context.id, "System context").unwrap();
+        assert_eq!(names(&state), ["Overview", "System context"]);
+
+        assert!(delete(&mut state, MAIN_DIAGRAM_ID).is_err());
+        delete(&mut state, &context.id).unwrap();
+        assert!(delete(&mut state, &context.id).is_err());
+        assert_eq!(names(&state), ["Overview"]);
+    }
+
+    #[test]
+    fn duplicates_are_independent_copies() {
+        let mut state = state();
+        let copy = duplicate(&mut state, MAIN_DIAGRAM_ID, None).unwrap();
+        assert_eq!((copy.name.as_str(), copy.element_count), ("Main (copy)", 1));
+
+        let moved = [DiagramOp::MoveElement {
+            id: "api".into(),
+            position: Position { x: 50.0, y: 50.0 },
+        }];
+        on_diagram(&mut state, Some(&copy.id), |view| apply_patch(view, &moved)).unwrap();
+        assert_eq!(state.diagram[0].position.x, 0.0);
+        assert_eq!(state.diagram_revision, 0);
+        let view = read_diagram(&state, Some(&copy.id), |view| (view.elements[0].position.x, view.diagram_revision));
+        assert_eq!(view.unwrap(), (50.0, 1));
+    }
+
+    #[test]
+    fn failed_edits_leave_every_diagram_in_place() {
+        let mut state = state();
+        let view = create(&mut state, "Deployment", DiagramKind::Deployment).unwrap();
+        let delete_missing = [DiagramOp::DeleteElement { id: "api".into() }];
+        assert!(on_diagram(&mut state, Some(&view.id), |view| apply_patch(view, &delete_missing)).is_err());
+        assert_eq!(state.diagram.len(), 1);
+        assert!(state.views[0].elements.is_empty());
+        assert!(on_diagram(&mut state, Some("nope"), |_| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+// Change events broadcast to every window when project state mutates
+//
+// Every event carries a revision from a single monotonically increasing counter.
+// A window that sees a gap in revisions knows it missed something and can either
+// replay recent events via `get_changes_since` or reload from scratch.
+
+use crate::diagram_patch::DiagramOp;
+use crate::diagrams::DiagramSummary;
+use crate::{ApiError, Component, Connection, DiagramElement, Project, ProjectTranscript};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+// Enough history to cover a window that was briefly busy or hidden
+const HISTORY_LIMIT: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ComponentChange {
+    Added,
+    Updated,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum Change {
+    ProjectCreated {
+        project: Project,
+    },
+    ProjectUpdated {
+        project: Project,
+    },
+    ProjectDeleted {
+        project_id: String,
+    },
+    ComponentChanged {
+        project_id: String,
+        change: ComponentChange,
+        component_id: String,
+        /// Absent when the component was removed
+        component: Option<Component>,
+    },
+    DiagramSaved {
+        project_id: String,
+        diagram_id: String,
+        elements: Vec<DiagramElement>,
+    },
+    ConnectionsSaved {
+        project_id: String,
+        diagram_id: String,
+        connections: Vec<Connection>,
+    },
+    DiagramPatched {
+        project_id: String,
+        diagram_id: String,
+        diagram_revision: u64,
+        ops: Vec<DiagramOp>,
+    },
+    /// A diagram was created, renamed, duplicated or deleted
+    DiagramsChanged {
+        project_id: String,
+        diagrams: Vec<DiagramSummary>,
+    },
+    TranscriptSaved {
+        project_id: String,
+        transcript: ProjectTranscript,
+    },
+    /// A dependency update went through under `CyclePolicy::Warn` and closed this cycle
+    DependencyCycleWarning {
+        project_id: String,
+        component_id: String,
+        cycle: Vec<String>,
+    },
+}
+
+impl Change {
+    /// Tauri event name the change is emitted under
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            Change::ProjectCreated { .. } => "project-created",
+            Change::ProjectUpdated { .. } => "project-updated",
+            Change::ProjectDeleted { .. } => "project-deleted",
+            Change::ComponentChanged { .. } => "component-changed",
+            Change::DiagramSaved { .. } => "diagram-saved",
+            Change::ConnectionsSaved { .. } => "connections-saved",
+            Change::DiagramPatched { .. } => "diagram-patched",
+            Change::DiagramsChanged { .. } => "diagrams-changed",
+            Change::TranscriptSaved { .. } => "transcript-saved",
+            Change::DependencyCycleWarning { .. } => "dependency-cycle-warning",
+        }
+    }
+
+    pub fn project_id(&self) -> &str {
+        match self {
+            Change::ProjectCreated { project } | Change::ProjectUpdated { project } => &project.id,
+            Change::ProjectDeleted { project_id }
+            | Change::ComponentChanged { project_id, .. }
+            | Change::DiagramSaved { project_id, .. }
+            | Change::ConnectionsSaved { project_id, .. }
+            | Change::DiagramPatched { project_id, .. }
+            | Change::DiagramsChanged { project_id, .. }
+            | Change::TranscriptSaved { project_id, .. }
+            | Change::DependencyCycleWarning { project_id, .. } => project_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub revision: u64,
+    pub project_id: String,
+    pub emitted_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeBatch {
+    pub current_revision: u64,
+    /// False when older events were already dropped from history and a full reload is needed
+    pub complete: bool,
+    pub events: Vec<ChangeEvent>,
+}
+
+/// Destination for change events; the app handle in production, a recorder in tests
+pub trait EventSink {
+    fn emit_change(&self, event: &ChangeEvent);
+}
+
+impl EventSink for AppHandle {
+    fn emit_change(&self, event: &ChangeEvent) {
+        if let Err(e) = self.emit_all(event.change.event_name(), event.clone()) {
+            log::warn!("Failed to emit {} (revision {}): {}", event.change.event_name(), event.revision, e);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FeedState {
+    revision: u64,
+    history: VecDeque<ChangeEvent>,
+}
+
+#[derive(Debug, Default)]
+pub struct ChangeFeed {
+    state: Mutex<FeedState>,
+}
+
+impl ChangeFeed {
+    /// Assigns the next revision and emits the event. Revision assignment and emission
+    /// happen under one lock, so listeners always observe revisions in increasing order.
+    pub fn publish(&self, sink: &impl EventSink, change: Change) -> Result<u64, ApiError> {
+        let mut state = self.state.lock().map_err(|_| ApiError::StateLockError {
+            resource: "ChangeFeed".to_string(),
+            source: None,
+        })?;
+
+        state.revision += 1;
+        let event = ChangeEvent {
+            revision: state.revision,
+            project_id: change.project_id().to_string(),
+            emitted_at: Utc::now(),
+            change,
+        };
+        sink.emit_change(&event);
+
+        if state.history.len() == HISTORY_LIMIT {
+            state.history.pop_front();
+        }
+        let revision = event.revision;
+        state.history.push_back(event);
+        Ok(revision)
+    }
+
+    pub fn current_revision(&self) -> Result<u64, ApiError> {
+        let state = self.state.lock().map_err(|_| ApiError::StateLockError {
+            resource: "ChangeFeed".to_string(),
+            source: None,
+        })?;
+        Ok(state.revision)
+    }
+
+    pub fn changes_since(&self, revision: u64) -> Result<ChangeBatch, ApiError> {
+        let state = self.state.lock().map_err(|_| ApiError::StateLockError {
+            resource: "ChangeFeed".to_string(),
+            source: None,
+        })?;
+
+        let oldest_kept = state.history.front().map(|e| e.revision).unwrap_or(state.revision + 1);
+        Ok(ChangeBatch {
+            current_revision: state.revision,
+            complete: revision >= state.revision || revision + 1 >= oldest_kept,
+            events: state
+                .history
+                .iter()
+                .filter(|e| e.revision > revision)
+                .cloned()
+                .collect(),
+        })
+    }
+}
+
+/// Revision a window should remember after loading state, before listening for events
+#[tauri::command]
+pub async fn get_change_revision(changes: State<'_, ChangeFeed>) -> Result<u64, ApiError> {
+    changes.current_revision()
+}
+
+#[tauri::command]
+pub async fn get_changes_since(revision: u64, changes: State<'_, ChangeFeed>) -> Result<ChangeBatch, ApiError> {
+    let batch = changes.changes_since(revision)?;
+    log::debug!(
+        "Replaying {} changes after revision {} (current {})",
+        batch.events.len(),
+        revision,
+        batch.current_revision
+    );
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<(String, u64)>>,
+    }
+
+    impl EventSink for RecordingSink {
+        fn emit_change(&self, event: &ChangeEvent) {
+            self.events
+                .lock()
+                .unwrap()
+                .push((event.change.event_name().to_string(), event.revision));
+        }
+    }
+
+    fn deleted(id: &str) -> Change {
+        Change::ProjectDeleted { project_id: id.into() }
+    }
+
+    #[test]
+    fn emits_named_events_with_increasing_revisions() {
+        let feed = ChangeFeed::default();
+        let sink = RecordingSink::default();
+        feed.publish(&sink, deleted("a")).unwrap();
+        feed.publish(
+            &sink,
+            Change::DiagramSaved {
+                project_id: "a".into(),
+                diagram_id: "main".into(),
+                elements: vec![],
+            },
+        )
+        .unwrap();
+
+        let events = sink.events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![("project-deleted".to_string(), 1), ("diagram-saved".to_string(), 2)]
+        );
+
+        let payload = serde_json::to_value(feed.changes_since(1).unwrap().events[0].clone()).unwrap();
+        assert_eq!(payload["revision"], 2);
+        assert_eq!(payload["type"], "DiagramSaved");
+        assert_eq!(payload["project_id"], "a");
+    }
+
+    #[test]
+    fn replays_missed_changes_until_history_is_trimmed() {
+        let feed = ChangeFeed::default();
+        let sink = RecordingSink::default();
+        assert!(feed.changes_since(0).unwrap().complete);
+
+        for i in 0..(HISTORY_LIMIT + 10) {
+            feed.publish(&sink, deleted(&i.to_string())).unwrap();
+        }
+        let current = feed.current_revision().unwrap();
+        assert_eq!(current, (HISTORY_LIMIT + 10) as u64);
+
+        let recent = feed.changes_since(current - 3).unwrap();
+        assert!(recent.complete);
+        assert_eq!(recent.events.iter().map(|e| e.revision).collect::<Vec<_>>(), [current - 2, current - 1, current]);
+
+        let stale = feed.changes_since(2).unwrap();
+        assert!(!stale.complete);
+        assert_eq!(stale.events.len(), HISTORY_LIMIT);
+
+        let up_to_date = feed.changes_since(current).unwrap();
+        assert!(up_to_date.complete && up_to_date.events.is_empty());
+    }
+
+    #[test]
+    fn concurrent_publishers_emit_in_revision_order() {
+        let feed = Arc::new(ChangeFeed::default());
+        let sink = Arc::new(RecordingSink::default());
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let feed = feed.clone();
+                let sink = sink.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        feed.publish(&*sink, deleted(&format!("{}-{}", t, i))).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let revisions: Vec<u64> = sink.events.lock().unwrap().iter().map(|(_, r)| *r).collect();
+        assert_eq!(revisions, (1..=800).collect::<Vec<u64>>());
+    }
+}
@@ -0,0 +1,326 @@
+// What-if failure simulation
+//
+// A scenario marks nodes, connections and whole failure domains (every node whose metadata or
+// properties match `key=value`, e.g. `zone=a`) as failed. An entry point keeps working when it
+// is up and every group of things it relies on still has a working member, the same model the
+// availability analysis uses. Each failure is then replayed on its own to tell single points
+// of failure apart from outages that need several failures at once.
+
+use crate::availability::{entry_points, redundancy_groups, Member};
+use crate::lint::LintContext;
+use crate::state::{ProjectState, ProjectStore};
+use crate::system_graph::{self, SystemNode};
+use crate::ApiError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::State;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailureScenario {
+    /// Component or diagram element ids
+    pub nodes: Vec<String>,
+    pub connections: Vec<String>,
+    /// `key=value` selectors over component metadata and element properties
+    pub domains: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowImpact {
+    pub entry: SystemNode,
+    /// Nodes the entry could reach before the failure and no longer can
+    pub lost: Vec<SystemNode>,
+    /// Failed nodes or connections that break this entry on their own
+    pub single_points_of_failure: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureReport {
+    pub project_id: String,
+    pub failed_nodes: Vec<SystemNode>,
+    pub failed_connections: Vec<String>,
+    /// Entry points that stop working
+    pub degraded: Vec<FlowImpact>,
+    /// Entry points that still work but lost some redundancy
+    pub at_risk: Vec<FlowImpact>,
+    pub unaffected: Vec<SystemNode>,
+    /// Every failure that breaks at least one entry point by itself
+    pub single_points_of_failure: Vec<String>,
+}
+
+struct Simulation<'a> {
+    groups: HashMap<&'a str, Vec<Vec<Member<'a>>>>,
+}
+
+#[derive(Default, Clone)]
+struct Failed<'a> {
+    nodes: HashSet<&'a str>,
+    connections: HashSet<&'a str>,
+}
+
+impl<'a> Simulation<'a> {
+    fn link_up(&self, member: &Member, failed: &Failed) -> bool {
+        member.via_dependency || member.connection_ids.iter().any(|c| !failed.connections.contains(c))
+    }
+
+    fn works(
+        &self,
+        node: &'a str,
+        failed: &Failed<'a>,
+        memo: &mut HashMap<&'a str, bool>,
+        stack: &mut Vec<&'a str>,
+    ) -> bool {
+        if let Some(&known) = memo.get(node) {
+            return known;
+        }
+        // A cycle can't take itself down; whatever else the nodes rely on decides
+        if stack.contains(&node) {
+            return true;
+        }
+        if failed.nodes.contains(node) {
+            memo.insert(node, false);
+            return false;
+        }
+        stack.push(node);
+        let working = self.groups.get(node).is_none_or(|groups| {
+            groups.iter().all(|group| {
+                group
+                    .iter()
+                    .any(|m| self.link_up(m, failed) && self.works(m.to, failed, memo, stack))
+            })
+        });
+        stack.pop();
+        memo.insert(node, working);
+        working
+    }
+
+    fn entry_works(&self, entry: &'a str, failed: &Failed<'a>) -> bool {
+        self.works(entry, failed, &mut HashMap::new(), &mut Vec::new())
+    }
+
+    fn reachable(&self, entry: &'a str, failed: &Failed<'a>) -> HashSet<&'a str> {
+        let mut seen = HashSet::new();
+        let mut queue = vec![entry];
+        while let Some(node) = queue.pop() {
+            if failed.nodes.contains(node) || !seen.insert(node) {
+                continue;
+            }
+            for member in self.groups.get(node).into_iter().flatten().flatten() {
+                if self.link_up(member, failed) {
+                    queue.push(member.to);
+                }
+            }
+        }
+        seen
+    }
+}
+
+fn parse_domain(domain: &str) -> Result<(&str, &str), ApiError> {
+    domain
+        .split_once('=')
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or_else(|| ApiError::InvalidProjectData {
+            details: format!("Failure domain '{}' must look like key=value", domain),
+            source: None,
+        })
+}
+
+pub fn simulate(state: &ProjectState, scenario: &FailureScenario) -> Result<FailureReport, ApiError> {
+    let ctx = LintContext::new(state);
+    let graph = &ctx.graph;
+
+    let unknown: Vec<&str> = scenario
+        .nodes
+        .iter()
+        .filter(|id| graph.node(id).is_none())
+        .chain(scenario.connections.iter().filter(|id| system_graph::connection(state, id).is_none()))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(ApiError::InvalidProjectData {
+            details: format!("Unknown nodes or connections in failure scenario: {}", unknown.join(", ")),
+            source: None,
+        });
+    }
+
+    let mut failed = Failed::default();
+    // A linked element fails as its component
+    failed.nodes.extend(scenario.nodes.iter().filter_map(|id| graph.node(id)).map(|n| n.id.as_str()));
+    failed.connections.extend(scenario.connections.iter().map(String::as_str));
+    for domain in &scenario.domains {
+        let (key, value) = parse_domain(domain)?;
+        failed.nodes.extend(
+            graph
+                .nodes()
+                .iter()
+                .filter(|n| ctx.attributes(&n.id).and_then(|a| a.get(key)).is_some_and(|v| v.trim() == value))
+                .map(|n| n.id.as_str()),
+        );
+    }
+
+    // Each failure on its own, to find the ones that break an entry without help
+    let singles: Vec<(&str, Failed)> = failed
+        .nodes
+        .iter()
+        .map(|&id| (id, Failed { nodes: HashSet::from([id]), ..Failed::default() }))
+        .chain(
+            failed
+                .connections
+                .iter()
+                .map(|&id| (id, Failed { connections: HashSet::from([id]), ..Failed::default() })),
+        )
+        .collect();
+
+    let simulation = Simulation { groups: redundancy_groups(&ctx) };
+    let healthy = Failed::default();
+    let (mut degraded, mut at_risk, mut unaffected) = (Vec::new(), Vec::new(), Vec::new());
+    let mut single_points_of_failure = HashSet::new();
+    for entry in entry_points(&ctx) {
+        let before = simulation.reachable(&entry.id, &healthy);
+        let after = simulation.reachable(&entry.id, &failed);
+        let mut lost: Vec<SystemNode> = before
+            .difference(&after)
+            .filter_map(|id| graph.node(id).cloned())
+            .collect();
+        lost.sort_by(|a, b| a.label.cmp(&b.label));
+
+        if simulation.entry_works(&entry.id, &failed) {
+            if lost.is_empty() {
+                unaffected.push(entry.clone());
+            } else {
+                at_risk.push(FlowImpact {
+                    entry: entry.clone(),
+                    lost,
+                    single_points_of_failure: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        let mut spofs: Vec<String> = singles
+            .iter()
+            .filter(|(_, alone)| !simulation.entry_works(&entry.id, alone))
+            .map(|(id, _)| id.to_string())
+            .collect();
+        spofs.sort();
+        single_points_of_failure.extend(spofs.iter().cloned());
+        degraded.push(FlowImpact {
+            entry: entry.clone(),
+            lost,
+            single_points_of_failure: spofs,
+        });
+    }
+
+    let mut failed_nodes: Vec<SystemNode> = failed.nodes.iter().filter_map(|id| graph.node(id).cloned()).collect();
+    failed_nodes.sort_by(|a, b| a.label.cmp(&b.label));
+    let mut failed_connections: Vec<String> = failed.connections.iter().map(|c| c.to_string()).collect();
+    failed_connections.sort();
+    let mut single_points_of_failure: Vec<String> = single_points_of_failure.into_iter().collect();
+    single_points_of_failure.sort();
+
+    Ok(FailureReport {
+        project_id: state.project.id.clone(),
+        failed_nodes,
+        failed_connections,
+        degraded,
+        at_risk,
+        unaffected,
+        single_points_of_failure,
+    })
+}
+
+#[tauri::command]
+pub async fn simulate_failure(
+    project_id: String,
+    scenario: FailureScenario,
+    projects: State<'_, ProjectStore>,
+) -> Result<FailureReport, ApiError> {
+    let report = projects
+        .read(&project_id, |state| simulate(state, &scenario))?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })??;
+    log::info!(
+        "Failure simulation in {}: {} nodes and {} connections down, {} entry points degraded",
+        project_id,
+        report.failed_nodes.len(),
+        report.failed_connections.len(),
+        report.degraded.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{component_with, connection, state_with, strings};
+    use crate::{ComponentType, Connection};
+
+    // web -> api -> (db-a | db-b), web -> auth
+    fn state() -> ProjectState {
+        let mut state = state_with(vec![
+            component_with("web", ComponentType::Frontend, &[], &[("zone", "a")]),
+            component_with("api", ComponentType::Backend, &[], &[("zone", "b")]),
+            component_with("auth", ComponentType::Service, &[], &[("zone", "b")]),
+            component_with("db-a", ComponentType::Database, &[], &[("zone", "a")]),
+            component_with("db-b", ComponentType::Database, &[], &[("zone", "b")]),
+        ]);
+        let redundant = |id: &str, from: &str, to: &str| Connection {
+            properties: strings(&[("redundancy_group", "db")]),
+            ..connection(id, from, to)
+        };
+        state.connections = vec![
+            connection("web-api", "web", "api"),
+            connection("web-auth", "web", "auth"),
+            redundant("api-a", "api", "db-a"),
+            redundant("api-b", "api", "db-b"),
+        ];
+        state
+    }
+
+    fn scenario(nodes: &[&str], connections: &[&str], domains: &[&str]) -> FailureScenario {
+        let owned = |ids: &[&str]| ids.iter().map(|s| s.to_string()).collect();
+        FailureScenario {
+            nodes: owned(nodes),
+            connections: owned(connections),
+            domains: owned(domains),
+        }
+    }
+
+    #[test]
+    fn redundant_replica_only_puts_flow_at_risk() {
+        let report = simulate(&state(), &scenario(&["db-a"], &[], &[])).unwrap();
+        assert!(report.degraded.is_empty());
+        assert_eq!(report.at_risk.len(), 1);
+        assert_eq!(report.at_risk[0].lost[0].id, "db-a");
+    }
+
+    #[test]
+    fn names_single_points_of_failure() {
+        let report = simulate(&state(), &scenario(&["db-a", "db-b"], &["web-auth"], &[])).unwrap();
+        assert_eq!(report.degraded.len(), 1);
+        let web = &report.degraded[0];
+        assert_eq!(web.entry.id, "web");
+        // Losing both databases takes two failures; the auth connection is enough by itself
+        assert_eq!(web.single_points_of_failure, ["web-auth"]);
+        assert_eq!(
+            web.lost.iter().map(|n| n.id.as_str()).collect::<Vec<_>>(),
+            ["auth", "db-a", "db-b"]
+        );
+    }
+
+    #[test]
+    fn fails_whole_domains() {
+        let report = simulate(&state(), &scenario(&[], &[], &["zone=b"])).unwrap();
+        assert_eq!(
+            report.failed_nodes.iter().map(|n| n.id.as_str()).collect::<Vec<_>>(),
+            ["api", "auth", "db-b"]
+        );
+        assert_eq!(report.single_points_of_failure, ["api", "auth"]);
+
+        assert!(simulate(&state(), &scenario(&[], &[], &["zone"])).is_err());
+        assert!(simulate(&state(), &scenario(&["nope"], &[], &[])).is_err());
+    }
+}
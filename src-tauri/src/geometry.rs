@@ -0,0 +1,585 @@
+// Element geometry: bounds, overlaps and arranging a selection
+//
+// Elements are boxes with a top-left position, a size, a rotation around their centre and a
+// z-index. Overlap checks use the axis-aligned box around the rotated element, which is what
+// the canvas hit-tests against. Every rearrangement is a diagram patch of `move_element`
+// operations; pinned elements never move, though alignment and distribution still measure
+// against them. Containers don't overlap their own contents, and moving one moves what's
+// inside it.
+
+use crate::diagram_patch::{check_diagram_revision, commit_patch, DiagramOp};
+use crate::diagrams::{on_diagram, read_diagram};
+use crate::containment;
+use crate::events::ChangeFeed;
+use crate::layout::is_pinned;
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::{ApiError, DiagramElement, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, State};
+
+// Size given to elements saved before elements had one
+pub const DEFAULT_WIDTH: f64 = 160.0;
+pub const DEFAULT_HEIGHT: f64 = 80.0;
+// Overlap resolution gives up after this many passes over the diagram
+const MAX_PASSES: usize = 200;
+
+// Where a box starts or how long it is along one axis
+type Extent = fn(&Bounds) -> f64;
+
+pub fn default_width() -> f64 {
+    DEFAULT_WIDTH
+}
+
+pub fn default_height() -> f64 {
+    DEFAULT_HEIGHT
+}
+
+/// Axis-aligned box around a possibly rotated element
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Bounds {
+    pub fn of(element: &DiagramElement) -> Self {
+        let (sin, cos) = element.rotation.to_radians().sin_cos();
+        let width = (element.width * cos).abs() + (element.height * sin).abs();
+        let height = (element.width * sin).abs() + (element.height * cos).abs();
+        let (cx, cy) = (element.position.x + element.width / 2.0, element.position.y + element.height / 2.0);
+        Bounds {
+            x: cx - width / 2.0,
+            y: cy - height / 2.0,
+            width,
+            height,
+        }
+    }
+
+    fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    fn centre(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    /// How far the boxes (grown by `spacing`) overlap along each axis, if they do
+    fn overlap(&self, other: &Bounds, spacing: f64) -> Option<(f64, f64)> {
+        let dx = self.right().min(other.right()) - self.x.max(other.x) + spacing;
+        let dy = self.bottom().min(other.bottom()) - self.y.max(other.y) + spacing;
+        (dx > 1e-9 && dy > 1e-9).then_some((dx, dy))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Overlap {
+    pub first: String,
+    pub second: String,
+    /// Area shared by the two boxes, zero when they're only closer than the spacing
+    pub area: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlignEdge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    CenterX,
+    CenterY,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Arrangement {
+    Align { edge: AlignEdge },
+    /// Equal gaps between the elements, keeping the outermost two in place
+    Distribute { axis: Axis },
+    /// Rounds top-left corners to the nearest grid point
+    Snap { grid: f64 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArrangeResult {
+    pub diagram_revision: u64,
+    pub moved: usize,
+    /// Overlaps left between elements that couldn't be moved
+    pub unresolved: Vec<Overlap>,
+    pub inverse: Vec<DiagramOp>,
+}
+
+/// Index pairs, lower first, of every element and each container it sits inside
+fn nested_pairs(diagram: &[DiagramElement]) -> HashSet<(usize, usize)> {
+    let index: HashMap<&str, usize> = diagram.iter().enumerate().map(|(i, e)| (e.id.as_str(), i)).collect();
+    diagram
+        .iter()
+        .enumerate()
+        .flat_map(|(i, e)| {
+            containment::ancestors(diagram, &e.id)
+                .into_iter()
+                .filter_map(|a| index.get(a))
+                .map(move |&a| (a.min(i), a.max(i)))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Index pairs of overlapping boxes, in a stable order; a container overlapping what's
+/// inside it doesn't count
+fn overlapping_pairs(
+    bounds: &[Bounds],
+    nested: &HashSet<(usize, usize)>,
+    spacing: f64,
+) -> Vec<(usize, usize, f64, f64)> {
+    // Sweep along x so only boxes sharing an x range are compared
+    let mut order: Vec<usize> = (0..bounds.len()).collect();
+    order.sort_by(|&a, &b| bounds[a].x.total_cmp(&bounds[b].x).then(a.cmp(&b)));
+    let mut pairs = Vec::new();
+    for (i, &a) in order.iter().enumerate() {
+        for &b in &order[i + 1..] {
+            if bounds[b].x >= bounds[a].right() + spacing {
+                break;
+            }
+            let pair = (a.min(b), a.max(b));
+            if nested.contains(&pair) {
+                continue;
+            }
+            if let Some((dx, dy)) = bounds[a].overlap(&bounds[b], spacing) {
+                pairs.push((pair.0, pair.1, dx, dy));
+            }
+        }
+    }
+    pairs.sort_by_key(|&(a, b, _, _)| (a, b));
+    pairs
+}
+
+fn overlaps_of(diagram: &[DiagramElement], bounds: &[Bounds], spacing: f64) -> Vec<Overlap> {
+    overlapping_pairs(bounds, &nested_pairs(diagram), spacing)
+        .into_iter()
+        .map(|(a, b, _, _)| Overlap {
+            first: diagram[a].id.clone(),
+            second: diagram[b].id.clone(),
+            area: bounds[a].overlap(&bounds[b], 0.0).map_or(0.0, |(dx, dy)| dx * dy),
+        })
+        .collect()
+}
+
+pub fn find_overlaps(diagram: &[DiagramElement], spacing: f64) -> Vec<Overlap> {
+    let bounds: Vec<Bounds> = diagram.iter().map(Bounds::of).collect();
+    overlaps_of(diagram, &bounds, spacing)
+}
+
+fn validate_spacing(spacing: f64) -> Result<f64, ApiError> {
+    if !spacing.is_finite() || spacing < 0.0 {
+        return Err(ApiError::InvalidProjectData {
+            details: format!("Spacing must be zero or more, got {}", spacing),
+            source: None,
+        });
+    }
+    Ok(spacing)
+}
+
+/// Indices of the selected elements, or of every element when there's no selection
+fn selection(state: &ProjectState, element_ids: Option<&[String]>) -> Result<Vec<usize>, ApiError> {
+    let Some(ids) = element_ids else {
+        return Ok((0..state.diagram.len()).collect());
+    };
+    let index: HashMap<&str, usize> = state.diagram.iter().enumerate().map(|(i, e)| (e.id.as_str(), i)).collect();
+    let unknown: Vec<&str> = ids.iter().map(String::as_str).filter(|id| !index.contains_key(id)).collect();
+    if !unknown.is_empty() {
+        return Err(ApiError::InvalidProjectData {
+            details: format!("Unknown diagram elements: {}", unknown.join(", ")),
+            source: None,
+        });
+    }
+    let mut selected: Vec<usize> = ids.iter().map(|id| index[id.as_str()]).collect();
+    selected.sort_unstable();
+    selected.dedup();
+    Ok(selected)
+}
+
+/// Moves for elements whose bounds were shifted; the contents of a moved container that
+/// weren't shifted themselves go along with it, except pinned ones
+fn move_ops(state: &ProjectState, bounds: &[Bounds]) -> Vec<DiagramOp> {
+    let targets: HashMap<String, Position> = state
+        .diagram
+        .iter()
+        .zip(bounds)
+        .filter_map(|(element, moved)| {
+            let before = Bounds::of(element);
+            if is_pinned(&element.properties) {
+                return Some((element.id.clone(), element.position.clone()));
+            }
+            if moved.x == before.x && moved.y == before.y {
+                return None;
+            }
+            let position = Position {
+                x: element.position.x + moved.x - before.x,
+                y: element.position.y + moved.y - before.y,
+            };
+            Some((element.id.clone(), position))
+        })
+        .collect();
+    containment::move_ops(state, &targets)
+}
+
+/// Pushes the selected elements apart along whichever axis needs the smaller move
+pub fn resolve_overlaps(
+    state: &ProjectState,
+    element_ids: Option<&[String]>,
+    spacing: f64,
+) -> Result<(Vec<DiagramOp>, Vec<Overlap>), ApiError> {
+    let spacing = validate_spacing(spacing)?;
+    let selected: HashSet<usize> = selection(state, element_ids)?.into_iter().collect();
+    let movable: Vec<bool> = state
+        .diagram
+        .iter()
+        .enumerate()
+        .map(|(i, e)| selected.contains(&i) && !is_pinned(&e.properties))
+        .collect();
+    let mut bounds: Vec<Bounds> = state.diagram.iter().map(Bounds::of).collect();
+    let nested = nested_pairs(&state.diagram);
+    let inside: Vec<Vec<usize>> = state.diagram.iter().map(|e| containment::descendants(&state.diagram, &e.id)).collect();
+    // Containers carry their contents along, apart from pinned ones
+    let shift = |bounds: &mut [Bounds], element: usize, dx: f64, dy: f64| {
+        let carried = inside[element].iter().filter(|&&i| !is_pinned(&state.diagram[i].properties));
+        for &i in std::iter::once(&element).chain(carried) {
+            bounds[i].x += dx;
+            bounds[i].y += dy;
+        }
+    };
+
+    for _ in 0..MAX_PASSES {
+        let mut moved = false;
+        for (a, b, dx, dy) in overlapping_pairs(&bounds, &nested, spacing) {
+            let (share_a, share_b) = match (movable[a], movable[b]) {
+                (true, true) => (0.5, 0.5),
+                (true, false) => (1.0, 0.0),
+                (false, true) => (0.0, 1.0),
+                (false, false) => continue,
+            };
+            // Push b away from a; boxes sharing a centre split by index
+            let (ca, cb) = (bounds[a].centre(), bounds[b].centre());
+            if dx <= dy {
+                let sign = if cb.0 >= ca.0 { 1.0 } else { -1.0 };
+                shift(&mut bounds, a, -sign * dx * share_a, 0.0);
+                shift(&mut bounds, b, sign * dx * share_b, 0.0);
+            } else {
+                let sign = if cb.1 >= ca.1 { 1.0 } else { -1.0 };
+                shift(&mut bounds, a, 0.0, -sign * dy * share_a);
+                shift(&mut bounds, b, 0.0, sign * dy * share_b);
+            }
+            moved = true;
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    let unresolved = overlaps_of(&state.diagram, &bounds, 0.0)
+        .into_iter()
+        .filter(|o| {
+            [&o.first, &o.second]
+                .iter()
+                .any(|id| state.diagram.iter().position(|e| &e.id == *id).is_some_and(|i| selected.contains(&i)))
+        })
+        .collect();
+    Ok((move_ops(state, &bounds), unresolved))
+}
+
+fn too_few(needed: usize, what: &str) -> ApiError {
+    ApiError::InvalidProjectData {
+        details: format!("Select at least {} elements to {}", needed, what),
+        source: None,
+    }
+}
+
+pub fn arrange(state: &ProjectState, element_ids: &[String], arrangement: Arrangement) -> Result<Vec<DiagramOp>, ApiError> {
+    let selected = selection(state, Some(element_ids))?;
+    let mut bounds: Vec<Bounds> = state.diagram.iter().map(Bounds::of).collect();
+    let pinned: Vec<usize> = selected.iter().copied().filter(|&i| is_pinned(&state.diagram[i].properties)).collect();
+    let free: Vec<usize> = selected.iter().copied().filter(|i| !pinned.contains(i)).collect();
+
+    match arrangement {
+        Arrangement::Align { edge } => {
+            if selected.len() < 2 {
+                return Err(too_few(2, "align"));
+            }
+            // Pinned elements can't move, so the others line up with them
+            let reference = if pinned.is_empty() { &selected } else { &pinned };
+            let edges = reference.iter().map(|&i| bounds[i]);
+            let target = match edge {
+                AlignEdge::Left => edges.map(|b| b.x).fold(f64::INFINITY, f64::min),
+                AlignEdge::Top => edges.map(|b| b.y).fold(f64::INFINITY, f64::min),
+                AlignEdge::Right => edges.map(|b| b.right()).fold(f64::NEG_INFINITY, f64::max),
+                AlignEdge::Bottom => edges.map(|b| b.bottom()).fold(f64::NEG_INFINITY, f64::max),
+                AlignEdge::CenterX => edges.map(|b| b.centre().0).sum::<f64>() / reference.len() as f64,
+                AlignEdge::CenterY => edges.map(|b| b.centre().1).sum::<f64>() / reference.len() as f64,
+            };
+            for &i in &free {
+                let b = &mut bounds[i];
+                match edge {
+                    AlignEdge::Left => b.x = target,
+                    AlignEdge::Top => b.y = target,
+                    AlignEdge::Right => b.x = target - b.width,
+                    AlignEdge::Bottom => b.y = target - b.height,
+                    AlignEdge::CenterX => b.x = target - b.width / 2.0,
+                    AlignEdge::CenterY => b.y = target - b.height / 2.0,
+                }
+            }
+        }
+        Arrangement::Distribute { axis } => {
+            if selected.len() < 3 {
+                return Err(too_few(3, "distribute"));
+            }
+            let (start, size): (Extent, Extent) = match axis {
+                Axis::Horizontal => (|b| b.x, |b| b.width),
+                Axis::Vertical => (|b| b.y, |b| b.height),
+            };
+            let mut order = selected.clone();
+            order.sort_by(|&a, &b| {
+                let centre = |i: usize| start(&bounds[i]) + size(&bounds[i]) / 2.0;
+                centre(a).total_cmp(&centre(b)).then(a.cmp(&b))
+            });
+            let (first, last) = (bounds[order[0]], bounds[order[order.len() - 1]]);
+            let occupied: f64 = order.iter().map(|&i| size(&bounds[i])).sum();
+            let gap = (start(&last) + size(&last) - start(&first) - occupied) / (order.len() - 1) as f64;
+            // Pinned elements in the middle keep their place; the rest are spaced as if they'd moved
+            let mut cursor = start(&first);
+            for &i in &order {
+                if free.contains(&i) {
+                    match axis {
+                        Axis::Horizontal => bounds[i].x = cursor,
+                        Axis::Vertical => bounds[i].y = cursor,
+                    }
+                }
+                cursor += size(&bounds[i]) + gap;
+            }
+        }
+        Arrangement::Snap { grid } => {
+            if !grid.is_finite() || grid <= 0.0 {
+                return Err(ApiError::InvalidProjectData {
+                    details: format!("Grid size must be positive, got {}", grid),
+                    source: None,
+                });
+            }
+            // Snaps the top-left corner, which for a rotated element isn't its bounds' corner
+            for &i in &free {
+                let position = &state.diagram[i].position;
+                bounds[i].x += (position.x / grid).round() * grid - position.x;
+                bounds[i].y += (position.y / grid).round() * grid - position.y;
+            }
+        }
+    }
+    Ok(move_ops(state, &bounds))
+}
+
+fn commit_moves(
+    state: &mut ProjectState,
+    diagram_id: Option<&str>,
+    ops: Vec<DiagramOp>,
+    unresolved: Vec<Overlap>,
+    app: &AppHandle,
+    changes: &ChangeFeed,
+) -> Result<ArrangeResult, ApiError> {
+    let moved = ops.len();
+    let inverse = commit_patch(state, diagram_id, ops, app, changes)?;
+    Ok(ArrangeResult {
+        diagram_revision: state.diagram_revision,
+        moved,
+        unresolved,
+        inverse,
+    })
+}
+
+#[tauri::command]
+pub async fn get_overlaps(
+    project_id: String,
+    diagram_id: Option<String>,
+    spacing: Option<f64>,
+    projects: State<'_, ProjectStore>,
+) -> Result<Vec<Overlap>, ApiError> {
+    let spacing = validate_spacing(spacing.unwrap_or(0.0))?;
+    projects
+        .read(&project_id, |state| {
+            read_diagram(state, diagram_id.as_deref(), |view| find_overlaps(view.elements, spacing))
+        })?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })?
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn fix_overlaps(
+    project_id: String,
+    diagram_id: Option<String>,
+    element_ids: Option<Vec<String>>,
+    spacing: Option<f64>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<ArrangeResult, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let diagram_id = diagram_id.as_deref();
+    on_diagram(&mut state, diagram_id, |state| {
+        check_diagram_revision(state, diagram_id, expected_revision)?;
+        let (ops, unresolved) = resolve_overlaps(state, element_ids.as_deref(), spacing.unwrap_or(0.0))?;
+        let result = commit_moves(state, diagram_id, ops, unresolved, &app, &changes)?;
+        log::info!(
+            "Moved {} elements apart in project {}, {} overlaps left",
+            result.moved,
+            project_id,
+            result.unresolved.len()
+        );
+        Ok(result)
+    })
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn arrange_elements(
+    project_id: String,
+    diagram_id: Option<String>,
+    element_ids: Vec<String>,
+    arrangement: Arrangement,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<ArrangeResult, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let diagram_id = diagram_id.as_deref();
+    on_diagram(&mut state, diagram_id, |state| {
+        check_diagram_revision(state, diagram_id, expected_revision)?;
+        let ops = arrange(state, &element_ids, arrangement)?;
+        let result = commit_moves(state, diagram_id, ops, Vec::new(), &app, &changes)?;
+        log::info!("Arranged {} elements in project {} ({:?})", result.moved, project_id, arrangement);
+        Ok(result)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagram_patch::apply_patch;
+    use crate::test_support::{diagram_state, element_at};
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn old_elements_get_default_geometry() {
+        let element: DiagramElement = serde_json::from_str(
+            r#"{"id": "a", "element_type": "service", "position": {"x": 1.0, "y": 2.0}, "properties": {}}"#,
+        )
+        .unwrap();
+        assert_eq!((element.width, element.height), (DEFAULT_WIDTH, DEFAULT_HEIGHT));
+        assert_eq!((element.rotation, element.z_index), (0.0, 0));
+
+        // A quarter turn swaps the box's sides around the same centre
+        let mut turned = element.clone();
+        turned.rotation = 90.0;
+        let bounds = Bounds::of(&turned);
+        assert!((bounds.width - DEFAULT_HEIGHT).abs() < 1e-9);
+        assert!((bounds.x - (1.0 + (DEFAULT_WIDTH - DEFAULT_HEIGHT) / 2.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn resolves_overlaps_around_pinned_elements() {
+        let mut pinned = element_at("pinned", 0.0, 0.0);
+        pinned.properties.insert("pinned".into(), "true".into());
+        let mut state = diagram_state(vec![pinned, element_at("a", 80.0, 10.0), element_at("b", 90.0, 20.0)], vec![]);
+        assert_eq!(find_overlaps(&state.diagram, 0.0).len(), 3);
+
+        let (ops, unresolved) = resolve_overlaps(&state, None, 10.0).unwrap();
+        assert!(unresolved.is_empty());
+        assert!(ops.iter().all(|op| !matches!(op, DiagramOp::MoveElement { id, .. } if id == "pinned")));
+        apply_patch(&mut state, &ops).unwrap();
+        assert!(find_overlaps(&state.diagram, 9.0).is_empty());
+        assert_eq!(state.diagram[0].position, Position { x: 0.0, y: 0.0 });
+
+        // Two pinned elements on top of each other stay that way, and say so
+        state.diagram[1] = state.diagram[0].clone();
+        state.diagram[1].id = "also-pinned".into();
+        let (_, unresolved) = resolve_overlaps(&state, None, 0.0).unwrap();
+        assert_eq!(unresolved.len(), 1);
+
+        // A container pushed aside leaves its pinned contents where they are
+        let mut vpc = element_at("vpc", 50.0, 0.0);
+        vpc.width = 300.0;
+        let mut child = element_at("child", 240.0, 0.0);
+        child.parent_id = Some("vpc".into());
+        child.properties.insert("pinned".into(), "true".into());
+        let mut state = diagram_state(vec![state.diagram[0].clone(), vpc, child], vec![]);
+        let (ops, _) = resolve_overlaps(&state, None, 0.0).unwrap();
+        apply_patch(&mut state, &ops).unwrap();
+        assert!(state.diagram[1].position.x > 50.0);
+        assert_eq!(state.diagram[2].position, Position { x: 240.0, y: 0.0 });
+    }
+
+    #[test]
+    fn aligns_distributes_and_snaps() {
+        let elements = vec![element_at("a", 0.0, 0.0), element_at("b", 30.0, 40.0), element_at("c", 400.0, 7.0)];
+        let mut state = diagram_state(elements, vec![]);
+        let ops = arrange(&state, &ids(&["a", "b", "c"]), Arrangement::Align { edge: AlignEdge::Bottom }).unwrap();
+        apply_patch(&mut state, &ops).unwrap();
+        assert!(state.diagram.iter().all(|e| e.position.y == 40.0));
+
+        let ops = arrange(&state, &ids(&["a", "b", "c"]), Arrangement::Distribute { axis: Axis::Horizontal }).unwrap();
+        apply_patch(&mut state, &ops).unwrap();
+        let xs: Vec<f64> = state.diagram.iter().map(|e| e.position.x).collect();
+        assert_eq!(xs, [0.0, 200.0, 400.0]);
+
+        state.diagram[1].position = Position { x: 203.0, y: 47.0 };
+        let ops = arrange(&state, &ids(&["b"]), Arrangement::Snap { grid: 20.0 }).unwrap();
+        apply_patch(&mut state, &ops).unwrap();
+        assert_eq!(state.diagram[1].position, Position { x: 200.0, y: 40.0 });
+
+        assert!(arrange(&state, &ids(&["a"]), Arrangement::Align { edge: AlignEdge::Left }).is_err());
+        assert!(arrange(&state, &ids(&["a", "nope"]), Arrangement::Align { edge: AlignEdge::Left }).is_err());
+
+        // A container snapped after its child doesn't drag the child off the grid
+        let mut child = element_at("child", 33.0, 47.0);
+        child.parent_id = Some("box".into());
+        let mut nested = diagram_state(vec![child, element_at("box", 13.0, 27.0)], vec![]);
+        let ops = arrange(&nested, &ids(&["child", "box"]), Arrangement::Snap { grid: 20.0 }).unwrap();
+        apply_patch(&mut nested, &ops).unwrap();
+        assert_eq!(nested.diagram[0].position, Position { x: 40.0, y: 40.0 });
+        assert_eq!(nested.diagram[1].position, Position { x: 20.0, y: 20.0 });
+    }
+
+    #[test]
+    fn aligning_a_container_carries_its_contents() {
+        let mut vpc = element_at("vpc", 300.0, 0.0);
+        vpc.width = 300.0;
+        let mut child = element_at("child", 320.0, 10.0);
+        child.parent_id = Some("vpc".into());
+        let mut state = diagram_state(vec![element_at("a", 0.0, 0.0), vpc, child], vec![]);
+
+        let ops = arrange(&state, &ids(&["a", "vpc"]), Arrangement::Align { edge: AlignEdge::Left }).unwrap();
+        apply_patch(&mut state, &ops).unwrap();
+        assert_eq!(state.diagram[1].position, Position { x: 0.0, y: 0.0 });
+        assert_eq!(state.diagram[2].position, Position { x: 20.0, y: 10.0 });
+    }
+}
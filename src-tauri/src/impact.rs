@@ -0,0 +1,123 @@
+// Impact analysis: what breaks if a component or diagram element goes down
+
+use crate::state::ProjectStore;
+use crate::system_graph::{Reached, SystemGraph, SystemNode};
+use crate::ApiError;
+use serde::Serialize;
+use tauri::State;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImpactReport {
+    pub project_id: String,
+    pub root: SystemNode,
+    pub direct_count: usize,
+    pub transitive_count: usize,
+    /// Every dependent, nearest first; `distance` 1 means a direct dependent
+    pub dependents: Vec<Reached>,
+}
+
+pub fn impact_of(graph: &SystemGraph, project_id: &str, node_id: &str) -> Option<ImpactReport> {
+    let root = graph.node(node_id)?.clone();
+    let mut dependents = graph.dependents(node_id);
+    // Stable output for reviews: by distance, then by label
+    dependents.sort_by(|a, b| a.distance.cmp(&b.distance).then_with(|| a.node.label.cmp(&b.node.label)));
+    let direct_count = dependents.iter().filter(|d| d.distance == 1).count();
+    Some(ImpactReport {
+        project_id: project_id.to_string(),
+        root,
+        direct_count,
+        transitive_count: dependents.len() - direct_count,
+        dependents,
+    })
+}
+
+#[tauri::command]
+pub async fn analyze_impact(
+    project_id: String,
+    node_id: String,
+    projects: State<'_, ProjectStore>,
+) -> Result<ImpactReport, ApiError> {
+    let graph = projects
+        .read(&project_id, SystemGraph::from_state)?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })?;
+    let report = impact_of(&graph, &project_id, &node_id).ok_or_else(|| ApiError::ComponentNotFound {
+        component_id: node_id.clone(),
+        project_id: project_id.clone(),
+        source: None,
+    })?;
+    log::debug!(
+        "Impact of {} in {}: {} direct, {} transitive dependents",
+        node_id,
+        project_id,
+        report.direct_count,
+        report.transitive_count
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, component_with, state_with};
+    use crate::{Component, ComponentType, DiagramElement};
+
+    #[test]
+    fn reports_direct_and_transitive_dependents() {
+        let component = |id: &str, name: &str, deps: &[&str]| Component {
+            name: name.into(),
+            ..component_with(id, ComponentType::Service, deps, &[])
+        };
+        let state = state_with(vec![
+            component("auth", "Auth Service", &[]),
+            component("orders", "Orders", &["auth"]),
+            component("billing", "Billing", &["auth", "orders"]),
+            component("ui", "UI", &["orders"]),
+            component("docs", "Docs", &[]),
+        ]);
+        let graph = SystemGraph::from_state(&state);
+
+        let report = impact_of(&graph, "p", "auth").unwrap();
+        assert_eq!(report.root.label, "Auth Service");
+        assert_eq!(report.direct_count, 2);
+        assert_eq!(report.transitive_count, 1);
+        assert_eq!(
+            report.dependents.iter().map(|d| d.node.label.as_str()).collect::<Vec<_>>(),
+            ["Billing", "Orders", "UI"]
+        );
+        assert_eq!(report.dependents[2].path.len(), 2);
+
+        assert!(impact_of(&graph, "p", "missing").is_none());
+    }
+
+    #[test]
+    fn connections_to_linked_elements_reach_the_component() {
+        let mut state = state_with(vec![test_support::component("auth"), test_support::component("orders")]);
+        state.diagram = vec![
+            DiagramElement {
+                component_id: Some("auth".into()),
+                ..test_support::element("auth-box")
+            },
+            DiagramElement {
+                component_id: Some("orders".into()),
+                ..test_support::element("orders-box")
+            },
+            test_support::element("ui"),
+        ];
+        state.connections = vec![
+            test_support::connection("c1", "orders-box", "auth-box"),
+            test_support::connection("c2", "ui", "orders-box"),
+        ];
+        let graph = SystemGraph::from_state(&state);
+
+        let report = impact_of(&graph, "p", "auth").unwrap();
+        assert_eq!(
+            report.dependents.iter().map(|d| (d.node.id.as_str(), d.distance)).collect::<Vec<_>>(),
+            [("orders", 1), ("ui", 2)]
+        );
+        // Asking about the element answers for its component
+        assert_eq!(impact_of(&graph, "p", "auth-box").unwrap().root.id, "auth");
+    }
+}
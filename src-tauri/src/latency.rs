@@ -0,0 +1,401 @@
+// End-to-end request latency over diagram connections
+//
+// Starting at an entry element, every outgoing connection is a call that takes the
+// connection's latency and then whatever the callee's own calls take. A node's calls run
+// one after another unless it sets `fan_out = parallel`, in which case they start together
+// and the slowest one decides when the node is done. The result is a timeline of call spans,
+// much like a distributed trace, from which the critical path and budget overruns are read.
+
+use crate::state::{ProjectState, ProjectStore};
+use crate::units::{Measure, TimeSpan};
+use crate::{ApiError, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::State;
+
+// Diamond-heavy diagrams expand into a tree of calls; stop well before that gets silly
+const MAX_SPANS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionLatency {
+    pub p50: TimeSpan,
+    /// Falls back to `p50` when not measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p99: Option<TimeSpan>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyBudget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p50: Option<TimeSpan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p99: Option<TimeSpan>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FanOut {
+    Sequential,
+    Parallel,
+}
+
+// (p50, p99) in seconds
+type Times = (f64, f64);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CallSpan {
+    pub connection_id: String,
+    pub from: String,
+    pub to: String,
+    pub depth: usize,
+    /// Index of the call that made this one, if any
+    pub parent: Option<usize>,
+    pub fan_out: FanOut,
+    pub start_p50: f64,
+    pub end_p50: f64,
+    pub start_p99: f64,
+    pub end_p99: f64,
+    pub on_critical_path: bool,
+    #[serde(skip)]
+    previous_sibling: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyPath {
+    /// Connections from the entry down to the call that finishes last on this path
+    pub connection_ids: Vec<String>,
+    pub p50: Measure,
+    pub p99: Measure,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyReport {
+    pub project_id: String,
+    pub entry_id: String,
+    pub total_p50: Measure,
+    /// Sum of tail latencies, so a pessimistic bound rather than a true p99
+    pub total_p99: Measure,
+    pub spans: Vec<CallSpan>,
+    /// Connections on the chain that determines the p99 total, in call order
+    pub critical_path: Vec<String>,
+    pub budget: LatencyBudget,
+    pub over_budget: Vec<LatencyPath>,
+    pub warnings: Vec<String>,
+}
+
+fn attribute<'a>(state: &'a ProjectState, id: &str, key: &str) -> Option<&'a str> {
+    state
+        .diagram
+        .iter()
+        .find(|e| e.id == id)
+        .map(|e| &e.properties)
+        .or_else(|| state.project.components.iter().find(|c| c.id == id).map(|c| &c.metadata))
+        .and_then(|attributes| attributes.get(key))
+        .map(String::as_str)
+}
+
+fn fan_out(state: &ProjectState, id: &str) -> FanOut {
+    match attribute(state, id, "fan_out").map(|v| v.trim().to_lowercase()) {
+        Some(mode) if mode == "parallel" => FanOut::Parallel,
+        _ => FanOut::Sequential,
+    }
+}
+
+struct Walk<'a> {
+    state: &'a ProjectState,
+    calls: HashMap<&'a str, Vec<&'a Connection>>,
+    spans: Vec<CallSpan>,
+    warnings: Vec<String>,
+    stack: Vec<&'a str>,
+}
+
+impl<'a> Walk<'a> {
+    fn visit(&mut self, node: &'a str, start: Times, depth: usize, parent: Option<usize>) -> Times {
+        let mode = fan_out(self.state, node);
+        let mut cursor = start;
+        let mut end = start;
+        let mut previous = None;
+        self.stack.push(node);
+
+        let calls = self.calls.get(node).cloned().unwrap_or_default();
+        for connection in calls {
+            if self.spans.len() >= MAX_SPANS {
+                self.warnings.push(format!("Stopped after {} calls; the diagram fans out too widely", MAX_SPANS));
+                break;
+            }
+            let target = connection.target_id.as_str();
+            if self.stack.contains(&target) {
+                self.warnings
+                    .push(format!("Connection {} loops back to {} and was not followed", connection.id, target));
+                continue;
+            }
+            let latency = connection.latency.unwrap_or_else(|| {
+                self.warnings.push(format!("Connection {} has no latency annotation; counted as 0", connection.id));
+                ConnectionLatency::default()
+            });
+
+            let call_start = match mode {
+                FanOut::Sequential => cursor,
+                FanOut::Parallel => start,
+            };
+            let span = self.spans.len();
+            self.spans.push(CallSpan {
+                connection_id: connection.id.clone(),
+                from: node.to_string(),
+                to: target.to_string(),
+                depth,
+                parent,
+                fan_out: mode,
+                start_p50: call_start.0,
+                end_p50: call_start.0,
+                start_p99: call_start.1,
+                end_p99: call_start.1,
+                on_critical_path: false,
+                previous_sibling: if mode == FanOut::Sequential { previous } else { None },
+            });
+
+            let arrived = (
+                call_start.0 + latency.p50.0,
+                call_start.1 + latency.p99.unwrap_or(latency.p50).0,
+            );
+            let done = self.visit(target, arrived, depth + 1, Some(span));
+            self.spans[span].end_p50 = done.0;
+            self.spans[span].end_p99 = done.1;
+
+            cursor = done;
+            end = (end.0.max(done.0), end.1.max(done.1));
+            previous = Some(span);
+        }
+
+        self.stack.pop();
+        end
+    }
+
+    /// The call under `span` (or under the entry for `None`) that finishes last
+    fn latest_child(&self, parent: Option<usize>) -> Option<usize> {
+        self.spans
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.parent == parent)
+            .max_by(|a, b| a.1.end_p99.total_cmp(&b.1.end_p99))
+            .map(|(i, _)| i)
+    }
+
+    fn latest_leaf(&self, mut span: usize) -> usize {
+        while let Some(child) = self.latest_child(Some(span)) {
+            span = child;
+        }
+        span
+    }
+
+    // Walks back from the last call to finish: to the sibling that had to finish before it
+    // started, otherwise to the call that made it
+    fn critical_path(&mut self) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut current = self.latest_child(None).map(|s| self.latest_leaf(s));
+        while let Some(span) = current {
+            self.spans[span].on_critical_path = true;
+            path.push(self.spans[span].connection_id.clone());
+            current = match self.spans[span].previous_sibling {
+                Some(sibling) => Some(self.latest_leaf(sibling)),
+                None => self.spans[span].parent,
+            };
+        }
+        path.reverse();
+        path
+    }
+
+    fn chain(&self, mut span: usize) -> Vec<String> {
+        let mut ids = vec![self.spans[span].connection_id.clone()];
+        while let Some(parent) = self.spans[span].parent {
+            ids.push(self.spans[parent].connection_id.clone());
+            span = parent;
+        }
+        ids.reverse();
+        ids
+    }
+}
+
+pub fn analyze(state: &ProjectState, entry_id: &str, budget: LatencyBudget) -> LatencyReport {
+    let mut calls: HashMap<&str, Vec<&Connection>> = HashMap::new();
+    for connection in &state.connections {
+        calls.entry(connection.source_id.as_str()).or_default().push(connection);
+    }
+    // Sequential calls happen in the order given by an `order` property, then as stored
+    for list in calls.values_mut() {
+        list.sort_by_key(|c| c.properties.get("order").and_then(|o| o.trim().parse::<i64>().ok()).unwrap_or(i64::MAX));
+    }
+
+    let mut walk = Walk {
+        state,
+        calls,
+        spans: Vec::new(),
+        warnings: Vec::new(),
+        stack: Vec::new(),
+    };
+    let total = walk.visit(entry_id, (0.0, 0.0), 0, None);
+    let critical_path = walk.critical_path();
+
+    // A leaf call that finishes after the budget means the path leading to it is over
+    let mut calls_made = vec![0usize; walk.spans.len()];
+    for parent in walk.spans.iter().filter_map(|s| s.parent) {
+        calls_made[parent] += 1;
+    }
+    let mut over_budget: Vec<LatencyPath> = walk
+        .spans
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| calls_made[*i] == 0)
+        .filter(|(_, s)| {
+            budget.p50.is_some_and(|b| s.end_p50 > b.0) || budget.p99.is_some_and(|b| s.end_p99 > b.0)
+        })
+        .map(|(i, s)| LatencyPath {
+            connection_ids: walk.chain(i),
+            p50: Measure::duration(s.end_p50),
+            p99: Measure::duration(s.end_p99),
+        })
+        .collect();
+    over_budget.sort_by(|a, b| b.p99.value.total_cmp(&a.p99.value));
+
+    let mut warnings = walk.warnings;
+    warnings.dedup();
+    LatencyReport {
+        project_id: state.project.id.clone(),
+        entry_id: entry_id.to_string(),
+        total_p50: Measure::duration(total.0),
+        total_p99: Measure::duration(total.1),
+        spans: walk.spans,
+        critical_path,
+        budget,
+        over_budget,
+        warnings,
+    }
+}
+
+/// Calls are keyed by connection source, so the walk can start at a diagram element or at a
+/// component that connections are drawn from directly, but not at a component only reached
+/// through its linked elements
+fn is_entry(state: &ProjectState, id: &str) -> bool {
+    state.diagram.iter().any(|e| e.id == id) || state.connections.iter().any(|c| c.source_id == id)
+}
+
+/// `budget` overrides the project's configured latency budget for this run
+#[tauri::command]
+pub async fn analyze_latency(
+    project_id: String,
+    entry_id: String,
+    budget: Option<LatencyBudget>,
+    projects: State<'_, ProjectStore>,
+) -> Result<LatencyReport, ApiError> {
+    let report = projects
+        .read(&project_id, |state| {
+            is_entry(state, &entry_id).then(|| analyze(state, &entry_id, budget.unwrap_or(state.project.settings.latency_budget)))
+        })?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })?
+        .ok_or_else(|| ApiError::InvalidProjectData {
+            details: format!("Entry element {} not found on the diagram", entry_id),
+            source: None,
+        })?;
+    log::debug!(
+        "Latency from {} in {}: p50 {}, p99 {}, {} paths over budget",
+        entry_id,
+        project_id,
+        report.total_p50.display,
+        report.total_p99.display,
+        report.over_budget.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, diagram_state, element, strings};
+    use crate::DiagramElement;
+
+    fn call(id: &str, from: &str, to: &str, p50_ms: f64, p99_ms: f64) -> Connection {
+        Connection {
+            latency: Some(ConnectionLatency {
+                p50: TimeSpan(p50_ms / 1000.0),
+                p99: Some(TimeSpan(p99_ms / 1000.0)),
+            }),
+            ..test_support::connection(id, from, to)
+        }
+    }
+
+    fn state(gateway_fan_out: &str) -> ProjectState {
+        let gateway = DiagramElement {
+            properties: strings(&[("fan_out", gateway_fan_out)]),
+            ..element("gateway")
+        };
+        diagram_state(
+            vec![element("client"), gateway, element("users"), element("orders"), element("db")],
+            vec![
+                call("c-gw", "client", "gateway", 5.0, 20.0),
+                call("gw-users", "gateway", "users", 10.0, 40.0),
+                call("gw-orders", "gateway", "orders", 15.0, 30.0),
+                call("orders-db", "orders", "db", 20.0, 80.0),
+            ],
+        )
+    }
+
+    fn close(a: f64, b_ms: f64) -> bool {
+        (a - b_ms / 1000.0).abs() < 1e-9
+    }
+
+    #[test]
+    fn sequential_calls_add_up() {
+        let report = analyze(&state("sequential"), "client", LatencyBudget::default());
+        // 5 + 10 + 15 + 20
+        assert!(close(report.total_p50.value, 50.0));
+        assert!(close(report.total_p99.value, 170.0));
+        assert_eq!(report.critical_path, ["c-gw", "gw-users", "gw-orders", "orders-db"]);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn parallel_calls_take_the_slowest_branch() {
+        let report = analyze(&state("parallel"), "client", LatencyBudget::default());
+        // 5 + max(10, 15 + 20)
+        assert!(close(report.total_p50.value, 40.0));
+        assert!(close(report.total_p99.value, 130.0));
+        assert_eq!(report.critical_path, ["c-gw", "gw-orders", "orders-db"]);
+        let users = report.spans.iter().find(|s| s.connection_id == "gw-users").unwrap();
+        assert!(!users.on_critical_path);
+        assert!(close(users.start_p50, 5.0));
+    }
+
+    #[test]
+    fn flags_paths_over_budget_and_skips_loops() {
+        let mut state = state("parallel");
+        state.connections.push(call("db-client", "db", "client", 1.0, 1.0));
+        state.connections[1].latency = None;
+
+        let budget = LatencyBudget {
+            p50: None,
+            p99: Some(TimeSpan(0.1)),
+        };
+        let report = analyze(&state, "client", budget);
+        assert_eq!(report.over_budget.len(), 1);
+        assert_eq!(report.over_budget[0].connection_ids, ["c-gw", "gw-orders", "orders-db"]);
+        assert_eq!(report.over_budget[0].p99.display, "130 ms");
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings.iter().any(|w| w.contains("loops back")));
+        assert!(report.warnings.iter().any(|w| w.contains("gw-users")));
+    }
+
+    #[test]
+    fn entries_are_where_calls_start() {
+        let mut state = state("sequential");
+        state.project.components = vec![test_support::component("api"), test_support::component("billing")];
+        state.diagram[1].component_id = Some("api".into());
+        state.connections.push(call("billing-db", "billing", "db", 1.0, 1.0));
+        assert!(is_entry(&state, "gateway"));
+        assert!(is_entry(&state, "billing"));
+        // Its calls are drawn from the linked element, so the component itself would report nothing
+        assert!(!is_entry(&state, "api"));
+        assert!(!is_entry(&state, "nowhere"));
+    }
+}
@@ -0,0 +1,1070 @@
+// Automatic diagram layout
+//
+// Layouts only compute positions and hand them over as `move_element` operations. Elements
+// with a `pinned = true` property keep their position, though they still take part in the
+// layout so their neighbours are arranged around them; the layered layout keeps the rest of
+// each layer clear of them.
+//
+// The layered layout follows Sugiyama: break cycles by reversing back edges, assign layers by
+// longest path, route long edges through dummy nodes, reduce crossings with barycenter sweeps,
+// then place nodes near the average of their neighbours.
+//
+// The force-directed layout suits meshes: connected elements attract, all elements repel, and
+// elements in the same container are pulled towards their group's centre. It is seeded so
+// the same options give the same picture. It stops at the time limit and keeps the best
+// positions found so far. The grid layout packs each group into its own block in a single pass.
+//
+// Containers aren't placed themselves: once their contents have moved, each one is refitted
+// around its children, innermost first. The layered layout keeps siblings next to each other
+// within a layer so their containers don't need to overlap.
+
+use crate::diagram_patch::{check_diagram_revision, commit_patch, snapshot_diagram, DiagramOp};
+use crate::diagrams::on_diagram;
+use crate::containment;
+use crate::events::ChangeFeed;
+use crate::geometry::Bounds;
+use crate::simulation::Rng;
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::{ApiError, DiagramElement, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+const ORDERING_SWEEPS: usize = 24;
+const PLACEMENT_SWEEPS: usize = 8;
+// Pull of every element towards the middle, so disconnected parts don't drift off
+const GRAVITY: f64 = 0.05;
+const MAX_TIME_LIMIT_MS: u64 = 30_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutAlgorithm {
+    #[default]
+    Layered,
+    Force,
+    Grid,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutDirection {
+    #[default]
+    TopBottom,
+    LeftRight,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutOptions {
+    pub algorithm: LayoutAlgorithm,
+    pub direction: LayoutDirection,
+    /// Gap between neighbouring elements in a layer
+    pub node_spacing: f64,
+    /// Gap between layers
+    pub layer_spacing: f64,
+    /// Force-directed only: the same seed gives the same layout
+    pub seed: u64,
+    /// Force-directed only
+    pub iterations: usize,
+    pub time_limit_ms: u64,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: LayoutAlgorithm::default(),
+            direction: LayoutDirection::default(),
+            node_spacing: 60.0,
+            layer_spacing: 100.0,
+            seed: 0,
+            iterations: 300,
+            time_limit_ms: 2_000,
+        }
+    }
+}
+
+impl LayoutOptions {
+    /// Rejects bad spacing and caps the time limit
+    fn validate(&mut self) -> Result<(), ApiError> {
+        self.time_limit_ms = self.time_limit_ms.min(MAX_TIME_LIMIT_MS);
+        for (name, value) in [("node_spacing", self.node_spacing), ("layer_spacing", self.layer_spacing)] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(ApiError::InvalidProjectData {
+                    details: format!("Layout {} must be a non-negative number", name),
+                    source: None,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutResult {
+    pub diagram_revision: u64,
+    pub moved: usize,
+    /// Edge crossings left between layers, for layered layouts
+    pub crossings: Option<usize>,
+    /// The time limit ran out before the layout settled
+    pub stopped_early: bool,
+    /// Applying these as a diagram patch restores the previous positions
+    pub inverse: Vec<DiagramOp>,
+}
+
+pub struct Layout {
+    pub positions: HashMap<String, Position>,
+    pub crossings: Option<usize>,
+    pub stopped_early: bool,
+    /// Positions are final rather than moved onto the top-left of the elements they place
+    pub anchored: bool,
+}
+
+pub fn is_pinned(properties: &HashMap<String, String>) -> bool {
+    properties.get("pinned").is_some_and(|p| p.trim().eq_ignore_ascii_case("true"))
+}
+
+/// Elements grouped by their container, in order of first appearance; top-level ones get `None`
+fn groups(state: &ProjectState) -> (Vec<Option<usize>>, usize) {
+    let mut names: Vec<&str> = Vec::new();
+    let membership = state
+        .diagram
+        .iter()
+        .map(|e| {
+            let name = e.parent_id.as_deref()?;
+            Some(names.iter().position(|n| *n == name).unwrap_or_else(|| {
+                names.push(name);
+                names.len() - 1
+            }))
+        })
+        .collect();
+    (membership, names.len())
+}
+
+/// Element graph over diagram connections, as indices into `state.diagram`
+fn element_edges(state: &ProjectState) -> Vec<(usize, usize)> {
+    let index: HashMap<&str, usize> = state.diagram.iter().enumerate().map(|(i, e)| (e.id.as_str(), i)).collect();
+    let mut edges = Vec::new();
+    for connection in &state.connections {
+        let from = index.get(connection.source_id.as_str());
+        let to = index.get(connection.target_id.as_str());
+        if let (Some(&from), Some(&to)) = (from, to) {
+            if from != to && !edges.contains(&(from, to)) {
+                edges.push((from, to));
+            }
+        }
+    }
+    edges
+}
+
+// Reverses the edges that close a cycle in a depth-first walk, leaving a DAG
+fn break_cycles(count: usize, edges: &mut [(usize, usize)]) {
+    let mut outgoing = vec![Vec::new(); count];
+    for (i, &(from, _)) in edges.iter().enumerate() {
+        outgoing[from].push(i);
+    }
+    // 0 = unvisited, 1 = on the stack, 2 = done
+    let mut mark = vec![0u8; count];
+    let mut reverse = Vec::new();
+    for root in 0..count {
+        if mark[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        mark[root] = 1;
+        while let Some((node, next)) = stack.pop() {
+            match outgoing[node].get(next) {
+                Some(&edge) => {
+                    stack.push((node, next + 1));
+                    let to = edges[edge].1;
+                    match mark[to] {
+                        0 => {
+                            mark[to] = 1;
+                            stack.push((to, 0));
+                        }
+                        1 => reverse.push(edge),
+                        _ => {}
+                    }
+                }
+                None => mark[node] = 2,
+            }
+        }
+    }
+    for edge in reverse {
+        let (from, to) = edges[edge];
+        edges[edge] = (to, from);
+    }
+}
+
+fn assign_layers(count: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut preds = vec![Vec::new(); count];
+    let mut succs = vec![Vec::new(); count];
+    for &(from, to) in edges {
+        preds[to].push(from);
+        succs[from].push(to);
+    }
+    let mut remaining: Vec<usize> = preds.iter().map(Vec::len).collect();
+    let mut order: Vec<usize> = (0..count).filter(|&n| remaining[n] == 0).collect();
+    let mut next = 0;
+    while let Some(&node) = order.get(next) {
+        next += 1;
+        for &succ in &succs[node] {
+            remaining[succ] -= 1;
+            if remaining[succ] == 0 {
+                order.push(succ);
+            }
+        }
+    }
+
+    let mut layer = vec![0; count];
+    for &node in &order {
+        for &succ in &succs[node] {
+            layer[succ] = layer[succ].max(layer[node] + 1);
+        }
+    }
+    // Sources sit right above their nearest successor instead of all at the top
+    for &node in order.iter().rev() {
+        if preds[node].is_empty() {
+            if let Some(nearest) = succs[node].iter().map(|&s| layer[s]).min() {
+                layer[node] = nearest - 1;
+            }
+        }
+    }
+    layer
+}
+
+struct Layered {
+    layers: Vec<Vec<usize>>,
+    preds: Vec<Vec<usize>>,
+    succs: Vec<Vec<usize>>,
+    // Real elements first, then dummies for edges that span layers
+    real: usize,
+}
+
+impl Layered {
+    fn build(count: usize, edges: &[(usize, usize)], layer: &[usize]) -> Self {
+        let mut layer = layer.to_vec();
+        let mut preds = vec![Vec::new(); count];
+        let mut succs = vec![Vec::new(); count];
+        for &(from, to) in edges {
+            let mut previous = from;
+            for dummy_layer in layer[from] + 1..layer[to] {
+                let dummy = layer.len();
+                layer.push(dummy_layer);
+                preds.push(vec![previous]);
+                succs.push(Vec::new());
+                succs[previous].push(dummy);
+                previous = dummy;
+            }
+            succs[previous].push(to);
+            preds[to].push(previous);
+        }
+        let depth = layer.iter().max().map_or(0, |l| l + 1);
+        let mut layers = vec![Vec::new(); depth];
+        for (node, &l) in layer.iter().enumerate() {
+            layers[l].push(node);
+        }
+        Self {
+            layers,
+            preds,
+            succs,
+            real: count,
+        }
+    }
+
+    fn crossings(&self) -> usize {
+        let mut position = vec![0; self.preds.len()];
+        for layer in &self.layers {
+            for (i, &node) in layer.iter().enumerate() {
+                position[node] = i;
+            }
+        }
+        let mut total = 0;
+        for layer in &self.layers {
+            let edges: Vec<(usize, usize)> = layer
+                .iter()
+                .flat_map(|&from| self.succs[from].iter().map(move |&to| (from, to)))
+                .map(|(from, to)| (position[from], position[to]))
+                .collect();
+            for (i, a) in edges.iter().enumerate() {
+                total += edges[i + 1..]
+                    .iter()
+                    .filter(|b| (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1))
+                    .count();
+            }
+        }
+        total
+    }
+
+    // Orders each layer by the mean position of its neighbours in the layer just swept
+    fn sweep(&mut self, downwards: bool) {
+        let mut position = vec![0.0; self.preds.len()];
+        for layer in &self.layers {
+            for (i, &node) in layer.iter().enumerate() {
+                position[node] = i as f64;
+            }
+        }
+        let indices: Vec<usize> = if downwards {
+            (1..self.layers.len()).collect()
+        } else {
+            (0..self.layers.len().saturating_sub(1)).rev().collect()
+        };
+        for l in indices {
+            let neighbours = if downwards { &self.preds } else { &self.succs };
+            let mut keyed: Vec<(f64, usize)> = self.layers[l]
+                .iter()
+                .map(|&node| {
+                    let adjacent = &neighbours[node];
+                    let key = if adjacent.is_empty() {
+                        position[node]
+                    } else {
+                        adjacent.iter().map(|&n| position[n]).sum::<f64>() / adjacent.len() as f64
+                    };
+                    (key, node)
+                })
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            self.layers[l] = keyed.into_iter().map(|(_, node)| node).collect();
+            for (i, &node) in self.layers[l].iter().enumerate() {
+                position[node] = i as f64;
+            }
+        }
+    }
+
+    /// Gathers the elements of each container together, at the group's average place in the layer
+    fn cluster(&mut self, membership: &[Option<usize>]) {
+        for layer in &mut self.layers {
+            let mut totals: HashMap<usize, (f64, usize)> = HashMap::new();
+            for (i, &node) in layer.iter().enumerate() {
+                if let Some(group) = membership.get(node).copied().flatten() {
+                    let total = totals.entry(group).or_default();
+                    *total = (total.0 + i as f64, total.1 + 1);
+                }
+            }
+            let mut keyed: Vec<(f64, Option<usize>, usize, usize)> = layer
+                .iter()
+                .enumerate()
+                .map(|(i, &node)| {
+                    let group = membership.get(node).copied().flatten();
+                    let key = group.map_or(i as f64, |g| totals[&g].0 / totals[&g].1 as f64);
+                    (key, group, i, node)
+                })
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+            *layer = keyed.into_iter().map(|(_, _, _, node)| node).collect();
+        }
+    }
+
+    fn minimize_crossings(&mut self) {
+        let mut best = (self.crossings(), self.layers.clone());
+        for i in 0..ORDERING_SWEEPS {
+            if best.0 == 0 {
+                break;
+            }
+            self.sweep(i % 2 == 0);
+            let crossings = self.crossings();
+            if crossings < best.0 {
+                best = (crossings, self.layers.clone());
+            }
+        }
+        self.layers = best.1;
+    }
+
+    /// Centre of every node along the layer, keeping neighbours as aligned as spacing allows
+    fn place(&self, sizes: &[f64], spacing: f64) -> Vec<f64> {
+        let width = |node: usize| if node < self.real { sizes[node] } else { 0.0 };
+        let gap = |a: usize, b: usize| {
+            let spacing = if a < self.real && b < self.real { spacing } else { spacing / 2.0 };
+            (width(a) + width(b)) / 2.0 + spacing
+        };
+
+        let mut x = vec![0.0; self.preds.len()];
+        for layer in &self.layers {
+            for pair in layer.windows(2) {
+                x[pair[1]] = x[pair[0]] + gap(pair[0], pair[1]);
+            }
+        }
+        for i in 0..PLACEMENT_SWEEPS {
+            let downwards = i % 2 == 0;
+            let neighbours = if downwards { &self.preds } else { &self.succs };
+            for layer in &self.layers {
+                let desired: Vec<f64> = layer
+                    .iter()
+                    .map(|&node| match neighbours[node].len() {
+                        0 => x[node],
+                        n => neighbours[node].iter().map(|&m| x[m]).sum::<f64>() / n as f64,
+                    })
+                    .collect();
+                let mut placed = desired.clone();
+                for j in 1..layer.len() {
+                    placed[j] = placed[j].max(placed[j - 1] + gap(layer[j - 1], layer[j]));
+                }
+                // Pushing only rightwards drifts; shift back so the layer sits on its targets
+                let drift = placed.iter().zip(&desired).map(|(p, d)| p - d).sum::<f64>() / layer.len().max(1) as f64;
+                for (&node, p) in layer.iter().zip(placed) {
+                    x[node] = p - drift;
+                }
+            }
+        }
+        x
+    }
+}
+
+pub fn layered(state: &ProjectState, options: &LayoutOptions) -> Layout {
+    let diagram = &state.diagram;
+    // Containers are refitted around their contents afterwards, so they don't get a layer
+    let nodes: Vec<usize> =
+        (0..diagram.len()).filter(|&i| !containment::is_container(diagram, &diagram[i].id)).collect();
+    let count = nodes.len();
+    let slot: HashMap<usize, usize> = nodes.iter().enumerate().map(|(node, &i)| (i, node)).collect();
+    let mut edges: Vec<(usize, usize)> = element_edges(state)
+        .into_iter()
+        .filter_map(|(from, to)| Some((*slot.get(&from)?, *slot.get(&to)?)))
+        .collect();
+    break_cycles(count, &mut edges);
+    let layer = assign_layers(count, &edges);
+    let mut graph = Layered::build(count, &edges, &layer);
+    graph.minimize_crossings();
+    let membership = groups(state).0;
+    graph.cluster(&nodes.iter().map(|&i| membership[i]).collect::<Vec<_>>());
+    let crossings = graph.crossings();
+
+    let axes = |x: f64, y: f64| match options.direction {
+        LayoutDirection::TopBottom => (x, y),
+        LayoutDirection::LeftRight => (y, x),
+    };
+    let pinned: Vec<bool> = nodes.iter().map(|&i| is_pinned(&diagram[i].properties)).collect();
+    let (across, along): (Vec<f64>, Vec<f64>) =
+        nodes.iter().map(|&i| axes(diagram[i].width, diagram[i].height)).unzip();
+    // Pinned elements keep their place in the order but take no room in the layer; the others
+    // are moved clear of them once placed
+    let widths: Vec<f64> = across.iter().zip(&pinned).map(|(&w, &p)| if p { 0.0 } else { w }).collect();
+    // Layers are as deep as the deepest element so they stay evenly spaced
+    let depth = along.iter().copied().fold(0.0, f64::max) + options.layer_spacing;
+    let x = graph.place(&widths, options.node_spacing);
+    let mut left: Vec<f64> = (0..count).map(|node| x[node] - widths[node] / 2.0).collect();
+    let top: Vec<f64> = (0..count).map(|node| layer[node] as f64 * depth).collect();
+
+    // Anchor at the top-left of the elements being moved, where the pinned ones are measured from
+    let free: Vec<usize> = (0..count).filter(|&node| !pinned[node]).collect();
+    let now = free.iter().map(|&node| axes(diagram[nodes[node]].position.x, diagram[nodes[node]].position.y));
+    let (now_across, now_along) = now.fold((f64::INFINITY, f64::INFINITY), |(a, d), (x, y)| (a.min(x), d.min(y)));
+    let laid_across = free.iter().map(|&node| left[node]).fold(f64::INFINITY, f64::min);
+    let laid_along = free.iter().map(|&node| top[node]).fold(f64::INFINITY, f64::min);
+    let (da, dd) = if free.is_empty() {
+        (0.0, 0.0)
+    } else {
+        (now_across - laid_across, now_along - laid_along)
+    };
+    let top: Vec<f64> = top.into_iter().map(|t| t + dd).collect();
+    for l in &mut left {
+        *l += da;
+    }
+
+    let reserved: Vec<(f64, f64, f64, f64)> = (0..count)
+        .filter(|&node| pinned[node])
+        .map(|node| {
+            let bounds = Bounds::of(&diagram[nodes[node]]);
+            let (a, d) = axes(bounds.x, bounds.y);
+            let (width, height) = axes(bounds.width, bounds.height);
+            (a, d, a + width, d + height)
+        })
+        .collect();
+    let spacing = options.node_spacing;
+    for row in &graph.layers {
+        let row: Vec<usize> = row.iter().copied().filter(|&node| node < count && !pinned[node]).collect();
+        for (j, &node) in row.iter().enumerate() {
+            // Pushing the rest of the layer along with it keeps the gaps already there
+            while let Some(edge) = reserved
+                .iter()
+                .filter(|r| {
+                    left[node] < r.2 + spacing
+                        && left[node] + across[node] > r.0 - spacing
+                        && top[node] < r.3
+                        && top[node] + along[node] > r.1
+                })
+                .map(|r| r.2 + spacing)
+                .reduce(f64::max)
+            {
+                let push = edge - left[node];
+                for &later in &row[j..] {
+                    left[later] += push;
+                }
+            }
+        }
+    }
+
+    let positions = free
+        .iter()
+        .map(|&node| {
+            let (x, y) = axes(left[node], top[node]);
+            (diagram[nodes[node]].id.clone(), Position { x, y })
+        })
+        .collect();
+    Layout {
+        positions,
+        crossings: Some(crossings),
+        stopped_early: false,
+        anchored: true,
+    }
+}
+
+pub fn force_directed(state: &ProjectState, options: &LayoutOptions) -> Layout {
+    let count = state.diagram.len();
+    let edges = element_edges(state);
+    let (membership, group_count) = groups(state);
+    let pinned: Vec<bool> = state.diagram.iter().map(|e| is_pinned(&e.properties)).collect();
+    let largest = state.diagram.iter().map(|e| e.width.max(e.height)).fold(0.0, f64::max);
+    let ideal = largest + options.node_spacing;
+    let side = ideal * (count.max(1) as f64).sqrt();
+
+    // Centres; pinned elements start (and stay) where they are
+    let mut rng = Rng::new(options.seed);
+    let mut pos: Vec<(f64, f64)> = state
+        .diagram
+        .iter()
+        .zip(&pinned)
+        .map(|(e, &pinned)| match pinned {
+            true => (e.position.x + e.width / 2.0, e.position.y + e.height / 2.0),
+            false => (rng.next_f64() * side, rng.next_f64() * side),
+        })
+        .collect();
+
+    let deadline = Instant::now() + Duration::from_millis(options.time_limit_ms);
+    let iterations = options.iterations.max(1);
+    let mut temperature = side / 10.0;
+    let cooling = temperature / iterations as f64;
+    let cell = 2.0 * ideal;
+    let mut stopped_early = false;
+    for _ in 0..iterations {
+        if Instant::now() >= deadline {
+            stopped_early = true;
+            break;
+        }
+        let mut shift = vec![(0.0, 0.0); count];
+
+        // Repulsion only matters up close, so compare each element with its neighbouring cells
+        let key = |p: (f64, f64)| ((p.0 / cell).floor() as i64, (p.1 / cell).floor() as i64);
+        let mut buckets: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, &p) in pos.iter().enumerate() {
+            buckets.entry(key(p)).or_default().push(i);
+        }
+        for i in 0..count {
+            let (cx, cy) = key(pos[i]);
+            for bucket in (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (cx + dx, cy + dy))) {
+                for &j in buckets.get(&bucket).into_iter().flatten().filter(|&&j| j > i) {
+                    let (mut dx, mut dy) = (pos[i].0 - pos[j].0, pos[i].1 - pos[j].1);
+                    if dx * dx + dy * dy < 1e-6 {
+                        dx = rng.next_f64() - 0.5;
+                        dy = rng.next_f64() - 0.5;
+                    }
+                    let distance = (dx * dx + dy * dy).sqrt();
+                    if distance > cell {
+                        continue;
+                    }
+                    let force = ideal * ideal / distance;
+                    shift[i].0 += dx / distance * force;
+                    shift[i].1 += dy / distance * force;
+                    shift[j].0 -= dx / distance * force;
+                    shift[j].1 -= dy / distance * force;
+                }
+            }
+        }
+
+        let mut attract = |a: usize, to: (f64, f64), strength: f64| {
+            let (dx, dy) = (pos[a].0 - to.0, pos[a].1 - to.1);
+            let distance = (dx * dx + dy * dy).sqrt().max(0.01);
+            let force = strength * distance * distance / ideal;
+            shift[a].0 -= dx / distance * force;
+            shift[a].1 -= dy / distance * force;
+        };
+        for &(a, b) in &edges {
+            attract(a, pos[b], 1.0);
+            attract(b, pos[a], 1.0);
+        }
+        let mut centres = vec![(0.0, 0.0, 0usize); group_count];
+        for (i, group) in membership.iter().enumerate() {
+            if let Some(g) = *group {
+                centres[g] = (centres[g].0 + pos[i].0, centres[g].1 + pos[i].1, centres[g].2 + 1);
+            }
+        }
+        for (i, group) in membership.iter().enumerate() {
+            if let Some(g) = *group {
+                let (x, y, n) = centres[g];
+                attract(i, (x / n as f64, y / n as f64), 1.0);
+            }
+        }
+        let middle = pos.iter().fold((0.0, 0.0), |acc, p| (acc.0 + p.0, acc.1 + p.1));
+        let middle = (middle.0 / count.max(1) as f64, middle.1 / count.max(1) as f64);
+        for (i, s) in shift.iter_mut().enumerate() {
+            s.0 -= (pos[i].0 - middle.0) * GRAVITY * ideal / side;
+            s.1 -= (pos[i].1 - middle.1) * GRAVITY * ideal / side;
+        }
+
+        for (i, (dx, dy)) in shift.into_iter().enumerate() {
+            let length = (dx * dx + dy * dy).sqrt();
+            if pinned[i] || length == 0.0 {
+                continue;
+            }
+            let step = length.min(temperature);
+            pos[i].0 += dx / length * step;
+            pos[i].1 += dy / length * step;
+        }
+        temperature = (temperature - cooling).max(ideal * 0.01);
+    }
+
+    let positions = state
+        .diagram
+        .iter()
+        .zip(pos)
+        .map(|(e, (x, y))| {
+            let position = Position {
+                x: x - e.width / 2.0,
+                y: y - e.height / 2.0,
+            };
+            (e.id.clone(), position)
+        })
+        .collect();
+    Layout {
+        positions,
+        crossings: None,
+        stopped_early,
+        anchored: false,
+    }
+}
+
+/// Packs each group into a near-square block, ordered so connected elements sit together,
+/// then shelves the blocks largest first
+pub fn grid(state: &ProjectState, options: &LayoutOptions) -> Layout {
+    let (membership, group_count) = groups(state);
+    let free: Vec<usize> = (0..state.diagram.len())
+        .filter(|&i| {
+            let element = &state.diagram[i];
+            !is_pinned(&element.properties) && !containment::is_container(&state.diagram, &element.id)
+        })
+        .collect();
+    let mut neighbours = vec![Vec::new(); state.diagram.len()];
+    for (a, b) in element_edges(state) {
+        neighbours[a].push(b);
+        neighbours[b].push(a);
+    }
+
+    // Ungrouped elements share one extra block
+    let mut blocks: Vec<Vec<usize>> = vec![Vec::new(); group_count + 1];
+    for &i in &free {
+        blocks[membership[i].unwrap_or(group_count)].push(i);
+    }
+    let blocks: Vec<Vec<usize>> = blocks
+        .into_iter()
+        .filter(|b| !b.is_empty())
+        .map(|members| {
+            let mut ordered = Vec::with_capacity(members.len());
+            let mut seen = vec![false; state.diagram.len()];
+            for &start in &members {
+                let mut queue = VecDeque::from([start]);
+                while let Some(node) = queue.pop_front() {
+                    if seen[node] || membership[node] != membership[start] || !members.contains(&node) {
+                        continue;
+                    }
+                    seen[node] = true;
+                    ordered.push(node);
+                    queue.extend(&neighbours[node]);
+                }
+            }
+            ordered
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..blocks.len()).collect();
+    order.sort_by_key(|&b| std::cmp::Reverse(blocks[b].len()));
+
+    // Cells fit the largest element being placed
+    let largest = |size: fn(&DiagramElement) -> f64| free.iter().map(|&i| size(&state.diagram[i])).fold(0.0, f64::max);
+    let (cell_w, cell_h) = (largest(|e| e.width) + options.node_spacing, largest(|e| e.height) + options.node_spacing);
+    let block_gap = options.node_spacing * 2.0;
+    let widest = blocks.iter().map(|b| (b.len() as f64).sqrt().ceil()).fold(1.0, f64::max);
+    let row_width = (free.len() as f64).sqrt().ceil().max(widest) * cell_w;
+
+    let mut positions = HashMap::new();
+    let (mut x, mut y, mut shelf_height) = (0.0, 0.0, 0.0f64);
+    for b in order {
+        let members = &blocks[b];
+        let columns = (members.len() as f64).sqrt().ceil() as usize;
+        let rows = members.len().div_ceil(columns);
+        let width = columns as f64 * cell_w;
+        if x > 0.0 && x + width > row_width {
+            x = 0.0;
+            y += shelf_height + block_gap;
+            shelf_height = 0.0;
+        }
+        for (i, &element) in members.iter().enumerate() {
+            let position = Position {
+                x: x + (i % columns) as f64 * cell_w,
+                y: y + (i / columns) as f64 * cell_h,
+            };
+            positions.insert(state.diagram[element].id.clone(), position);
+        }
+        x += width + block_gap;
+        shelf_height = shelf_height.max(rows as f64 * cell_h);
+    }
+    Layout {
+        positions,
+        crossings: None,
+        stopped_early: false,
+        anchored: false,
+    }
+}
+
+/// Moves for every unpinned element, anchored at the top-left of the elements being moved,
+/// then containers refitted around their children
+pub fn layout_ops(state: &ProjectState, layout: Layout) -> Vec<DiagramOp> {
+    let diagram = &state.diagram;
+    let free: Vec<_> = diagram
+        .iter()
+        .filter(|e| !is_pinned(&e.properties) && !containment::is_container(diagram, &e.id))
+        .collect();
+    let origin = |current: fn(&Position) -> f64, new: fn(&Position) -> f64| {
+        let now = free.iter().map(|e| current(&e.position)).fold(f64::INFINITY, f64::min);
+        let laid = free
+            .iter()
+            .filter_map(|e| layout.positions.get(&e.id))
+            .map(new)
+            .fold(f64::INFINITY, f64::min);
+        if now.is_finite() && laid.is_finite() {
+            now - laid
+        } else {
+            0.0
+        }
+    };
+    let (dx, dy) = if layout.anchored {
+        (0.0, 0.0)
+    } else {
+        (origin(|p| p.x, |p| p.x), origin(|p| p.y, |p| p.y))
+    };
+    // Pinned elements stay put even when their container moves
+    let mut targets: HashMap<String, Position> = diagram
+        .iter()
+        .filter(|e| is_pinned(&e.properties))
+        .map(|e| (e.id.clone(), e.position.clone()))
+        .collect();
+    targets.extend(free.iter().filter_map(|element| {
+        let target = layout.positions.get(&element.id)?;
+        let position = Position {
+            x: target.x + dx,
+            y: target.y + dy,
+        };
+        Some((element.id.clone(), position))
+    }));
+
+    // Innermost first, so outer containers wrap the refitted inner ones
+    let mut containers: Vec<&DiagramElement> = diagram
+        .iter()
+        .filter(|e| !is_pinned(&e.properties) && containment::is_container(diagram, &e.id))
+        .collect();
+    containers.sort_by_key(|e| std::cmp::Reverse(containment::ancestors(diagram, &e.id).len()));
+    let mut sizes: HashMap<&str, (f64, f64)> = HashMap::new();
+    for container in containers {
+        let children = diagram.iter().filter(|e| e.parent_id.as_deref() == Some(container.id.as_str())).map(|child| {
+            let mut placed = child.clone();
+            if let Some(target) = targets.get(&child.id) {
+                placed.position = target.clone();
+            }
+            if let Some(&(width, height)) = sizes.get(child.id.as_str()) {
+                (placed.width, placed.height) = (width, height);
+            }
+            Bounds::of(&placed)
+        });
+        if let Some(fit) = containment::wrap(children) {
+            targets.insert(container.id.clone(), Position { x: fit.x, y: fit.y });
+            sizes.insert(&container.id, (fit.width, fit.height));
+        }
+    }
+
+    let mut ops = containment::move_ops(state, &targets);
+    ops.extend(diagram.iter().filter_map(|e| {
+        let &(width, height) = sizes.get(e.id.as_str())?;
+        ((width, height) != (e.width, e.height)).then(|| DiagramOp::UpdateElement {
+            id: e.id.clone(),
+            element_type: None,
+            width: Some(width),
+            height: Some(height),
+            rotation: None,
+            z_index: None,
+            parent_id: None,
+            component_id: None,
+            properties: HashMap::new(),
+        })
+    }));
+    ops
+}
+
+#[tauri::command]
+pub async fn auto_layout(
+    project_id: String,
+    diagram_id: Option<String>,
+    options: Option<LayoutOptions>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<LayoutResult, ApiError> {
+    let mut options = options.unwrap_or_default();
+    options.validate()?;
+    let algorithm = options.algorithm;
+    let diagram_id = diagram_id.as_deref();
+
+    // Layouts only read the diagram, so they run on a copy of it without holding the lock
+    let snapshot = snapshot_diagram(&projects, &project_id, diagram_id, expected_revision)?;
+    let revision = snapshot.diagram_revision;
+    let layout = tokio::task::spawn_blocking(move || match options.algorithm {
+        LayoutAlgorithm::Layered => layered(&snapshot, &options),
+        LayoutAlgorithm::Force => force_directed(&snapshot, &options),
+        LayoutAlgorithm::Grid => grid(&snapshot, &options),
+    })
+    .await
+    .map_err(|e| ApiError::Internal {
+        details: format!("Diagram layout failed: {}", e),
+        source: Some(Box::new(e)),
+    })?;
+
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    on_diagram(&mut state, diagram_id, |state| {
+        // Edits made while the layout ran would be overwritten with positions computed before them
+        check_diagram_revision(state, diagram_id, Some(revision))?;
+        let (crossings, stopped_early) = (layout.crossings, layout.stopped_early);
+        let ops = layout_ops(state, layout);
+        let moved = ops.len();
+        let inverse = commit_patch(state, diagram_id, ops, &app, &changes)?;
+        log::info!("Laid out {} elements in project {} ({:?})", moved, project_id, algorithm);
+        Ok(LayoutResult {
+            diagram_revision: state.diagram_revision,
+            moved,
+            crossings,
+            stopped_early,
+            inverse,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagram_patch::apply_patch;
+    use crate::test_support::{connection, diagram_state, element};
+
+    fn graph(ids: &[&str], edges: &[(&str, &str)]) -> ProjectState {
+        diagram_state(
+            ids.iter().map(|id| element(id)).collect(),
+            edges
+                .iter()
+                .map(|(from, to)| connection(&format!("{}-{}", from, to), from, to))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn layers_follow_edges_in_both_directions() {
+        let state = graph(&["web", "api", "db", "cache"], &[("web", "api"), ("api", "db"), ("api", "cache"), ("db", "web")]);
+        let layout = layered(&state, &LayoutOptions::default());
+        let y = |id: &str| layout.positions[id].y;
+        // The db -> web edge closes a cycle and is reversed rather than followed
+        assert!(y("web") < y("api") && y("api") < y("db"));
+        assert_eq!(y("db"), y("cache"));
+        assert!((layout.positions["db"].x - layout.positions["cache"].x).abs() >= 160.0);
+
+        let options = LayoutOptions {
+            direction: LayoutDirection::LeftRight,
+            ..LayoutOptions::default()
+        };
+        let sideways = layered(&state, &options);
+        let x = |id: &str| sideways.positions[id].x;
+        assert!(x("web") < x("api") && x("api") < x("db"));
+        assert_eq!(sideways.positions["db"].x, sideways.positions["cache"].x);
+    }
+
+    #[test]
+    fn reorders_layers_to_remove_crossings() {
+        // a -> d and b -> c cross when laid out in declaration order
+        let state = graph(&["a", "b", "c", "d"], &[("a", "d"), ("b", "c")]);
+        let layout = layered(&state, &LayoutOptions::default());
+        assert_eq!(layout.crossings, Some(0));
+        let x = |id: &str| layout.positions[id].x;
+        assert_eq!(x("a") < x("b"), x("d") < x("c"));
+    }
+
+    fn grouped(count: usize, groups: usize) -> ProjectState {
+        let ids: Vec<String> = (0..count).map(|i| format!("e{}", i)).collect();
+        let refs: Vec<&str> = ids.iter().map(String::as_str).collect();
+        // A ring inside each group, so every group is connected but no two are
+        let edges: Vec<(String, String)> = (0..count)
+            .filter(|i| (i + groups) < count)
+            .map(|i| (ids[i].clone(), ids[i + groups].clone()))
+            .collect();
+        let edge_refs: Vec<(&str, &str)> = edges.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect();
+        let mut state = graph(&refs, &edge_refs);
+        for (i, element) in state.diagram.iter_mut().enumerate() {
+            element.parent_id = Some(format!("g{}", i % groups));
+        }
+        state.diagram.extend((0..groups).map(|g| element(&format!("g{}", g))));
+        state
+    }
+
+    fn centre(layout: &Layout, state: &ProjectState, group: &str) -> (f64, f64) {
+        let members: Vec<&Position> = state
+            .diagram
+            .iter()
+            .filter(|e| e.parent_id.as_deref() == Some(group))
+            .map(|e| &layout.positions[&e.id])
+            .collect();
+        let n = members.len() as f64;
+        (members.iter().map(|p| p.x).sum::<f64>() / n, members.iter().map(|p| p.y).sum::<f64>() / n)
+    }
+
+    #[test]
+    fn force_layout_is_seeded_and_keeps_groups_together() {
+        let state = grouped(24, 2);
+        let options = LayoutOptions {
+            algorithm: LayoutAlgorithm::Force,
+            seed: 42,
+            ..LayoutOptions::default()
+        };
+        let a = force_directed(&state, &options);
+        let b = force_directed(&state, &options);
+        assert!(!a.stopped_early);
+        assert!(state.diagram.iter().all(|e| a.positions[&e.id] == b.positions[&e.id]));
+        let other = force_directed(&state, &LayoutOptions { seed: 7, ..options });
+        assert!(state.diagram.iter().any(|e| a.positions[&e.id] != other.positions[&e.id]));
+
+        // Every element is nearer its own group's centre than the other group's
+        let centres = [centre(&a, &state, "g0"), centre(&a, &state, "g1")];
+        let distance = |p: &Position, c: (f64, f64)| ((p.x - c.0).powi(2) + (p.y - c.1).powi(2)).sqrt();
+        for (i, element) in state.diagram.iter().enumerate().filter(|(_, e)| e.parent_id.is_some()) {
+            let p = &a.positions[&element.id];
+            assert!(distance(p, centres[i % 2]) < distance(p, centres[1 - i % 2]), "{}", element.id);
+        }
+    }
+
+    #[test]
+    fn grid_packs_groups_into_separate_blocks() {
+        let state = grouped(30, 3);
+        let layout = grid(&state, &LayoutOptions::default());
+        let mut seen: Vec<(f64, f64)> = Vec::new();
+        for p in layout.positions.values() {
+            assert!(!seen.contains(&(p.x, p.y)));
+            seen.push((p.x, p.y));
+        }
+        // Group bounding boxes don't overlap
+        let bounds: Vec<(f64, f64, f64, f64)> = ["g0", "g1", "g2"]
+            .iter()
+            .map(|g| {
+                let ps: Vec<&Position> = state
+                    .diagram
+                    .iter()
+                    .filter(|e| e.parent_id.as_deref() == Some(*g))
+                    .map(|e| &layout.positions[&e.id])
+                    .collect();
+                let fold = |f: fn(&Position) -> f64, pick: fn(f64, f64) -> f64, start: f64| {
+                    ps.iter().map(|p| f(p)).fold(start, pick)
+                };
+                (
+                    fold(|p| p.x, f64::min, f64::INFINITY),
+                    fold(|p| p.y, f64::min, f64::INFINITY),
+                    fold(|p| p.x, f64::max, f64::NEG_INFINITY) + 160.0,
+                    fold(|p| p.y, f64::max, f64::NEG_INFINITY) + 80.0,
+                )
+            })
+            .collect();
+        for (i, a) in bounds.iter().enumerate() {
+            for b in &bounds[i + 1..] {
+                assert!(a.2 <= b.0 || b.2 <= a.0 || a.3 <= b.1 || b.3 <= a.1, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn large_diagrams_finish_within_the_time_limit() {
+        let state = grouped(1_200, 12);
+        let options = LayoutOptions {
+            algorithm: LayoutAlgorithm::Force,
+            time_limit_ms: 300,
+            ..LayoutOptions::default()
+        };
+        let started = Instant::now();
+        let layout = force_directed(&state, &options);
+        assert!(started.elapsed() < Duration::from_secs(3));
+        // Containers are placed too, though only to be refitted afterwards
+        assert_eq!(layout.positions.len(), 1_212);
+        assert!(layout.positions.values().all(|p| p.x.is_finite() && p.y.is_finite()));
+        assert_eq!(grid(&state, &options).positions.len(), 1_200);
+
+        let mut patient = LayoutOptions {
+            time_limit_ms: u64::MAX,
+            ..LayoutOptions::default()
+        };
+        patient.validate().unwrap();
+        assert_eq!(patient.time_limit_ms, MAX_TIME_LIMIT_MS);
+    }
+
+    #[test]
+    fn pinned_elements_stay_put() {
+        let mut state = graph(&["a", "b", "c"], &[("a", "b"), ("b", "c")]);
+        state.diagram[1].position = Position { x: 500.0, y: 500.0 };
+        state.diagram[1].properties.insert("pinned".into(), "true".into());
+
+        let layout = layered(&state, &LayoutOptions::default());
+        let ops = layout_ops(&state, layout);
+        assert!(ops.iter().all(|op| !matches!(op, DiagramOp::MoveElement { id, .. } if id == "b")));
+        apply_patch(&mut state, &ops).unwrap();
+        assert_eq!(state.diagram[1].position, Position { x: 500.0, y: 500.0 });
+        assert!(state.diagram[0].position.y < state.diagram[2].position.y);
+    }
+
+    #[test]
+    fn layers_keep_clear_of_pinned_elements() {
+        let mut state = graph(&["a", "b", "c"], &[("a", "b"), ("a", "c")]);
+        let free = layered(&state, &LayoutOptions::default());
+        let mut pin = element("pin");
+        pin.position = free.positions["b"].clone();
+        pin.properties.insert("pinned".into(), "true".into());
+        state.diagram.push(pin);
+
+        let layout = layered(&state, &LayoutOptions::default());
+        assert!(!layout.positions.contains_key("pin"));
+        let ops = layout_ops(&state, layout);
+        apply_patch(&mut state, &ops).unwrap();
+        let overlaps = crate::geometry::find_overlaps(&state.diagram, 0.0);
+        assert!(overlaps.is_empty(), "{:?}", overlaps);
+    }
+
+    #[test]
+    fn containers_are_left_out_of_the_layers() {
+        let mut state = graph(&["a", "b", "g"], &[("a", "b"), ("g", "a")]);
+        state.diagram[0].parent_id = Some("g".into());
+        let layout = layered(&state, &LayoutOptions::default());
+        assert!(!layout.positions.contains_key("g"));
+        assert_eq!(layout.positions["a"].y, 0.0);
+        assert!(layout.positions["a"].y < layout.positions["b"].y);
+    }
+
+    #[test]
+    fn containers_are_refitted_around_their_children() {
+        let mut state = grouped(6, 2);
+        // g1 sits inside g0, so it has to be fitted first
+        state.diagram.last_mut().unwrap().parent_id = Some("g0".into());
+        let layout = grid(&state, &LayoutOptions::default());
+        let ops = layout_ops(&state, layout);
+        apply_patch(&mut state, &ops).unwrap();
+
+        let find = |id: &str| Bounds::of(state.diagram.iter().find(|e| e.id == id).unwrap());
+        let inside = |inner: &Bounds, outer: &Bounds| {
+            inner.x >= outer.x
+                && inner.y >= outer.y
+                && inner.x + inner.width <= outer.x + outer.width
+                && inner.y + inner.height <= outer.y + outer.height
+        };
+        for element in state.diagram.iter().filter(|e| e.parent_id.is_some()) {
+            let parent = element.parent_id.as_deref().unwrap();
+            assert!(inside(&Bounds::of(element), &find(parent)), "{} escapes {}", element.id, parent);
+        }
+    }
+}
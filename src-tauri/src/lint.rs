@@ -0,0 +1,503 @@
+// Architecture lint engine
+//
+// Rules inspect a read-only `LintContext` built from one project's state and push
+// findings. Built-in rules live here; anything implementing `LintRule` can be run
+// alongside them.
+
+use crate::availability;
+use crate::containment;
+use crate::geometry::Bounds;
+use crate::lint_rules;
+use crate::state::{ProjectState, ProjectStore};
+use crate::system_graph::{EdgeVia, SystemGraph};
+use crate::{ApiError, ComponentType};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, State};
+
+// Metadata keys that count as a documented backup strategy
+const BACKUP_KEYS: &[&str] = &["backup", "backup_policy", "backup_schedule", "backups"];
+
+// A node this many direct dependents without redundancy is a single point of failure
+const SPOF_MIN_DEPENDENTS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintFinding {
+    pub rule_id: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Components, diagram elements or connections involved
+    pub entity_ids: Vec<String>,
+    pub suggestion: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintReport {
+    pub project_id: String,
+    /// Most severe first
+    pub findings: Vec<LintFinding>,
+    pub counts: HashMap<Severity, usize>,
+    /// User rule files that failed to load and were skipped
+    pub rule_errors: Vec<String>,
+}
+
+/// Coarse role of a node, from a component's type or a diagram element's type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    Frontend,
+    Database,
+    Other,
+}
+
+fn element_role(element_type: &str) -> NodeRole {
+    let element_type = element_type.to_lowercase();
+    let is = |words: &[&str]| words.iter().any(|w| element_type.contains(w));
+    if is(&["database", "datastore", "postgres", "mysql", "mongo", "sql"]) || element_type == "db" {
+        NodeRole::Database
+    } else if is(&["frontend", "client", "browser", "mobile", "web"]) || element_type == "ui" {
+        NodeRole::Frontend
+    } else {
+        NodeRole::Other
+    }
+}
+
+pub struct LintContext<'a> {
+    pub state: &'a ProjectState,
+    pub graph: SystemGraph,
+}
+
+impl<'a> LintContext<'a> {
+    pub fn new(state: &'a ProjectState) -> Self {
+        Self {
+            state,
+            graph: SystemGraph::from_state(state),
+        }
+    }
+
+    pub fn role(&self, id: &str) -> NodeRole {
+        if let Some(component) = self.state.project.components.iter().find(|c| c.id == id) {
+            return match component.component_type {
+                ComponentType::Frontend => NodeRole::Frontend,
+                ComponentType::Database => NodeRole::Database,
+                _ => NodeRole::Other,
+            };
+        }
+        self.state
+            .diagram
+            .iter()
+            .find(|e| e.id == id)
+            .map_or(NodeRole::Other, |e| element_role(&e.element_type))
+    }
+
+    /// Component metadata or diagram element properties
+    pub fn attributes(&self, id: &str) -> Option<&'a HashMap<String, String>> {
+        let state: &'a ProjectState = self.state;
+        state
+            .project
+            .components
+            .iter()
+            .find(|c| c.id == id)
+            .map(|c| &c.metadata)
+            .or_else(|| state.diagram.iter().find(|e| e.id == id).map(|e| &e.properties))
+    }
+
+    fn label(&self, id: &str) -> String {
+        self.graph.node(id).map_or_else(|| id.to_string(), |n| n.label.clone())
+    }
+}
+
+pub trait LintRule: Send + Sync {
+    fn id(&self) -> &str;
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>);
+}
+
+struct UiTalksToDatabase;
+
+impl LintRule for UiTalksToDatabase {
+    fn id(&self) -> &str {
+        "ui-direct-database"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        for edge in ctx.graph.edges() {
+            if ctx.role(&edge.from) != NodeRole::Frontend || ctx.role(&edge.to) != NodeRole::Database {
+                continue;
+            }
+            let mut entity_ids = vec![edge.from.clone(), edge.to.clone()];
+            if let EdgeVia::Connection { connection_id, .. } = &edge.via {
+                entity_ids.push(connection_id.clone());
+            }
+            findings.push(LintFinding {
+                rule_id: self.id().to_string(),
+                severity: Severity::Error,
+                message: format!("{} talks directly to {}", ctx.label(&edge.from), ctx.label(&edge.to)),
+                entity_ids,
+                suggestion: "Put an API or service layer between the frontend and the database".to_string(),
+            });
+        }
+    }
+}
+
+struct SinglePointOfFailure;
+
+impl LintRule for SinglePointOfFailure {
+    fn id(&self) -> &str {
+        "single-point-of-failure"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        for node in ctx.graph.nodes() {
+            let dependents: HashSet<&str> = ctx.graph.incoming(&node.id).map(|e| e.from.as_str()).collect();
+            if dependents.len() < SPOF_MIN_DEPENDENTS {
+                continue;
+            }
+            if availability::node_replicas(ctx, &node.id).unwrap_or(1) > 1 {
+                continue;
+            }
+            let mut entity_ids = vec![node.id.clone()];
+            let mut dependents: Vec<&str> = dependents.into_iter().collect();
+            dependents.sort_unstable();
+            entity_ids.extend(dependents.iter().map(|d| d.to_string()));
+            findings.push(LintFinding {
+                rule_id: self.id().to_string(),
+                severity: Severity::Warning,
+                message: format!("{} has {} dependents and no redundancy", node.label, dependents.len()),
+                entity_ids,
+                suggestion: "Run more than one instance (set a `replicas` value above 1) or add a failover".to_string(),
+            });
+        }
+    }
+}
+
+struct OrphanComponent;
+
+impl LintRule for OrphanComponent {
+    fn id(&self) -> &str {
+        "orphan-component"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        // A lone node is a fresh design, not an orphan
+        if ctx.graph.nodes().len() < 2 {
+            return;
+        }
+        for node in ctx.graph.nodes() {
+            if ctx.graph.incoming(&node.id).next().is_some() || ctx.graph.outgoing(&node.id).next().is_some() {
+                continue;
+            }
+            // Containers group other elements rather than talk to them
+            if containment::is_container(&ctx.state.diagram, &node.id) {
+                continue;
+            }
+            findings.push(LintFinding {
+                rule_id: self.id().to_string(),
+                severity: Severity::Info,
+                message: format!("{} is not connected to anything", node.label),
+                entity_ids: vec![node.id.clone()],
+                suggestion: "Connect it to the components it talks to, or remove it".to_string(),
+            });
+        }
+    }
+}
+
+struct DanglingConnection;
+
+impl LintRule for DanglingConnection {
+    fn id(&self) -> &str {
+        "dangling-connection"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        for connection in &ctx.state.connections {
+            let missing: Vec<&str> = [&connection.source_id, &connection.target_id]
+                .into_iter()
+                .filter(|id| ctx.graph.node(id).is_none())
+                .map(String::as_str)
+                .collect();
+            if missing.is_empty() {
+                continue;
+            }
+            let mut entity_ids = vec![connection.id.clone()];
+            entity_ids.extend(missing.iter().map(|id| id.to_string()));
+            findings.push(LintFinding {
+                rule_id: self.id().to_string(),
+                severity: Severity::Error,
+                message: format!("Connection {} points at missing element {}", connection.id, missing.join(", ")),
+                entity_ids,
+                suggestion: "Delete the connection or reattach it to an existing element".to_string(),
+            });
+        }
+    }
+}
+
+struct ContainmentBoundary;
+
+impl LintRule for ContainmentBoundary {
+    fn id(&self) -> &str {
+        "containment-boundary"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        let diagram = &ctx.state.diagram;
+        for element in diagram {
+            let Some(parent_id) = &element.parent_id else {
+                continue;
+            };
+            let Some(parent) = diagram.iter().find(|e| &e.id == parent_id) else {
+                findings.push(LintFinding {
+                    rule_id: self.id().to_string(),
+                    severity: Severity::Error,
+                    message: format!("{} sits inside missing container {}", ctx.label(&element.id), parent_id),
+                    entity_ids: vec![element.id.clone(), parent_id.clone()],
+                    suggestion: "Move the element into an existing container or out of containers altogether"
+                        .to_string(),
+                });
+                continue;
+            };
+            let (inner, outer) = (Bounds::of(element), Bounds::of(parent));
+            let inside = inner.x >= outer.x
+                && inner.y >= outer.y
+                && inner.x + inner.width <= outer.x + outer.width
+                && inner.y + inner.height <= outer.y + outer.height;
+            if !inside {
+                findings.push(LintFinding {
+                    rule_id: self.id().to_string(),
+                    severity: Severity::Warning,
+                    message: format!(
+                        "{} is drawn outside its container {}",
+                        ctx.label(&element.id),
+                        ctx.label(parent_id)
+                    ),
+                    entity_ids: vec![element.id.clone(), parent_id.clone()],
+                    suggestion: "Move it back inside, resize the container, or take it out of the container"
+                        .to_string(),
+                });
+            }
+        }
+    }
+}
+
+struct DatabaseWithoutBackup;
+
+impl LintRule for DatabaseWithoutBackup {
+    fn id(&self) -> &str {
+        "database-without-backup"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        for node in ctx.graph.nodes() {
+            if ctx.role(&node.id) != NodeRole::Database {
+                continue;
+            }
+            let has_backup = ctx.attributes(&node.id).is_some_and(|attributes| {
+                BACKUP_KEYS.iter().any(|key| {
+                    attributes.get(*key).is_some_and(|v| {
+                        let v = v.trim().to_lowercase();
+                        !v.is_empty() && v != "none" && v != "false"
+                    })
+                })
+            });
+            if has_backup {
+                continue;
+            }
+            findings.push(LintFinding {
+                rule_id: self.id().to_string(),
+                severity: Severity::Warning,
+                message: format!("{} has no backup strategy", node.label),
+                entity_ids: vec![node.id.clone()],
+                suggestion: "Record the backup policy in metadata, e.g. `backup = daily snapshots, 30 day retention`".to_string(),
+            });
+        }
+    }
+}
+
+pub fn builtin_rules() -> Vec<Box<dyn LintRule>> {
+    vec![
+        Box::new(UiTalksToDatabase),
+        Box::new(SinglePointOfFailure),
+        Box::new(OrphanComponent),
+        Box::new(DanglingConnection),
+        Box::new(ContainmentBoundary),
+        Box::new(DatabaseWithoutBackup),
+    ]
+}
+
+pub fn run_rules(state: &ProjectState, rules: &[Box<dyn LintRule>]) -> LintReport {
+    let ctx = LintContext::new(state);
+    let mut findings = Vec::new();
+    for rule in rules {
+        rule.check(&ctx, &mut findings);
+    }
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.rule_id.cmp(&b.rule_id)));
+
+    let mut counts = HashMap::new();
+    for finding in &findings {
+        *counts.entry(finding.severity).or_insert(0) += 1;
+    }
+    LintReport {
+        project_id: state.project.id.clone(),
+        findings,
+        counts,
+        rule_errors: Vec::new(),
+    }
+}
+
+#[tauri::command]
+pub async fn lint_project(
+    project_id: String,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+) -> Result<LintReport, ApiError> {
+    // User rule files are read before the project is locked
+    let (user_rules, rule_errors) = lint_rules::user_rules_dir(&app)
+        .map(|dir| lint_rules::load_rules_dir(&dir))
+        .unwrap_or_default();
+
+    let mut report = projects
+        .read(&project_id, |state| {
+            let mut rules = builtin_rules();
+            rules.extend(user_rules.into_iter().map(|r| Box::new(r) as Box<dyn LintRule>));
+            rules.extend(
+                state
+                    .project
+                    .settings
+                    .lint_rules
+                    .iter()
+                    .cloned()
+                    .map(|r| Box::new(r) as Box<dyn LintRule>),
+            );
+            run_rules(state, &rules)
+        })?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })?;
+    report.rule_errors = rule_errors;
+    log::debug!("Lint found {} issues in project {}", report.findings.len(), project_id);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::availability::{Availability, AvailabilityInputs};
+    use crate::test_support::{self, component_with, diagram_state, element_at, state_with};
+    use crate::DiagramElement;
+
+    fn rule_ids(report: &LintReport) -> Vec<&str> {
+        report.findings.iter().map(|f| f.rule_id.as_str()).collect()
+    }
+
+    #[test]
+    fn flags_frontend_wired_to_database() {
+        let state = state_with(vec![
+            component_with("ui", ComponentType::Frontend, &["db"], &[]),
+            component_with("db", ComponentType::Database, &[], &[("backup", "nightly")]),
+        ]);
+        let report = run_rules(&state, &builtin_rules());
+        assert_eq!(rule_ids(&report), ["ui-direct-database"]);
+        assert_eq!(report.findings[0].entity_ids, ["ui", "db"]);
+        assert_eq!(report.counts[&Severity::Error], 1);
+    }
+
+    #[test]
+    fn clean_layered_design_has_no_findings() {
+        let state = state_with(vec![
+            component_with("ui", ComponentType::Frontend, &["api"], &[]),
+            component_with("api", ComponentType::Api, &["db"], &[]),
+            component_with("db", ComponentType::Database, &[], &[("backup_policy", "hourly")]),
+        ]);
+        assert!(run_rules(&state, &builtin_rules()).findings.is_empty());
+    }
+
+    #[test]
+    fn reports_spof_orphans_missing_backups_and_dangling_connections() {
+        let mut state = state_with(vec![
+            component_with("orders", ComponentType::Service, &["db"], &[]),
+            component_with("billing", ComponentType::Service, &["db"], &[]),
+            component_with("db", ComponentType::Database, &[], &[("backup", "none")]),
+            component_with("legacy", ComponentType::Backend, &[], &[]),
+        ]);
+        state.diagram = vec![DiagramElement {
+            element_type: "cache".into(),
+            ..test_support::element("cache")
+        }];
+        state.connections = vec![test_support::connection("conn", "cache", "gone")];
+
+        let report = run_rules(&state, &builtin_rules());
+        assert_eq!(
+            rule_ids(&report),
+            [
+                "dangling-connection",
+                "database-without-backup",
+                "single-point-of-failure",
+                "orphan-component",
+                "orphan-component"
+            ]
+        );
+        let spof = &report.findings[2];
+        assert_eq!(spof.entity_ids, ["db", "billing", "orders"]);
+        assert_eq!(report.findings[0].entity_ids, ["conn", "gone"]);
+
+        // Replicas remove the single point of failure, whether typed or in metadata
+        state.project.components[2].availability = Some(AvailabilityInputs {
+            target: Availability(0.999),
+            replicas: Some(3),
+        });
+        assert!(!rule_ids(&run_rules(&state, &builtin_rules())).contains(&"single-point-of-failure"));
+        state.project.components[2].availability = None;
+        state.project.components[2].metadata.insert("replicas".into(), "3".into());
+        assert!(!rule_ids(&run_rules(&state, &builtin_rules())).contains(&"single-point-of-failure"));
+    }
+
+    #[test]
+    fn components_wired_on_the_diagram_are_not_orphans() {
+        let mut state = state_with(vec![
+            component_with("api", ComponentType::Api, &[], &[]),
+            component_with("db", ComponentType::Database, &[], &[("backup", "daily")]),
+        ]);
+        let linked = |id: &str, component: &str| DiagramElement {
+            component_id: Some(component.into()),
+            ..test_support::element(id)
+        };
+        state.diagram = vec![linked("api-box", "api"), linked("db-box", "db")];
+        assert_eq!(rule_ids(&run_rules(&state, &builtin_rules())), ["orphan-component", "orphan-component"]);
+
+        state.connections = vec![test_support::connection("q", "api-box", "db-box")];
+        assert!(run_rules(&state, &builtin_rules()).findings.is_empty());
+    }
+
+    #[test]
+    fn checks_containment_boundaries() {
+        let in_vpc = |id: &str, x: f64| DiagramElement {
+            parent_id: Some("vpc".into()),
+            ..element_at(id, x, 0.0)
+        };
+        let vpc = DiagramElement {
+            width: 300.0,
+            ..element_at("vpc", 0.0, 0.0)
+        };
+        let mut state = diagram_state(
+            vec![vpc, in_vpc("a", 0.0), in_vpc("b", 250.0)],
+            vec![test_support::connection("ab", "a", "b")],
+        );
+
+        // The container itself isn't reported as an orphan
+        let report = run_rules(&state, &builtin_rules());
+        assert_eq!(rule_ids(&report), ["containment-boundary"]);
+        assert_eq!(report.findings[0].entity_ids, ["b", "vpc"]);
+
+        state.diagram[2].position.x = 150.0;
+        state.diagram[1].parent_id = Some("gone".into());
+        let report = run_rules(&state, &builtin_rules());
+        assert_eq!(report.findings[0].severity, Severity::Error);
+        assert_eq!(report.findings[0].entity_ids, ["a", "gone"]);
+    }
+}
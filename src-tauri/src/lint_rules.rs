@@ -0,0 +1,469 @@
+// Declarative custom lint rules
+//
+// Rules are written in TOML or JSON and describe a pattern that counts as a violation:
+//
+//   [[rules]]
+//   id = "service-only-to-integration"
+//   severity = "Error"
+//   message = "{from} calls {to}; only Service components may talk to integrations"
+//   [rules.edge]
+//   from = { not_component_type = ["Service"] }
+//   to = { component_type = ["Integration"] }
+//
+// Each rule has exactly one of `node`, `edge` or `path`. User-wide rules are read from
+// `<app data dir>/lint-rules/*.{toml,json}`; a project's own rules live in its settings.
+
+use crate::events::{Change, ChangeFeed};
+use crate::lint::{LintContext, LintFinding, LintRule, Severity};
+use crate::state::ProjectStore;
+use crate::system_graph::EdgeVia;
+use crate::{check_revision, ApiError, ComponentType};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+
+const USER_RULES_DIR: &str = "lint-rules";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleFormat {
+    Toml,
+    Json,
+}
+
+impl RuleFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "toml" => Some(RuleFormat::Toml),
+            "json" => Some(RuleFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Rule files are JSON objects; anything else is treated as TOML
+    fn detect(source: &str) -> Self {
+        if source.trim_start().starts_with('{') {
+            RuleFormat::Json
+        } else {
+            RuleFormat::Toml
+        }
+    }
+}
+
+/// Conditions a single component or diagram element must all satisfy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeMatcher {
+    /// Must be a component of one of these types
+    pub component_type: Vec<ComponentType>,
+    /// Must not be a component of these types; diagram elements always pass
+    pub not_component_type: Vec<ComponentType>,
+    /// Must be a diagram element of one of these types (case-insensitive)
+    pub element_type: Vec<String>,
+    pub has_metadata: Vec<String>,
+    pub missing_metadata: Vec<String>,
+    /// Metadata values that must match exactly (case-insensitive)
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodePattern {
+    pub select: NodeMatcher,
+    pub min_dependents: Option<usize>,
+    pub max_dependents: Option<usize>,
+    pub min_dependencies: Option<usize>,
+    pub max_dependencies: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EdgeKind {
+    Dependency,
+    Connection,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgePattern {
+    pub from: NodeMatcher,
+    pub to: NodeMatcher,
+    /// Only diagram connections of these types (case-insensitive)
+    pub connection_type: Vec<String>,
+    pub kind: Option<EdgeKind>,
+}
+
+/// Matches when `from` can reach `to` by following edges, within `max_hops` if set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathPattern {
+    pub from: NodeMatcher,
+    pub to: NodeMatcher,
+    pub max_hops: Option<usize>,
+}
+
+fn default_severity() -> Severity {
+    Severity::Warning
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomRule {
+    pub id: String,
+    #[serde(default = "default_severity")]
+    pub severity: Severity,
+    /// May reference `{node}`, `{from}`, `{to}` and `{hops}`
+    pub message: String,
+    #[serde(default)]
+    pub suggestion: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<NodePattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge: Option<EdgePattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathPattern>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(default)]
+    rules: Vec<CustomRule>,
+}
+
+fn invalid(details: String) -> ApiError {
+    ApiError::InvalidLintRules { details, source: None }
+}
+
+pub fn parse_rules(source: &str, format: Option<RuleFormat>) -> Result<Vec<CustomRule>, ApiError> {
+    let file: RuleFile = match format.unwrap_or_else(|| RuleFormat::detect(source)) {
+        RuleFormat::Toml => toml::from_str(source).map_err(|e| ApiError::InvalidLintRules {
+            details: e.message().to_string(),
+            source: Some(Box::new(e)),
+        })?,
+        RuleFormat::Json => serde_json::from_str(source).map_err(|e| ApiError::InvalidLintRules {
+            details: e.to_string(),
+            source: Some(Box::new(e)),
+        })?,
+    };
+
+    let mut ids = HashSet::new();
+    for rule in &file.rules {
+        if rule.id.trim().is_empty() {
+            return Err(invalid("Rule id cannot be empty".to_string()));
+        }
+        if !ids.insert(rule.id.as_str()) {
+            return Err(invalid(format!("Duplicate rule id '{}'", rule.id)));
+        }
+        let patterns = [rule.node.is_some(), rule.edge.is_some(), rule.path.is_some()];
+        if patterns.iter().filter(|p| **p).count() != 1 {
+            return Err(invalid(format!("Rule '{}' needs exactly one of node, edge or path", rule.id)));
+        }
+    }
+    Ok(file.rules)
+}
+
+/// Rules from every `.toml`/`.json` file in `dir`, plus a message per file that failed to load
+pub fn load_rules_dir(dir: &Path) -> (Vec<CustomRule>, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (rules, errors);
+    };
+
+    let mut paths: Vec<(PathBuf, RuleFormat)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter_map(|p| RuleFormat::from_path(&p).map(|f| (p, f)))
+        .collect();
+    paths.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (path, format) in paths {
+        match fs::read_to_string(&path).map_err(ApiError::from).and_then(|s| parse_rules(&s, Some(format))) {
+            Ok(loaded) => rules.extend(loaded),
+            Err(e) => {
+                log::warn!("Skipping lint rules in {}: {}", path.display(), e);
+                errors.push(format!("{}: {}", path.display(), e));
+            }
+        }
+    }
+    (rules, errors)
+}
+
+pub fn user_rules_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path_resolver().app_data_dir().map(|dir| dir.join(USER_RULES_DIR))
+}
+
+impl NodeMatcher {
+    fn matches(&self, ctx: &LintContext, id: &str) -> bool {
+        let component = ctx.state.project.components.iter().find(|c| c.id == id);
+        let element = ctx.state.diagram.iter().find(|e| e.id == id);
+
+        if !self.component_type.is_empty() && !component.is_some_and(|c| self.component_type.contains(&c.component_type)) {
+            return false;
+        }
+        if component.is_some_and(|c| self.not_component_type.contains(&c.component_type)) {
+            return false;
+        }
+        if !self.element_type.is_empty()
+            && !element.is_some_and(|e| self.element_type.iter().any(|t| t.eq_ignore_ascii_case(&e.element_type)))
+        {
+            return false;
+        }
+
+        let empty = HashMap::new();
+        let attributes = ctx.attributes(id).unwrap_or(&empty);
+        self.has_metadata.iter().all(|k| attributes.contains_key(k))
+            && self.missing_metadata.iter().all(|k| !attributes.contains_key(k))
+            && self
+                .metadata
+                .iter()
+                .all(|(k, v)| attributes.get(k).is_some_and(|actual| actual.trim().eq_ignore_ascii_case(v.trim())))
+    }
+}
+
+fn within(value: usize, min: Option<usize>, max: Option<usize>) -> bool {
+    min.is_none_or(|m| value >= m) && max.is_none_or(|m| value <= m)
+}
+
+impl CustomRule {
+    fn finding(&self, message: String, entity_ids: Vec<String>) -> LintFinding {
+        LintFinding {
+            rule_id: self.id.clone(),
+            severity: self.severity,
+            message,
+            entity_ids,
+            suggestion: self.suggestion.clone(),
+        }
+    }
+
+    fn render(&self, values: &[(&str, &str)]) -> String {
+        values
+            .iter()
+            .fold(self.message.clone(), |message, (key, value)| message.replace(&format!("{{{}}}", key), value))
+    }
+
+    fn check_node(&self, pattern: &NodePattern, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        for node in ctx.graph.nodes() {
+            let dependents: HashSet<&str> = ctx.graph.incoming(&node.id).map(|e| e.from.as_str()).collect();
+            let dependencies: HashSet<&str> = ctx.graph.outgoing(&node.id).map(|e| e.to.as_str()).collect();
+            if pattern.select.matches(ctx, &node.id)
+                && within(dependents.len(), pattern.min_dependents, pattern.max_dependents)
+                && within(dependencies.len(), pattern.min_dependencies, pattern.max_dependencies)
+            {
+                findings.push(self.finding(self.render(&[("node", &node.label)]), vec![node.id.clone()]));
+            }
+        }
+    }
+
+    fn check_edge(&self, pattern: &EdgePattern, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        for edge in ctx.graph.edges() {
+            let kind_matches = match (&edge.via, pattern.kind) {
+                (_, None) => true,
+                (EdgeVia::Dependency, Some(kind)) => kind == EdgeKind::Dependency,
+                (EdgeVia::Connection { .. }, Some(kind)) => kind == EdgeKind::Connection,
+            };
+            let type_matches = pattern.connection_type.is_empty()
+                || matches!(&edge.via, EdgeVia::Connection { connection_type, .. }
+                    if pattern.connection_type.iter().any(|t| t.eq_ignore_ascii_case(connection_type)));
+            if !kind_matches || !type_matches || !pattern.from.matches(ctx, &edge.from) || !pattern.to.matches(ctx, &edge.to) {
+                continue;
+            }
+
+            let from = ctx.graph.node(&edge.from).map_or(edge.from.as_str(), |n| n.label.as_str());
+            let to = ctx.graph.node(&edge.to).map_or(edge.to.as_str(), |n| n.label.as_str());
+            let mut entity_ids = vec![edge.from.clone(), edge.to.clone()];
+            if let EdgeVia::Connection { connection_id, .. } = &edge.via {
+                entity_ids.push(connection_id.clone());
+            }
+            findings.push(self.finding(self.render(&[("from", from), ("to", to)]), entity_ids));
+        }
+    }
+
+    fn check_path(&self, pattern: &PathPattern, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        for start in ctx.graph.nodes().iter().filter(|n| pattern.from.matches(ctx, &n.id)) {
+            // Breadth-first so the reported hop count is the shortest path
+            let mut hops: HashMap<&str, usize> = HashMap::from([(start.id.as_str(), 0)]);
+            let mut queue = VecDeque::from([start.id.as_str()]);
+            while let Some(current) = queue.pop_front() {
+                let distance = hops[current];
+                if pattern.max_hops.is_some_and(|max| distance >= max) {
+                    continue;
+                }
+                for edge in ctx.graph.outgoing(current) {
+                    if hops.contains_key(edge.to.as_str()) {
+                        continue;
+                    }
+                    hops.insert(edge.to.as_str(), distance + 1);
+                    queue.push_back(edge.to.as_str());
+                }
+            }
+
+            let mut reached: Vec<(&str, usize)> = hops
+                .into_iter()
+                .filter(|(id, d)| *d > 0 && pattern.to.matches(ctx, id))
+                .collect();
+            reached.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+            for (target, distance) in reached {
+                let to = ctx.graph.node(target).map_or(target, |n| n.label.as_str());
+                let message = self.render(&[("from", &start.label), ("to", to), ("hops", &distance.to_string())]);
+                findings.push(self.finding(message, vec![start.id.clone(), target.to_string()]));
+            }
+        }
+    }
+}
+
+impl LintRule for CustomRule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<LintFinding>) {
+        if let Some(pattern) = &self.node {
+            self.check_node(pattern, ctx, findings);
+        } else if let Some(pattern) = &self.edge {
+            self.check_edge(pattern, ctx, findings);
+        } else if let Some(pattern) = &self.path {
+            self.check_path(pattern, ctx, findings);
+        }
+    }
+}
+
+/// Replaces a project's own lint rules with the ones in `source`
+#[tauri::command]
+pub async fn set_project_lint_rules(
+    project_id: String,
+    source: String,
+    format: Option<RuleFormat>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<Vec<CustomRule>, ApiError> {
+    let rules = parse_rules(&source, format)?;
+    let entry = projects.require(&project_id)?;
+    let mut state = crate::state::write_entry(&entry)?;
+    let project = &mut state.project;
+    check_revision("project", &project.id, expected_revision, project.revision, &*project)?;
+
+    project.settings.lint_rules = rules.clone();
+    project.revision += 1;
+    project.updated_at = Utc::now();
+    changes.publish(&app, Change::ProjectUpdated { project: project.clone() })?;
+    log::info!("Stored {} custom lint rules for project {}", rules.len(), project_id);
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::run_rules;
+    use crate::state::ProjectState;
+    use crate::test_support::{self, component_with, state_with};
+    use crate::{Connection, DiagramElement};
+
+    const TEAM_RULES: &str = r#"
+[[rules]]
+id = "service-only-to-integration"
+severity = "Error"
+message = "{from} calls {to}; only Service components may talk to integrations"
+suggestion = "Route the call through a Service"
+[rules.edge]
+from = { not_component_type = ["Service"] }
+to = { component_type = ["Integration"] }
+
+[[rules]]
+id = "database-owner"
+message = "{node} has no owner"
+[rules.node]
+select = { component_type = ["Database"], missing_metadata = ["owner"] }
+
+[[rules]]
+id = "frontend-reaches-payments"
+severity = "Info"
+message = "{from} reaches {to} in {hops} hops"
+[rules.path]
+from = { component_type = ["Frontend"] }
+to = { metadata = { domain = "payments" } }
+max_hops = 2
+"#;
+
+    fn state() -> ProjectState {
+        let mut state = state_with(vec![
+            component_with("ui", ComponentType::Frontend, &["api"], &[]),
+            component_with("api", ComponentType::Api, &["stripe", "db"], &[]),
+            component_with("orders", ComponentType::Service, &["stripe"], &[]),
+            component_with("stripe", ComponentType::Integration, &[], &[("domain", "Payments")]),
+            component_with("db", ComponentType::Database, &[], &[("owner", "data-team")]),
+            component_with("ledger", ComponentType::Database, &[], &[]),
+        ]);
+        state.diagram = vec![DiagramElement {
+            element_type: "Queue".into(),
+            ..test_support::element("queue")
+        }];
+        state.connections = vec![Connection {
+            connection_type: "amqp".into(),
+            ..test_support::connection("c1", "queue", "stripe")
+        }];
+        state
+    }
+
+    #[test]
+    fn toml_rules_match_edges_nodes_and_paths() {
+        let rules: Vec<Box<dyn LintRule>> = parse_rules(TEAM_RULES, None)
+            .unwrap()
+            .into_iter()
+            .map(|r| Box::new(r) as Box<dyn LintRule>)
+            .collect();
+        let report = run_rules(&state(), &rules);
+        let summary: Vec<(&str, &str)> = report
+            .findings
+            .iter()
+            .map(|f| (f.rule_id.as_str(), f.message.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("service-only-to-integration", "api calls stripe; only Service components may talk to integrations"),
+                ("service-only-to-integration", "Queue calls stripe; only Service components may talk to integrations"),
+                ("database-owner", "ledger has no owner"),
+                ("frontend-reaches-payments", "ui reaches stripe in 2 hops"),
+            ]
+        );
+        assert_eq!(report.findings[1].entity_ids, ["queue", "stripe", "c1"]);
+    }
+
+    #[test]
+    fn json_rules_filter_on_connection_type() {
+        let source = r#"{"rules": [{
+            "id": "no-amqp-to-integrations",
+            "message": "{from} -> {to}",
+            "edge": {"to": {"component_type": ["Integration"]}, "connection_type": ["AMQP"], "kind": "Connection"}
+        }]}"#;
+        let rules = parse_rules(source, None).unwrap();
+        let report = run_rules(&state(), &[Box::new(rules[0].clone()) as Box<dyn LintRule>]);
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].message, "Queue -> stripe");
+        assert_eq!(report.findings[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn rejects_malformed_rules_and_skips_bad_files() {
+        let no_pattern = "[[rules]]\nid = \"x\"\nmessage = \"m\"\n";
+        assert!(matches!(parse_rules(no_pattern, None), Err(ApiError::InvalidLintRules { .. })));
+        let typo = "[[rules]]\nid = \"x\"\nmessage = \"m\"\n[rules.node]\nselect = { component_typ = [\"Api\"] }\n";
+        assert!(parse_rules(typo, Some(RuleFormat::Toml)).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("team.toml"), TEAM_RULES).unwrap();
+        fs::write(dir.path().join("broken.json"), "{not json").unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+        let (rules, errors) = load_rules_dir(dir.path());
+        assert_eq!(rules.len(), 3);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.json"));
+
+        assert!(load_rules_dir(&dir.path().join("missing")).0.is_empty());
+    }
+}
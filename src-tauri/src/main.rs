@@ -3,11 +3,11 @@
 
 use std::collections::HashMap;
 use std::path::{PathBuf, Path};
-use std::sync::{Arc, RwLock, Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::env;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tauri::State;
+use tauri::{AppHandle, State};
 use uuid::Uuid;
 use tempfile::NamedTempFile;
 use std::io::Write;
@@ -35,6 +35,8 @@ impl OperationNames {
     pub const COMPONENT_MANAGEMENT: &'static str = "component management";
     pub const TRANSCRIPTION: &'static str = "transcription";
     pub const TRANSCRIPTION_INIT: &'static str = "transcription initialization";
+    pub const BUNDLE_IO: &'static str = "project bundle I/O";
+    pub const SEARCH: &'static str = "search";
 }
 
 // Custom error types for structured error handling
@@ -57,6 +59,38 @@ pub enum ApiError {
         source: Option<Box<dyn std::error::Error + Send + Sync>>,
     },
     
+    #[error("Revision conflict on {entity} {entity_id}: expected revision {expected_revision}, current is {current_revision}")]
+    RevisionConflict {
+        entity: String,
+        entity_id: String,
+        expected_revision: u64,
+        current_revision: u64,
+        /// Server copy of the entity so the client can merge or retry
+        current: JsonValue,
+        #[source]
+        #[serde(skip)]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+
+    #[error("Component {component_id} depends on unknown components: {}", unknown_ids.join(", "))]
+    UnknownDependency {
+        component_id: String,
+        unknown_ids: Vec<String>,
+        #[source]
+        #[serde(skip)]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+
+    #[error("Dependency cycle: {}", cycle.join(" -> "))]
+    DependencyCycle {
+        component_id: String,
+        /// Component ids along the cycle, starting and ending with `component_id`
+        cycle: Vec<String>,
+        #[source]
+        #[serde(skip)]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+
     #[error("Invalid project data: {details}")]
     InvalidProjectData { 
         details: String,
@@ -131,6 +165,30 @@ pub enum ApiError {
         source: Option<Box<dyn std::error::Error + Send + Sync>>,
     },
     
+    #[error("Invalid lint rules: {details}")]
+    InvalidLintRules {
+        details: String,
+        #[source]
+        #[serde(skip)]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+
+    #[error("Invalid project bundle: {details}")]
+    InvalidBundle {
+        details: String,
+        #[source]
+        #[serde(skip)]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+
+    #[error("Search failed: {details}")]
+    SearchError {
+        details: String,
+        #[source]
+        #[serde(skip)]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+
     #[error("State lock error: Failed to acquire lock for {resource}")]
     StateLockError { 
         resource: String,
@@ -196,6 +254,87 @@ impl From<ApiError> for String {
 #[cfg(debug_assertions)]
 mod dev_utils;
 
+// Per-project state and locking
+mod state;
+use state::{read_entry, write_entry, ProjectState, ProjectStore};
+
+// Change events broadcast to all windows
+mod events;
+use events::{Change, ChangeFeed, ComponentChange};
+
+// Single-file .archicomm project bundles
+mod bundle;
+
+// Component dependency validation and migration
+mod dependencies;
+
+// Cycle detection and topological layering of component dependencies
+mod dependency_graph;
+
+// Components and diagram elements as one dependency graph
+mod system_graph;
+
+// Transitive dependents of a component or element
+mod impact;
+
+// Architecture lint rules
+mod lint;
+
+// Custom lint rules written in TOML or JSON
+mod lint_rules;
+
+// Data size and duration units for estimates
+mod units;
+
+// Back-of-envelope capacity estimation
+mod capacity;
+
+// Critical path and latency budgets over connections
+mod latency;
+
+// Composite availability of entry points
+mod availability;
+
+// What-if failure simulation
+mod failure;
+
+// Seeded discrete-event traffic simulation
+mod simulation;
+
+// Monthly cost estimates from an editable pricing table
+mod cost;
+
+// Atomic add/update/move/delete batches for diagrams
+mod diagram_patch;
+
+// Automatic diagram layout
+mod layout;
+
+// Element bounds, overlap fixing and align/distribute/snap
+mod geometry;
+
+// Nested groups and containers in diagrams
+mod containment;
+
+// Multiple named diagrams per project
+mod diagrams;
+
+// Element-to-component links and reconciling them
+mod component_links;
+
+// Orthogonal connection routing around element boxes
+mod routing;
+
+// Full-text search index
+mod search;
+
+// Filtered and paginated project listings
+mod project_query;
+
+// Builders shared by the unit tests
+#[cfg(test)]
+mod test_support;
+
 
 // ========= Native Audio Recording (CPAL + Hound) ==========
 // use std::io::BufWriter;
@@ -422,9 +561,37 @@ pub struct Project {
     pub updated_at: DateTime<Utc>,
     pub status: ProjectStatus,
     pub components: Vec<Component>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Bumped on every change to the project or its components
+    #[serde(default)]
+    pub revision: u64,
+    #[serde(default)]
+    pub settings: ProjectSettings,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectSettings {
+    /// What `update_component` does when new dependencies would close a cycle
+    #[serde(default)]
+    pub dependency_cycles: CyclePolicy,
+    /// Project-specific lint rules, run alongside the built-in and user-wide ones
+    #[serde(default)]
+    pub lint_rules: Vec<lint_rules::CustomRule>,
+    /// End-to-end latency target checked by `analyze_latency`
+    #[serde(default)]
+    pub latency_budget: latency::LatencyBudget,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CyclePolicy {
+    Allow,
+    #[default]
+    Warn,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProjectStatus {
     Planning,
     InProgress,
@@ -441,9 +608,17 @@ pub struct Component {
     pub dependencies: Vec<String>,
     pub status: ComponentStatus,
     pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub revision: u64,
+    /// Typed inputs for capacity estimation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<capacity::CapacityInputs>,
+    /// Per-instance availability target and replica count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub availability: Option<availability::AvailabilityInputs>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ComponentType {
     Frontend,
     Backend,
@@ -461,27 +636,59 @@ pub enum ComponentStatus {
     Done,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DiagramElement {
     pub id: String,
     pub element_type: String,
+    /// Top-left corner before rotation
     pub position: Position,
+    #[serde(default = "geometry::default_width")]
+    pub width: f64,
+    #[serde(default = "geometry::default_height")]
+    pub height: f64,
+    /// Degrees clockwise around the element's centre
+    #[serde(default)]
+    pub rotation: f64,
+    /// Higher values draw on top
+    #[serde(default)]
+    pub z_index: i32,
+    /// Group or container element this one sits inside
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Shared component this element draws; several diagrams may show the same one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component_id: Option<String>,
     pub properties: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl DiagramElement {
+    /// Display name: the `label` or `name` property, falling back to the element type
+    pub fn label(&self) -> &str {
+        self.properties
+            .get("label")
+            .or_else(|| self.properties.get("name"))
+            .unwrap_or(&self.element_type)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub x: f64,
     pub y: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Connection {
     pub id: String,
     pub source_id: String,
     pub target_id: String,
     pub connection_type: String,
     pub properties: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<latency::ConnectionLatency>,
+    /// Bend points between the two ends, drawn as an orthogonal polyline
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub waypoints: Vec<Position>,
 }
 
 // Transcription data structures
@@ -499,6 +706,16 @@ pub struct TranscriptionResponse {
     pub segments: Vec<TranscriptionSegment>,
 }
 
+// Transcript attached to a project, optionally pointing at its source recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectTranscript {
+    pub id: String,
+    pub text: String,
+    pub segments: Vec<TranscriptionSegment>,
+    pub audio_path: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct TranscriptionOptions {
     pub timeout: Option<u64>,
@@ -506,10 +723,7 @@ pub struct TranscriptionOptions {
     pub max_segments: Option<usize>,
 }
 
-// Application state with RwLock for better concurrency
-type ProjectStore = RwLock<HashMap<String, Project>>;
-type DiagramStore = RwLock<HashMap<String, Vec<DiagramElement>>>;
-type ConnectionStore = RwLock<HashMap<String, Vec<Connection>>>;
+// Application state: per-project entries, see state.rs
 type TranscriptionJobStore = Arc<Mutex<HashMap<String, JoinHandle<()>>>>;
 
 // Global session directory for audio files
@@ -559,6 +773,23 @@ fn get_audio_session_dir() -> Result<PathBuf, ApiError> {
     Ok(audio_dir)
 }
 
+/// Resolves a recording path, refusing anything outside the audio session directory so an
+/// arbitrary local file can't be attached to a transcript and from there packed into a bundle
+pub fn audio_session_path(path: &str) -> Result<PathBuf, ApiError> {
+    let session_dir = fs::canonicalize(get_audio_session_dir()?)?;
+    let resolved = fs::canonicalize(path).map_err(|e| ApiError::AudioFileNotFound {
+        path: path.to_string(),
+        source: Some(Box::new(e)),
+    })?;
+    if !resolved.starts_with(&session_dir) {
+        return Err(ApiError::InvalidFilename {
+            details: format!("Audio path '{}' is outside the audio session directory", path),
+            source: None,
+        });
+    }
+    Ok(resolved)
+}
+
 // Helper function to create audio session directory for testing with custom base dir
 fn create_audio_session_dir_with_base(base_dir: &Path) -> Result<PathBuf, ApiError> {
     let process_id = process::id();
@@ -587,12 +818,47 @@ fn create_audio_session_dir_with_base(base_dir: &Path) -> Result<PathBuf, ApiErr
     Ok(audio_dir)
 }
 
+// Trims tags and drops empty or case-insensitive duplicate entries, keeping first spelling
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+// Rejects a write made against a stale copy; `None` skips the check for callers that don't track revisions
+fn check_revision<T: Serialize>(
+    entity: &str,
+    entity_id: &str,
+    expected_revision: Option<u64>,
+    current_revision: u64,
+    current: &T,
+) -> Result<(), ApiError> {
+    match expected_revision {
+        Some(expected) if expected != current_revision => Err(ApiError::RevisionConflict {
+            entity: entity.to_string(),
+            entity_id: entity_id.to_string(),
+            expected_revision: expected,
+            current_revision,
+            current: serde_json::to_value(current)?,
+            source: None,
+        }),
+        _ => Ok(()),
+    }
+}
+
 // Tauri commands for project management
 #[tauri::command]
 async fn create_project(
     name: String,
     description: String,
+    app: AppHandle,
     projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
 ) -> Result<Project, ApiError> {
     // Validate project data
     if name.trim().is_empty() {
@@ -617,15 +883,13 @@ async fn create_project(
         updated_at: Utc::now(),
         status: ProjectStatus::Planning,
         components: Vec::new(),
+        tags: Vec::new(),
+        revision: 1,
+        settings: ProjectSettings::default(),
     };
 
-    let mut store = projects.write().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    
-    let project_id = project.id.clone();
-    store.insert(project_id, project.clone());
+    projects.insert(ProjectState::new(project.clone()))?;
+    changes.publish(&app, Change::ProjectCreated { project: project.clone() })?;
 
     log::info!("Project created successfully: {} ({})", project.name, project.id);
     Ok(project)
@@ -633,12 +897,10 @@ async fn create_project(
 
 #[tauri::command]
 async fn get_projects(projects: State<'_, ProjectStore>) -> Result<Vec<Project>, ApiError> {
-    let store = projects.read().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    
-    let all_projects: Vec<Project> = store.values().cloned().collect();
+    let mut all_projects = Vec::new();
+    for entry in projects.entries()? {
+        all_projects.push(read_entry(&entry)?.project.clone());
+    }
     log::debug!("Retrieved {} projects", all_projects.len());
     Ok(all_projects)
 }
@@ -648,12 +910,7 @@ async fn get_project(
     project_id: String,
     projects: State<'_, ProjectStore>,
 ) -> Result<Option<Project>, ApiError> {
-    let store = projects.read().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    
-    let project = store.get(&project_id).cloned();
+    let project = projects.read(&project_id, |state| state.project.clone())?;
     if project.is_some() {
         log::debug!("Retrieved project: {}", project_id);
     } else {
@@ -663,19 +920,22 @@ async fn get_project(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn update_project(
     project_id: String,
     name: Option<String>,
     description: Option<String>,
     status: Option<ProjectStatus>,
+    tags: Option<Vec<String>>,
+    settings: Option<ProjectSettings>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
     projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
 ) -> Result<Option<Project>, ApiError> {
-    let mut store = projects.write().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    
-    if let Some(project) = store.get_mut(&project_id) {
+    let updated = projects.write(&project_id, |state| {
+        let project = &mut state.project;
+        check_revision("project", &project.id, expected_revision, project.revision, &*project)?;
         if let Some(new_name) = name {
             if new_name.trim().is_empty() {
                 return Err(ApiError::InvalidProjectData {
@@ -697,30 +957,38 @@ async fn update_project(
         if let Some(new_status) = status {
             project.status = new_status;
         }
+        if let Some(new_tags) = tags {
+            project.tags = normalize_tags(new_tags);
+        }
+        if let Some(new_settings) = settings {
+            project.settings = new_settings;
+        }
+        project.revision += 1;
         project.updated_at = Utc::now();
-        
-        log::info!("Project updated successfully: {} ({})", project.name, project.id);
-        Ok(Some(project.clone()))
-    } else {
-        log::debug!("Project not found for update: {}", project_id);
-        Ok(None)
+        // Published while the project is still locked so events follow mutation order
+        changes.publish(&app, Change::ProjectUpdated { project: project.clone() })?;
+        Ok(project.clone())
+    })?;
+
+    match &updated {
+        Some(project) => log::info!("Project updated successfully: {} ({})", project.name, project.id),
+        None => log::debug!("Project not found for update: {}", project_id),
     }
+    Ok(updated)
 }
 
 #[tauri::command]
 async fn delete_project(
     project_id: String,
+    app: AppHandle,
     projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
 ) -> Result<bool, ApiError> {
-    let mut store = projects.write().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    
-    let removed = store.remove(&project_id);
+    let removed = projects.remove(&project_id)?;
     let success = removed.is_some();
     
     if success {
+        changes.publish(&app, Change::ProjectDeleted { project_id: project_id.clone() })?;
         log::info!("Project deleted successfully: {}", project_id);
     } else {
         log::debug!("Project not found for deletion: {}", project_id);
@@ -731,12 +999,17 @@ async fn delete_project(
 
 // Tauri commands for component management
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn add_component(
     project_id: String,
     name: String,
     component_type: ComponentType,
     description: String,
+    create_element: Option<bool>,
+    diagram_id: Option<String>,
+    app: AppHandle,
     projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
 ) -> Result<Option<Component>, ApiError> {
     // Validate component data
     if name.trim().is_empty() {
@@ -753,12 +1026,9 @@ async fn add_component(
         });
     }
 
-    let mut store = projects.write().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    
-    if let Some(project) = store.get_mut(&project_id) {
+    let added = projects.write(&project_id, |state| {
+        // Fail on an unknown diagram before anything changes
+        diagrams::read_diagram(state, diagram_id.as_deref(), |_| ())?;
         let component = Component {
             id: Uuid::new_v4().to_string(),
             name: name.trim().to_string(),
@@ -767,20 +1037,80 @@ async fn add_component(
             dependencies: Vec::new(),
             status: ComponentStatus::NotStarted,
             metadata: HashMap::new(),
+            revision: 1,
+            capacity: None,
+            availability: None,
         };
-        
-        project.components.push(component.clone());
-        project.updated_at = Utc::now();
-        
-        log::info!("Component added successfully: {} to project {}", component.name, project_id);
-        Ok(Some(component))
-    } else {
-        log::debug!("Project not found for component addition: {}", project_id);
-        Ok(None)
+
+        let (revision, updated_at) = (state.project.revision, state.project.updated_at);
+        state.project.components.push(component.clone());
+        state.project.revision += 1;
+        state.project.updated_at = Utc::now();
+        let diagram_id = diagram_id.as_deref();
+        let mut patched = None;
+        if create_element.unwrap_or(false) {
+            let element = diagrams::on_diagram(state, diagram_id, |state| {
+                let ops = vec![diagram_patch::DiagramOp::AddElement {
+                    element: component_links::element_for(&state.diagram, &component, 0),
+                }];
+                let before = state.diagram_revision;
+                let inverse = diagram_patch::apply_patch(state, &ops)?;
+                let change = Change::DiagramPatched {
+                    project_id: project_id.clone(),
+                    diagram_id: diagram_id.unwrap_or(diagrams::MAIN_DIAGRAM_ID).to_string(),
+                    diagram_revision: state.diagram_revision,
+                    ops,
+                };
+                Ok((change, inverse, before))
+            });
+            match element {
+                Ok(element) => patched = Some(element),
+                Err(e) => {
+                    state.project.components.pop();
+                    (state.project.revision, state.project.updated_at) = (revision, updated_at);
+                    return Err(e);
+                }
+            }
+        }
+        // Anything that can't be announced is taken back. Nothing is announced until both are
+        // in place, and then the component goes first so listeners never see an element linked
+        // to a component they don't know about
+        let unpatch = |state: &mut ProjectState, inverse: &[diagram_patch::DiagramOp], before| {
+            diagrams::on_diagram(state, diagram_id, |state| diagram_patch::revert_patch(state, inverse, before))
+        };
+        let announced = changes.publish(&app, Change::ComponentChanged {
+            project_id: project_id.clone(),
+            change: ComponentChange::Added,
+            component_id: component.id.clone(),
+            component: Some(component.clone()),
+        });
+        if let Err(e) = announced {
+            if let Some((_, inverse, before)) = &patched {
+                unpatch(state, inverse, *before)?;
+            }
+            state.project.components.pop();
+            (state.project.revision, state.project.updated_at) = (revision, updated_at);
+            return Err(e);
+        }
+        if let Some((change, inverse, before)) = patched {
+            if let Err(e) = changes.publish(&app, change) {
+                // The component itself is already out; only its element goes
+                unpatch(state, &inverse, before)?;
+                return Err(e);
+            }
+        }
+        Ok(component)
+    })?;
+
+    match &added {
+        Some(component) => log::info!("Component added successfully: {} to project {}", component.name, project_id),
+        None => log::debug!("Project not found for component addition: {}", project_id),
     }
+    Ok(added)
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn update_component(
     project_id: String,
     component_id: String,
@@ -788,80 +1118,155 @@ async fn update_component(
     description: Option<String>,
     status: Option<ComponentStatus>,
     dependencies: Option<Vec<String>>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
     projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
 ) -> Result<Option<Component>, ApiError> {
-    let mut store = projects.write().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
+    let entry = projects.get(&project_id)?.ok_or_else(|| {
+        log::debug!("Project not found for component update: {}", project_id);
+        ApiError::ProjectNotFound { project_id: project_id.clone(), source: None }
     })?;
-    
-    if let Some(project) = store.get_mut(&project_id) {
-        if let Some(component) = project.components.iter_mut().find(|c| c.id == component_id) {
-            if let Some(new_name) = name {
-                if new_name.trim().is_empty() {
-                    return Err(ApiError::InvalidComponentData {
-                        details: "Component name cannot be empty".to_string(),
-                        source: None,
-                    });
-                }
-                if new_name.len() > 255 {
-                    return Err(ApiError::InvalidComponentData {
-                        details: format!("Component name too long: {} characters (max 255)", new_name.len()),
-                        source: None,
-                    });
+    let mut state = write_entry(&entry)?;
+    let project = &mut state.project;
+    let Some(index) = project.components.iter().position(|c| c.id == component_id) else {
+        log::debug!("Component not found for update: {} in project {}", component_id, project_id);
+        return Err(ApiError::ComponentNotFound { component_id, project_id, source: None });
+    };
+    let current = &project.components[index];
+    check_revision("component", &current.id, expected_revision, current.revision, current)?;
+    if let Some(new_name) = &name {
+        if new_name.trim().is_empty() {
+            return Err(ApiError::InvalidComponentData {
+                details: "Component name cannot be empty".to_string(),
+                source: None,
+            });
+        }
+        if new_name.len() > 255 {
+            return Err(ApiError::InvalidComponentData {
+                details: format!("Component name too long: {} characters (max 255)", new_name.len()),
+                source: None,
+            });
+        }
+    }
+
+    // Checked last, so an update refused for any other reason never warns about a cycle
+    let dependencies = dependencies
+        .map(|deps| dependencies::validate_dependencies(project, &component_id, deps))
+        .transpose()?;
+    if let Some(new_dependencies) = &dependencies {
+        let policy = project.settings.dependency_cycles;
+        if policy != CyclePolicy::Allow {
+            if let Some(cycle) = dependency_graph::find_new_cycle(project, &component_id, new_dependencies) {
+                if policy == CyclePolicy::Reject {
+                    return Err(ApiError::DependencyCycle { component_id, cycle, source: None });
                 }
-                component.name = new_name.trim().to_string();
+                dependency_graph::warn_cycle(&app, &changes, &project_id, &component_id, cycle)?;
             }
-            if let Some(new_description) = description {
-                component.description = new_description.trim().to_string();
-            }
-            if let Some(new_status) = status {
-                component.status = new_status;
-            }
-            if let Some(new_dependencies) = dependencies {
-                component.dependencies = new_dependencies;
-            }
-            
-            project.updated_at = Utc::now();
-            log::info!("Component updated successfully: {} in project {}", component.name, project_id);
-            Ok(Some(component.clone()))
-        } else {
-            log::debug!("Component not found for update: {} in project {}", component_id, project_id);
-            Err(ApiError::ComponentNotFound { component_id, project_id, source: None })
         }
-    } else {
-        log::debug!("Project not found for component update: {}", project_id);
-        Err(ApiError::ProjectNotFound { project_id, source: None })
     }
+
+    let component = &mut project.components[index];
+    if let Some(new_name) = name {
+        component.name = new_name.trim().to_string();
+    }
+    if let Some(new_description) = description {
+        component.description = new_description.trim().to_string();
+    }
+    if let Some(new_status) = status {
+        component.status = new_status;
+    }
+    if let Some(new_dependencies) = dependencies {
+        component.dependencies = new_dependencies;
+    }
+
+    component.revision += 1;
+    let updated = component.clone();
+    project.revision += 1;
+    project.updated_at = Utc::now();
+    changes.publish(&app, Change::ComponentChanged {
+        project_id: project_id.clone(),
+        change: ComponentChange::Updated,
+        component_id: updated.id.clone(),
+        component: Some(updated.clone()),
+    })?;
+    log::info!("Component updated successfully: {} in project {}", updated.name, project_id);
+    Ok(Some(updated))
 }
 
 #[tauri::command]
 async fn remove_component(
     project_id: String,
     component_id: String,
+    remove_elements: Option<bool>,
+    app: AppHandle,
     projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
 ) -> Result<bool, ApiError> {
-    let mut store = projects.write().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    
-    if let Some(project) = store.get_mut(&project_id) {
+    let removed = projects.write(&project_id, |state| {
+        let project = &mut state.project;
         let initial_len = project.components.len();
         project.components.retain(|c| c.id != component_id);
         project.updated_at = Utc::now();
-        
+
         let success = project.components.len() < initial_len;
         if success {
-            log::info!("Component removed successfully: {} from project {}", component_id, project_id);
-        } else {
-            log::debug!("Component not found for removal: {} in project {}", component_id, project_id);
+            project.revision += 1;
+            changes.publish(&app, Change::ComponentChanged {
+                project_id: project_id.clone(),
+                change: ComponentChange::Removed,
+                component_id: component_id.clone(),
+                component: None,
+            })?;
+            for dependent in dependencies::remove_dependency_references(project, &component_id) {
+                log::debug!("Removed dangling dependency on {} from component {}", component_id, dependent.id);
+                changes.publish(&app, Change::ComponentChanged {
+                    project_id: project_id.clone(),
+                    change: ComponentChange::Updated,
+                    component_id: dependent.id.clone(),
+                    component: Some(dependent),
+                })?;
+            }
+        }
+        // Elements that drew the component go from every diagram it was on when asked to, and
+        // connections drawn straight to the component go either way
+        if success {
+            let diagram_ids: Vec<String> = diagrams::summaries(state).into_iter().map(|d| d.id).collect();
+            for id in &diagram_ids {
+                let diagram_id = Some(id.as_str());
+                diagrams::on_diagram(state, diagram_id, |state| {
+                    let ops = component_links::remove_element_ops(&state.diagram, &component_id);
+                    if remove_elements.unwrap_or(false) && !ops.is_empty() {
+                        diagram_patch::commit_patch(state, diagram_id, ops, &app, &changes)?;
+                    }
+                    if diagram_patch::drop_dangling_connections(state) {
+                        state.diagram_revision += 1;
+                        changes.publish(&app, Change::ConnectionsSaved {
+                            project_id: project_id.clone(),
+                            diagram_id: id.clone(),
+                            connections: state.connections.clone(),
+                        })?;
+                    }
+                    Ok(())
+                })?;
+            }
         }
-        
         Ok(success)
-    } else {
-        log::debug!("Project not found for component removal: {}", project_id);
-        Err(ApiError::ProjectNotFound { project_id, source: None })
+    })?;
+
+    match removed {
+        Some(success) => {
+            if success {
+                log::info!("Component removed successfully: {} from project {}", component_id, project_id);
+            } else {
+                log::debug!("Component not found for removal: {} in project {}", component_id, project_id);
+            }
+            Ok(success)
+        }
+        None => {
+            log::debug!("Project not found for component removal: {}", project_id);
+            Err(ApiError::ProjectNotFound { project_id, source: None })
+        }
     }
 }
 
@@ -869,30 +1274,57 @@ async fn remove_component(
 #[tauri::command]
 async fn save_diagram(
     project_id: String,
+    diagram_id: Option<String>,
     elements: Vec<DiagramElement>,
-    diagrams: State<'_, DiagramStore>,
-) -> Result<(), ApiError> {
-    let mut store = diagrams.write().map_err(|_| ApiError::StateLockError {
-        resource: "DiagramStore".to_string(),
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<u64, ApiError> {
+    let invalid = |details| ApiError::InvalidProjectData {
+        details: format!("Invalid diagram: {}", details),
         source: None,
+    };
+    diagram_patch::check_elements(&elements).map_err(invalid)?;
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let diagram_revision = diagrams::on_diagram(&mut state, diagram_id.as_deref(), |state| {
+        diagram_patch::check_diagram_revision(state, diagram_id.as_deref(), expected_revision)?;
+        diagram_patch::check_component_links(&elements, &state.diagram, &state.project.components).map_err(invalid)?;
+        state.diagram = elements;
+        let dropped = diagram_patch::drop_dangling_connections(state);
+        state.diagram_revision += 1;
+        let diagram_id = diagram_id.clone().unwrap_or_else(|| diagrams::MAIN_DIAGRAM_ID.to_string());
+        changes.publish(&app, Change::DiagramSaved {
+            project_id: project_id.clone(),
+            diagram_id: diagram_id.clone(),
+            elements: state.diagram.clone(),
+        })?;
+        if dropped {
+            changes.publish(&app, Change::ConnectionsSaved {
+                project_id: project_id.clone(),
+                diagram_id,
+                connections: state.connections.clone(),
+            })?;
+        }
+        Ok(state.diagram_revision)
     })?;
-    
-    store.insert(project_id.clone(), elements);
     log::debug!("Diagram saved successfully for project: {}", project_id);
-    Ok(())
+    Ok(diagram_revision)
 }
 
 #[tauri::command]
 async fn load_diagram(
     project_id: String,
-    diagrams: State<'_, DiagramStore>,
+    diagram_id: Option<String>,
+    projects: State<'_, ProjectStore>,
 ) -> Result<Vec<DiagramElement>, ApiError> {
-    let store = diagrams.read().map_err(|_| ApiError::StateLockError {
-        resource: "DiagramStore".to_string(),
-        source: None,
-    })?;
-    
-    let elements = store.get(&project_id).cloned().unwrap_or_default();
+    let elements = projects
+        .read(&project_id, |state| {
+            diagrams::read_diagram(state, diagram_id.as_deref(), |view| view.elements.to_vec())
+        })?
+        .transpose()?
+        .unwrap_or_default();
     log::debug!("Diagram loaded for project: {} ({} elements)", project_id, elements.len());
     Ok(elements)
 }
@@ -900,30 +1332,46 @@ async fn load_diagram(
 #[tauri::command]
 async fn save_connections(
     project_id: String,
+    diagram_id: Option<String>,
     connections: Vec<Connection>,
-    connection_store: State<'_, ConnectionStore>,
-) -> Result<(), ApiError> {
-    let mut store = connection_store.write().map_err(|_| ApiError::StateLockError {
-        resource: "ConnectionStore".to_string(),
-        source: None,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<u64, ApiError> {
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    let diagram_revision = diagrams::on_diagram(&mut state, diagram_id.as_deref(), |state| {
+        diagram_patch::check_diagram_revision(state, diagram_id.as_deref(), expected_revision)?;
+        diagram_patch::check_connections(state, &connections).map_err(|details| ApiError::InvalidProjectData {
+            details: format!("Invalid connections: {}", details),
+            source: None,
+        })?;
+        state.connections = connections;
+        state.diagram_revision += 1;
+        changes.publish(&app, Change::ConnectionsSaved {
+            project_id: project_id.clone(),
+            diagram_id: diagram_id.clone().unwrap_or_else(|| diagrams::MAIN_DIAGRAM_ID.to_string()),
+            connections: state.connections.clone(),
+        })?;
+        Ok(state.diagram_revision)
     })?;
-    
-    store.insert(project_id.clone(), connections);
     log::debug!("Connections saved successfully for project: {}", project_id);
-    Ok(())
+    Ok(diagram_revision)
 }
 
 #[tauri::command]
 async fn load_connections(
     project_id: String,
-    connection_store: State<'_, ConnectionStore>,
+    diagram_id: Option<String>,
+    projects: State<'_, ProjectStore>,
 ) -> Result<Vec<Connection>, ApiError> {
-    let store = connection_store.read().map_err(|_| ApiError::StateLockError {
-        resource: "ConnectionStore".to_string(),
-        source: None,
-    })?;
-    
-    let connections = store.get(&project_id).cloned().unwrap_or_default();
+    let connections = projects
+        .read(&project_id, |state| {
+            diagrams::read_diagram(state, diagram_id.as_deref(), |view| view.connections.to_vec())
+        })?
+        .transpose()?
+        .unwrap_or_default();
     log::debug!("Connections loaded for project: {} ({} connections)", project_id, connections.len());
     Ok(connections)
 }
@@ -1299,6 +1747,50 @@ async fn test_transcription_pipeline(
     }
 }
 
+#[tauri::command]
+async fn save_transcript(
+    project_id: String,
+    text: String,
+    segments: Vec<TranscriptionSegment>,
+    audio_path: Option<String>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<ProjectTranscript, ApiError> {
+    let audio_path = audio_path
+        .map(|path| audio_session_path(&path).map(|p| p.to_string_lossy().to_string()))
+        .transpose()?;
+    let transcript = ProjectTranscript {
+        id: Uuid::new_v4().to_string(),
+        text,
+        segments,
+        audio_path,
+        created_at: Utc::now(),
+    };
+
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    state.transcripts.push(transcript.clone());
+    changes.publish(&app, Change::TranscriptSaved {
+        project_id: project_id.clone(),
+        transcript: transcript.clone(),
+    })?;
+    log::debug!("Transcript saved for project: {} ({})", project_id, transcript.id);
+    Ok(transcript)
+}
+
+#[tauri::command]
+async fn load_transcripts(
+    project_id: String,
+    projects: State<'_, ProjectStore>,
+) -> Result<Vec<ProjectTranscript>, ApiError> {
+    let project_transcripts = projects
+        .read(&project_id, |state| state.transcripts.clone())?
+        .unwrap_or_default();
+    log::debug!("Transcripts loaded for project: {} ({} transcripts)", project_id, project_transcripts.len());
+    Ok(project_transcripts)
+}
+
 // Utility commands
 #[tauri::command]
 async fn get_app_version() -> Result<String, ApiError> {
@@ -1353,37 +1845,25 @@ async fn show_in_folder(path: String) -> Result<(), ApiError> {
     Ok(())
 }
 
+// The main diagram keeps its top-level keys; other diagrams are listed under `diagrams`
+fn export_value(state: &ProjectState) -> serde_json::Value {
+    serde_json::json!({
+        "project": state.project,
+        "diagram_elements": containment::parents_first(&state.diagram),
+        "connections": state.connections,
+        "diagrams": diagrams::diagram_set(state),
+        "exported_at": Utc::now()
+    })
+}
+
 #[tauri::command]
 async fn export_project_data(
     project_id: String,
     projects: State<'_, ProjectStore>,
-    diagrams: State<'_, DiagramStore>,
-    connections: State<'_, ConnectionStore>,
 ) -> Result<String, ApiError> {
-    let project_store = projects.read().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
-    let diagram_store = diagrams.read().map_err(|_| ApiError::StateLockError {
-        resource: "DiagramStore".to_string(),
-        source: None,
-    })?;
-    let connection_store = connections.read().map_err(|_| ApiError::StateLockError {
-        resource: "ConnectionStore".to_string(),
-        source: None,
-    })?;
-
-    let project = project_store.get(&project_id)
-        .ok_or_else(|| ApiError::ProjectNotFound { project_id: project_id.clone(), source: None })?;
-    let diagram_elements = diagram_store.get(&project_id).cloned().unwrap_or_default();
-    let diagram_connections = connection_store.get(&project_id).cloned().unwrap_or_default();
-
-    let export_data = serde_json::json!({
-        "project": project,
-        "diagram_elements": diagram_elements,
-        "connections": diagram_connections,
-        "exported_at": Utc::now()
-    });
+    // Serialize under a single project read lock so the export is a consistent snapshot
+    let entry = projects.require(&project_id)?;
+    let export_data = export_value(&*read_entry(&entry)?);
 
     let json_string = serde_json::to_string_pretty(&export_data)
         .map_err(|e| ApiError::SerializationError {
@@ -1399,18 +1879,16 @@ async fn export_project_data(
 #[cfg(debug_assertions)]
 #[tauri::command]
 async fn populate_sample_data(
+    app: AppHandle,
     projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
 ) -> Result<Vec<Project>, ApiError> {
     let sample_projects = dev_utils::create_sample_projects();
-    let mut store = projects.write().map_err(|_| ApiError::StateLockError {
-        resource: "ProjectStore".to_string(),
-        source: None,
-    })?;
     
     let mut result = Vec::new();
     for project in sample_projects {
-        let project_id = project.id.clone();
-        store.insert(project_id, project.clone());
+        projects.insert(ProjectState::new(project.clone()))?;
+        changes.publish(&app, Change::ProjectCreated { project: project.clone() })?;
         result.push(project);
     }
     
@@ -1425,8 +1903,8 @@ fn main() {
 
     tauri::Builder::default()
         .manage(ProjectStore::default())
-        .manage(DiagramStore::default())
-        .manage(ConnectionStore::default())
+        .manage(ChangeFeed::default())
+        .manage(search::SearchIndex::in_memory())
         .manage(TranscriptionJobStore::new(Mutex::new(HashMap::new())))
         // .manage(Mutex::new(NativeRecorder::new()))
         .invoke_handler({
@@ -1437,6 +1915,37 @@ fn main() {
                         create_project,
                         get_projects,
                         get_project,
+                        project_query::query_projects,
+                        dependency_graph::analyze_dependencies,
+                        impact::analyze_impact,
+                        lint::lint_project,
+                        lint_rules::set_project_lint_rules,
+                        capacity::estimate_capacity,
+                        capacity::set_component_capacity,
+                        latency::analyze_latency,
+                        availability::analyze_availability,
+                        availability::set_component_availability,
+                        failure::simulate_failure,
+                        simulation::simulate_traffic,
+                        cost::estimate_costs,
+                        cost::compare_costs,
+                        cost::get_pricing_table,
+                        diagram_patch::patch_diagram,
+                        diagram_patch::get_diagram_snapshot,
+                        layout::auto_layout,
+                        geometry::get_overlaps,
+                        geometry::fix_overlaps,
+                        geometry::arrange_elements,
+                        routing::route_diagram_connections,
+                        containment::group_elements,
+                        containment::ungroup_elements,
+                        diagrams::list_diagrams,
+                        diagrams::create_diagram,
+                        diagrams::rename_diagram,
+                        diagrams::duplicate_diagram,
+                        diagrams::delete_diagram,
+                        component_links::reconcile_components,
+
                         update_project,
                         delete_project,
                         
@@ -1463,6 +1972,19 @@ fn main() {
                         transcribe_audio,
                         cancel_transcription,
                         test_transcription_pipeline,
+                        save_transcript,
+                        load_transcripts,
+
+                        // Project Bundle Commands
+                        bundle::save_project_bundle,
+                        bundle::open_project_bundle,
+
+                        // Search Commands
+                        search::search,
+
+                        // Change Event Commands
+                        events::get_change_revision,
+                        events::get_changes_since,
 
                         // Challenge Plugin I/O
                         load_challenges_from_file,
@@ -1475,6 +1997,37 @@ fn main() {
                         create_project,
                         get_projects,
                         get_project,
+                        project_query::query_projects,
+                        dependency_graph::analyze_dependencies,
+                        impact::analyze_impact,
+                        lint::lint_project,
+                        lint_rules::set_project_lint_rules,
+                        capacity::estimate_capacity,
+                        capacity::set_component_capacity,
+                        latency::analyze_latency,
+                        availability::analyze_availability,
+                        availability::set_component_availability,
+                        failure::simulate_failure,
+                        simulation::simulate_traffic,
+                        cost::estimate_costs,
+                        cost::compare_costs,
+                        cost::get_pricing_table,
+                        diagram_patch::patch_diagram,
+                        diagram_patch::get_diagram_snapshot,
+                        layout::auto_layout,
+                        geometry::get_overlaps,
+                        geometry::fix_overlaps,
+                        geometry::arrange_elements,
+                        routing::route_diagram_connections,
+                        containment::group_elements,
+                        containment::ungroup_elements,
+                        diagrams::list_diagrams,
+                        diagrams::create_diagram,
+                        diagrams::rename_diagram,
+                        diagrams::duplicate_diagram,
+                        diagrams::delete_diagram,
+                        component_links::reconcile_components,
+
                         update_project,
                         delete_project,
                         
@@ -1501,6 +2054,19 @@ fn main() {
                         transcribe_audio,
                         cancel_transcription,
                         test_transcription_pipeline,
+                        save_transcript,
+                        load_transcripts,
+
+                        // Project Bundle Commands
+                        bundle::save_project_bundle,
+                        bundle::open_project_bundle,
+
+                        // Search Commands
+                        search::search,
+
+                        // Change Event Commands
+                        events::get_change_revision,
+                        events::get_changes_since,
 
                         // Challenge Plugin I/O
                         load_challenges_from_file,
@@ -1541,10 +2107,7 @@ mod tests {
             id: "id1".into(),
             name: "Name".into(),
             description: "Desc".into(),
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-            status: ProjectStatus::Planning,
-            components: vec![],
+            ..test_support::project()
         };
         let s = serde_json::to_string(&p).unwrap();
         let v: serde_json::Value = serde_json::from_str(&s).unwrap();
@@ -1553,6 +2116,60 @@ mod tests {
         assert_eq!(v["status"], json!("Planning"));
     }
 
+    #[test]
+    fn check_revision_reports_server_copy() {
+        let component = Component {
+            name: "Auth".into(),
+            status: ComponentStatus::Done,
+            revision: 4,
+            ..test_support::component("c1")
+        };
+        assert!(check_revision("component", "c1", None, 4, &component).is_ok());
+        assert!(check_revision("component", "c1", Some(4), 4, &component).is_ok());
+
+        let err = check_revision("component", "c1", Some(3), 4, &component).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Revision conflict on component c1: expected revision 3, current is 4"
+        );
+        match err {
+            ApiError::RevisionConflict { current, current_revision, .. } => {
+                assert_eq!(current_revision, 4);
+                assert_eq!(current["name"], json!("Auth"));
+                assert_eq!(current["revision"], json!(4));
+            }
+            other => panic!("Expected RevisionConflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn export_includes_every_diagram() {
+        let mut state = ProjectState::new(test_support::project());
+        state.diagram = vec![test_support::element("main-el")];
+        state.views.push(diagrams::Diagram {
+            id: "ops".into(),
+            name: "Operations".into(),
+            kind: Default::default(),
+            elements: vec![test_support::element("ops-el")],
+            connections: vec![],
+            diagram_revision: 3,
+        });
+        let export = export_value(&state);
+        assert_eq!(export["diagram_elements"][0]["id"], "main-el");
+        let views = &export["diagrams"]["views"];
+        assert_eq!(views.as_array().unwrap().len(), 1);
+        assert_eq!(views[0]["id"], "ops");
+        assert_eq!(views[0]["elements"][0]["id"], "ops-el");
+        // Still readable where only the project is wanted
+        assert!(serde_json::from_value::<Project>(export["project"].clone()).is_ok());
+    }
+
+    #[test]
+    fn normalize_tags_trims_and_dedupes() {
+        let tags = normalize_tags(vec![" payments ".into(), "".into(), "Payments".into(), "web".into()]);
+        assert_eq!(tags, vec!["payments".to_string(), "web".to_string()]);
+    }
+
     #[test]
     fn test_validate_filename_security() {
         // Test path traversal attempts
@@ -0,0 +1,348 @@
+// Filtered, sorted and paginated project listings returning lightweight summaries
+
+use crate::state::{read_entry, ProjectStore};
+use crate::{ApiError, Project, ProjectStatus};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use tauri::State;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectSummary {
+    pub id: String,
+    pub name: String,
+    pub status: ProjectStatus,
+    pub component_count: usize,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The fields a query filters and sorts on, copied without the project's components
+#[derive(Debug, Clone)]
+pub struct ProjectListing {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub status: ProjectStatus,
+    pub tags: Vec<String>,
+    pub component_count: usize,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<&Project> for ProjectListing {
+    fn from(project: &Project) -> Self {
+        Self {
+            id: project.id.clone(),
+            name: project.name.clone(),
+            description: project.description.clone(),
+            status: project.status.clone(),
+            tags: project.tags.clone(),
+            component_count: project.components.len(),
+            created_at: project.created_at,
+            updated_at: project.updated_at,
+        }
+    }
+}
+
+impl From<ProjectListing> for ProjectSummary {
+    fn from(listing: ProjectListing) -> Self {
+        Self {
+            id: listing.id,
+            name: listing.name,
+            status: listing.status,
+            component_count: listing.component_count,
+            updated_at: listing.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProjectSortKey {
+    Name,
+    CreatedAt,
+    #[default]
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortDirection {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectQuery {
+    /// Matches projects in any of the given states
+    pub status: Option<Vec<ProjectStatus>>,
+    /// Case-insensitive substring match on name and description
+    pub text: Option<String>,
+    pub updated_after: Option<DateTime<Utc>>,
+    /// Projects must carry every listed tag
+    pub tags: Option<Vec<String>>,
+    pub sort_by: Option<ProjectSortKey>,
+    pub direction: Option<SortDirection>,
+    pub limit: Option<usize>,
+    /// Opaque cursor from a previous page's `next_cursor`
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectPage {
+    pub items: Vec<ProjectSummary>,
+    pub next_cursor: Option<String>,
+    /// Number of projects matching the filters across all pages
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+enum SortValue {
+    Text(String),
+    Timestamp(DateTime<Utc>),
+}
+
+// Position of the last item on a page; the next page starts strictly after it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PageCursor {
+    sort_by: ProjectSortKey,
+    direction: SortDirection,
+    value: SortValue,
+    id: String,
+}
+
+fn sort_value(project: &ProjectListing, sort_by: ProjectSortKey) -> SortValue {
+    match sort_by {
+        ProjectSortKey::Name => SortValue::Text(project.name.to_lowercase()),
+        ProjectSortKey::CreatedAt => SortValue::Timestamp(project.created_at),
+        ProjectSortKey::UpdatedAt => SortValue::Timestamp(project.updated_at),
+    }
+}
+
+fn compare_keys(a: (&SortValue, &str), b: (&SortValue, &str), direction: SortDirection) -> Ordering {
+    let ordering = a
+        .0
+        .partial_cmp(b.0)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.1.cmp(b.1));
+    match direction {
+        SortDirection::Ascending => ordering,
+        SortDirection::Descending => ordering.reverse(),
+    }
+}
+
+fn encode_cursor(cursor: &PageCursor) -> Result<String, ApiError> {
+    let raw = serde_json::to_vec(cursor)?;
+    Ok(raw.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn decode_cursor(encoded: &str) -> Result<PageCursor, ApiError> {
+    let invalid = || ApiError::InvalidProjectData {
+        details: "Invalid pagination cursor".to_string(),
+        source: None,
+    };
+    if !encoded.len().is_multiple_of(2) || !encoded.is_ascii() {
+        return Err(invalid());
+    }
+    let raw = (0..encoded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid())?;
+    serde_json::from_slice(&raw).map_err(|_| invalid())
+}
+
+fn matches_filters(project: &ProjectListing, query: &ProjectQuery, text: Option<&str>, tags: &[String]) -> bool {
+    if let Some(statuses) = &query.status {
+        if !statuses.is_empty() && !statuses.contains(&project.status) {
+            return false;
+        }
+    }
+    if let Some(after) = query.updated_after {
+        if project.updated_at <= after {
+            return false;
+        }
+    }
+    if let Some(needle) = text {
+        if !project.name.to_lowercase().contains(needle) && !project.description.to_lowercase().contains(needle) {
+            return false;
+        }
+    }
+    tags.iter()
+        .all(|tag| project.tags.iter().any(|own| own.eq_ignore_ascii_case(tag)))
+}
+
+pub fn query_projects_in(
+    projects: impl IntoIterator<Item = ProjectListing>,
+    query: &ProjectQuery,
+) -> Result<ProjectPage, ApiError> {
+    let sort_by = query.sort_by.unwrap_or_default();
+    let direction = query.direction.unwrap_or_default();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let text = query
+        .text
+        .as_deref()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty());
+    let tags: Vec<String> = query
+        .tags
+        .iter()
+        .flatten()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let cursor = match &query.cursor {
+        Some(encoded) => {
+            let cursor = decode_cursor(encoded)?;
+            if cursor.sort_by != sort_by || cursor.direction != direction {
+                return Err(ApiError::InvalidProjectData {
+                    details: "Pagination cursor was issued for a different sort order".to_string(),
+                    source: None,
+                });
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+
+    let mut matching: Vec<(SortValue, ProjectListing)> = projects
+        .into_iter()
+        .filter(|p| matches_filters(p, query, text.as_deref(), &tags))
+        .map(|p| (sort_value(&p, sort_by), p))
+        .collect();
+    matching.sort_by(|a, b| compare_keys((&a.0, &a.1.id), (&b.0, &b.1.id), direction));
+    let total = matching.len();
+
+    let start = match &cursor {
+        Some(c) => matching.partition_point(|(value, p)| {
+            compare_keys((value, &p.id), (&c.value, &c.id), direction) != Ordering::Greater
+        }),
+        None => 0,
+    };
+    let page: Vec<(SortValue, ProjectListing)> = matching.into_iter().skip(start).take(limit).collect();
+
+    let next_cursor = match page.last() {
+        Some((value, project)) if start + page.len() < total => Some(encode_cursor(&PageCursor {
+            sort_by,
+            direction,
+            value: value.clone(),
+            id: project.id.clone(),
+        })?),
+        _ => None,
+    };
+
+    Ok(ProjectPage {
+        items: page.into_iter().map(|(_, p)| ProjectSummary::from(p)).collect(),
+        next_cursor,
+        total,
+    })
+}
+
+#[tauri::command]
+pub async fn query_projects(
+    query: Option<ProjectQuery>,
+    projects: State<'_, ProjectStore>,
+) -> Result<ProjectPage, ApiError> {
+    // Each project lock is held only while its listing fields are copied
+    let mut listings = Vec::new();
+    for entry in projects.entries()? {
+        listings.push(ProjectListing::from(&read_entry(&entry)?.project));
+    }
+
+    let page = query_projects_in(listings, &query.unwrap_or_default())?;
+    log::debug!("Project query returned {} of {} projects", page.items.len(), page.total);
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use chrono::Duration;
+
+    fn project(id: &str, name: &str, status: ProjectStatus, age_hours: i64, tags: &[&str]) -> Project {
+        let updated = Utc::now() - Duration::hours(age_hours);
+        Project {
+            id: id.into(),
+            name: name.into(),
+            description: format!("{} description", name),
+            created_at: updated - Duration::days(1),
+            updated_at: updated,
+            status,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..test_support::project()
+        }
+    }
+
+    fn fixtures() -> Vec<Project> {
+        vec![
+            project("a", "Billing", ProjectStatus::Planning, 1, &["payments"]),
+            project("b", "analytics", ProjectStatus::Review, 5, &["data"]),
+            project("c", "Checkout", ProjectStatus::Planning, 3, &["payments", "web"]),
+            project("d", "Dashboards", ProjectStatus::Complete, 48, &["data", "web"]),
+        ]
+    }
+
+    #[test]
+    fn filters_and_summarizes() {
+        let projects = fixtures();
+        let query = ProjectQuery {
+            status: Some(vec![ProjectStatus::Planning]),
+            tags: Some(vec!["PAYMENTS".into()]),
+            ..Default::default()
+        };
+        let page = query_projects_in(projects.iter().map(ProjectListing::from), &query).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), ["a", "c"]);
+
+        let query = ProjectQuery {
+            text: Some("DASH".into()),
+            ..Default::default()
+        };
+        assert_eq!(query_projects_in(projects.iter().map(ProjectListing::from), &query).unwrap().items[0].id, "d");
+
+        let query = ProjectQuery {
+            updated_after: Some(Utc::now() - Duration::hours(4)),
+            ..Default::default()
+        };
+        assert_eq!(query_projects_in(projects.iter().map(ProjectListing::from), &query).unwrap().total, 2);
+    }
+
+    #[test]
+    fn sorts_by_name_case_insensitively() {
+        let projects = fixtures();
+        let query = ProjectQuery {
+            sort_by: Some(ProjectSortKey::Name),
+            direction: Some(SortDirection::Ascending),
+            ..Default::default()
+        };
+        let page = query_projects_in(projects.iter().map(ProjectListing::from), &query).unwrap();
+        let names: Vec<_> = page.items.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["analytics", "Billing", "Checkout", "Dashboards"]);
+    }
+
+    #[test]
+    fn paginates_with_cursor_without_skipping_or_repeating() {
+        let projects = fixtures();
+        let mut query = ProjectQuery {
+            limit: Some(3),
+            ..Default::default()
+        };
+        let first = query_projects_in(projects.iter().map(ProjectListing::from), &query).unwrap();
+        assert_eq!(first.items.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), ["a", "c", "b"]);
+
+        query.cursor = first.next_cursor.clone();
+        assert!(query.cursor.is_some());
+        let second = query_projects_in(projects.iter().map(ProjectListing::from), &query).unwrap();
+        assert_eq!(second.items.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), ["d"]);
+        assert!(second.next_cursor.is_none());
+
+        query.sort_by = Some(ProjectSortKey::Name);
+        assert!(query_projects_in(projects.iter().map(ProjectListing::from), &query).is_err());
+        query.cursor = Some("not-a-cursor".into());
+        assert!(query_projects_in(projects.iter().map(ProjectListing::from), &query).is_err());
+    }
+}
@@ -0,0 +1,565 @@
+// Orthogonal connection routing
+//
+// Every element's box, grown by the routing margin, is an obstacle. The candidate lines are
+// the edges of those grown boxes plus the lines through element centres; their crossings form
+// a sparse grid, and each connection takes the cheapest path through it from centre to centre,
+// where every bend costs as much as `bend_penalty` units of length. A connection may pass
+// through its own two ends, so the renderer clips the first and last segments at the boxes,
+// and through the containers around them, since it has to get in and out of those.
+//
+// Only the bends are stored, as the connection's waypoints. Connections that can't be routed
+// are reported and left alone. Moving an element drops the routes to it and to anything inside
+// it; route again to refresh them.
+
+use crate::diagram_patch::{check_diagram_revision, commit_patch, snapshot_diagram, DiagramOp};
+use crate::diagrams::on_diagram;
+use crate::containment;
+use crate::events::ChangeFeed;
+use crate::geometry::Bounds;
+use crate::state::{write_entry, ProjectState, ProjectStore};
+use crate::{ApiError, Position};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use tauri::{AppHandle, State};
+
+// Grids past this many points take too long to search; route a selection instead
+const MAX_GRID_POINTS: usize = 1_000_000;
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteOptions {
+    /// Clearance kept between a route and the boxes it passes
+    pub margin: f64,
+    /// Extra length a route will take to save one bend
+    pub bend_penalty: f64,
+}
+
+impl Default for RouteOptions {
+    fn default() -> Self {
+        Self {
+            margin: 20.0,
+            bend_penalty: 100.0,
+        }
+    }
+}
+
+impl RouteOptions {
+    fn validate(&self) -> Result<(), ApiError> {
+        for (name, value) in [("margin", self.margin), ("bend_penalty", self.bend_penalty)] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(ApiError::InvalidProjectData {
+                    details: format!("Routing {} must be a non-negative number", name),
+                    source: None,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteResult {
+    pub diagram_revision: u64,
+    /// Connections whose waypoints changed
+    pub routed: usize,
+    /// Connections without a clear path, or whose ends aren't both diagram elements
+    pub unrouted: Vec<String>,
+    pub inverse: Vec<DiagramOp>,
+}
+
+struct Rect {
+    left: f64,
+    top: f64,
+    right: f64,
+    bottom: f64,
+}
+
+#[derive(PartialEq)]
+struct Visit {
+    cost: f64,
+    state: usize,
+}
+
+impl Eq for Visit {}
+
+impl Ord for Visit {
+    // Cheapest first out of the max-heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost).then(other.state.cmp(&self.state))
+    }
+}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Directions a route can travel in: right, left, down, up
+const STEPS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+struct Grid {
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    /// Elements whose grown box covers each grid segment, horizontal segments first
+    blockers: Vec<Vec<usize>>,
+}
+
+/// Search buffers shared by every connection routed over one grid
+struct Search {
+    cost: Vec<f64>,
+    previous: Vec<usize>,
+    /// States written by the last search, so only those need resetting
+    touched: Vec<usize>,
+    queue: BinaryHeap<Visit>,
+}
+
+impl Search {
+    fn new(grid: &Grid) -> Self {
+        // States are a point and the direction the route arrived in
+        let states = grid.xs.len() * grid.ys.len() * 4;
+        Self {
+            cost: vec![f64::INFINITY; states],
+            previous: vec![usize::MAX; states],
+            touched: Vec::new(),
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        for state in self.touched.drain(..) {
+            self.cost[state] = f64::INFINITY;
+            self.previous[state] = usize::MAX;
+        }
+        self.queue.clear();
+    }
+
+    fn relax(&mut self, state: usize, cost: f64, previous: usize) {
+        if self.cost[state] == f64::INFINITY {
+            self.touched.push(state);
+        }
+        self.cost[state] = cost;
+        self.previous[state] = previous;
+        self.queue.push(Visit { cost, state });
+    }
+}
+
+fn coordinates(mut values: Vec<f64>) -> Vec<f64> {
+    values.sort_by(f64::total_cmp);
+    values.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+    values
+}
+
+fn index_of(values: &[f64], value: f64) -> usize {
+    values.partition_point(|&v| v < value - EPSILON)
+}
+
+impl Grid {
+    fn build(obstacles: &[Rect], centres: &[(f64, f64)]) -> Result<Self, ApiError> {
+        let xs = coordinates(
+            obstacles
+                .iter()
+                .flat_map(|r| [r.left, r.right])
+                .chain(centres.iter().map(|c| c.0))
+                .collect(),
+        );
+        let ys = coordinates(
+            obstacles
+                .iter()
+                .flat_map(|r| [r.top, r.bottom])
+                .chain(centres.iter().map(|c| c.1))
+                .collect(),
+        );
+        if xs.len() * ys.len() > MAX_GRID_POINTS {
+            return Err(ApiError::InvalidProjectData {
+                details: format!("Diagram is too large to route at once ({} elements)", obstacles.len()),
+                source: None,
+            });
+        }
+
+        let mut grid = Grid {
+            blockers: vec![Vec::new(); 2 * xs.len() * ys.len()],
+            xs,
+            ys,
+        };
+        // Box edges are grid lines, so a segment is blocked exactly when it lies inside a box
+        for (element, rect) in obstacles.iter().enumerate() {
+            let (left, right) = (index_of(&grid.xs, rect.left), index_of(&grid.xs, rect.right));
+            let (top, bottom) = (index_of(&grid.ys, rect.top), index_of(&grid.ys, rect.bottom));
+            for j in top + 1..bottom {
+                for i in left..right {
+                    let segment = grid.segment(i, j, 0);
+                    grid.blockers[segment].push(element);
+                }
+            }
+            for i in left + 1..right {
+                for j in top..bottom {
+                    let segment = grid.segment(i, j, 2);
+                    grid.blockers[segment].push(element);
+                }
+            }
+        }
+        Ok(grid)
+    }
+
+    /// The segment leaving point (i, j) rightwards (steps 0/1) or downwards (steps 2/3)
+    fn segment(&self, i: usize, j: usize, step: usize) -> usize {
+        let point = j * self.xs.len() + i;
+        if step < 2 {
+            point
+        } else {
+            self.xs.len() * self.ys.len() + point
+        }
+    }
+
+    fn neighbour(&self, point: usize, step: usize) -> Option<(usize, usize)> {
+        let (i, j) = (point % self.xs.len(), point / self.xs.len());
+        let (di, dj) = STEPS[step];
+        let (ni, nj) = (i.checked_add_signed(di)?, j.checked_add_signed(dj)?);
+        if ni >= self.xs.len() || nj >= self.ys.len() {
+            return None;
+        }
+        // Segments are stored once, keyed by their top-left end
+        let segment = self.segment(i.min(ni), j.min(nj), step);
+        Some((nj * self.xs.len() + ni, segment))
+    }
+
+    fn point(&self, point: usize) -> Position {
+        Position {
+            x: self.xs[point % self.xs.len()],
+            y: self.ys[point / self.xs.len()],
+        }
+    }
+
+    /// Bend points of the cheapest route, or None when the ends are walled off
+    fn route(
+        &self,
+        search: &mut Search,
+        exempt: &[usize],
+        ends: [(f64, f64); 2],
+        bend_penalty: f64,
+    ) -> Option<Vec<Position>> {
+        let locate = |(x, y): (f64, f64)| index_of(&self.ys, y) * self.xs.len() + index_of(&self.xs, x);
+        let (start, goal) = (locate(ends[0]), locate(ends[1]));
+        if start == goal {
+            return Some(Vec::new());
+        }
+        let passable = |segment: usize| self.blockers[segment].iter().all(|e| exempt.contains(e));
+
+        search.reset();
+        for step in 0..4 {
+            if let Some((next, _)) = self.neighbour(start, step).filter(|&(_, s)| passable(s)) {
+                search.relax(next * 4 + step, self.distance(start, next), usize::MAX);
+            }
+        }
+        let mut reached = None;
+        while let Some(Visit { cost: so_far, state }) = search.queue.pop() {
+            if so_far > search.cost[state] {
+                continue;
+            }
+            let (point, arrived) = (state / 4, state % 4);
+            if point == goal {
+                reached = Some(state);
+                break;
+            }
+            for step in 0..4 {
+                // Never double back on the segment just travelled
+                if step == arrived ^ 1 {
+                    continue;
+                }
+                let Some((next, segment)) = self.neighbour(point, step) else {
+                    continue;
+                };
+                if !passable(segment) {
+                    continue;
+                }
+                let bend = if step == arrived { 0.0 } else { bend_penalty };
+                let total = so_far + self.distance(point, next) + bend;
+                let next_state = next * 4 + step;
+                if total < search.cost[next_state] {
+                    search.relax(next_state, total, state);
+                }
+            }
+        }
+
+        // Walk back, keeping only the points where the direction changes
+        let mut state = reached?;
+        let mut bends = Vec::new();
+        while search.previous[state] != usize::MAX {
+            let before = search.previous[state];
+            if before % 4 != state % 4 {
+                bends.push(self.point(before / 4));
+            }
+            state = before;
+        }
+        bends.reverse();
+        Some(bends)
+    }
+
+    fn distance(&self, a: usize, b: usize) -> f64 {
+        let (pa, pb) = (self.point(a), self.point(b));
+        (pa.x - pb.x).abs() + (pa.y - pb.y).abs()
+    }
+}
+
+/// Waypoint updates for the chosen connections (all of them when none are named)
+pub fn route_connections(
+    state: &ProjectState,
+    connection_ids: Option<&[String]>,
+    options: &RouteOptions,
+) -> Result<(Vec<DiagramOp>, Vec<String>), ApiError> {
+    options.validate()?;
+    if let Some(ids) = connection_ids {
+        let unknown: Vec<&str> = ids
+            .iter()
+            .filter(|id| !state.connections.iter().any(|c| &c.id == *id))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            return Err(ApiError::InvalidProjectData {
+                details: format!("Unknown connections: {}", unknown.join(", ")),
+                source: None,
+            });
+        }
+    }
+
+    let bounds: Vec<Bounds> = state.diagram.iter().map(Bounds::of).collect();
+    let obstacles: Vec<Rect> = bounds
+        .iter()
+        .map(|b| Rect {
+            left: b.x - options.margin,
+            top: b.y - options.margin,
+            right: b.x + b.width + options.margin,
+            bottom: b.y + b.height + options.margin,
+        })
+        .collect();
+    let centres: Vec<(f64, f64)> = bounds.iter().map(|b| (b.x + b.width / 2.0, b.y + b.height / 2.0)).collect();
+    let grid = Grid::build(&obstacles, &centres)?;
+    let index: HashMap<&str, usize> = state.diagram.iter().enumerate().map(|(i, e)| (e.id.as_str(), i)).collect();
+    let mut search = Search::new(&grid);
+
+    let mut ops = Vec::new();
+    let mut unrouted = Vec::new();
+    for connection in &state.connections {
+        if connection_ids.is_some_and(|ids| !ids.contains(&connection.id)) {
+            continue;
+        }
+        let ends = (index.get(connection.source_id.as_str()), index.get(connection.target_id.as_str()));
+        let route = match ends {
+            (Some(&from), Some(&to)) if from != to => {
+                // A route may cross its own ends and the containers they sit in
+                let exempt: Vec<usize> = [from, to]
+                    .into_iter()
+                    .chain(
+                        [&connection.source_id, &connection.target_id]
+                            .into_iter()
+                            .flat_map(|id| containment::ancestors(&state.diagram, id))
+                            .filter_map(|id| index.get(id).copied()),
+                    )
+                    .collect();
+                grid.route(&mut search, &exempt, [centres[from], centres[to]], options.bend_penalty)
+            }
+            _ => None,
+        };
+        match route {
+            Some(waypoints) if waypoints != connection.waypoints => ops.push(DiagramOp::UpdateConnection {
+                id: connection.id.clone(),
+                connection_type: None,
+                properties: HashMap::new(),
+                latency: None,
+                waypoints: Some(waypoints),
+            }),
+            Some(_) => {}
+            None => unrouted.push(connection.id.clone()),
+        }
+    }
+    Ok((ops, unrouted))
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn route_diagram_connections(
+    project_id: String,
+    diagram_id: Option<String>,
+    connection_ids: Option<Vec<String>>,
+    options: Option<RouteOptions>,
+    expected_revision: Option<u64>,
+    app: AppHandle,
+    projects: State<'_, ProjectStore>,
+    changes: State<'_, ChangeFeed>,
+) -> Result<RouteResult, ApiError> {
+    let options = options.unwrap_or_default();
+    let diagram_id = diagram_id.as_deref();
+
+    // The search can take a while on large diagrams, so it runs on a copy without the lock
+    let snapshot = snapshot_diagram(&projects, &project_id, diagram_id, expected_revision)?;
+    let revision = snapshot.diagram_revision;
+    let (ops, unrouted) =
+        tokio::task::spawn_blocking(move || route_connections(&snapshot, connection_ids.as_deref(), &options))
+            .await
+            .map_err(|e| ApiError::Internal {
+                details: format!("Connection routing failed: {}", e),
+                source: Some(Box::new(e)),
+            })??;
+
+    let entry = projects.require(&project_id)?;
+    let mut state = write_entry(&entry)?;
+    on_diagram(&mut state, diagram_id, |state| {
+        // Routes found before an edit made while searching may run through what changed
+        check_diagram_revision(state, diagram_id, Some(revision))?;
+        let routed = ops.len();
+        let inverse = commit_patch(state, diagram_id, ops, &app, &changes)?;
+        log::info!(
+            "Routed {} connections in project {}, {} without a path",
+            routed,
+            project_id,
+            unrouted.len()
+        );
+        Ok(RouteResult {
+            diagram_revision: state.diagram_revision,
+            routed,
+            unrouted,
+            inverse,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagram_patch::apply_patch;
+    use crate::test_support::{connection, diagram_state, element_at};
+    use crate::Connection;
+
+    fn path(state: &ProjectState, connection: &Connection) -> Vec<Position> {
+        let centre = |id: &str| {
+            let b = Bounds::of(state.diagram.iter().find(|e| e.id == id).unwrap());
+            Position {
+                x: b.x + b.width / 2.0,
+                y: b.y + b.height / 2.0,
+            }
+        };
+        let mut points = vec![centre(&connection.source_id)];
+        points.extend(connection.waypoints.iter().cloned());
+        points.push(centre(&connection.target_id));
+        points
+    }
+
+    #[test]
+    fn clear_line_needs_no_waypoints() {
+        let state = diagram_state(
+            vec![element_at("a", 0.0, 0.0), element_at("b", 400.0, 0.0)],
+            vec![connection("ab", "a", "b")],
+        );
+        let (ops, unrouted) = route_connections(&state, None, &RouteOptions::default()).unwrap();
+        assert!(ops.is_empty());
+        assert!(unrouted.is_empty());
+    }
+
+    #[test]
+    fn detours_around_boxes_with_orthogonal_segments() {
+        let mut state = diagram_state(
+            vec![element_at("a", 0.0, 0.0), element_at("wall", 200.0, -100.0), element_at("b", 400.0, 0.0)],
+            vec![connection("ab", "a", "b")],
+        );
+        state.diagram[1].height = 250.0;
+        let (ops, unrouted) = route_connections(&state, None, &RouteOptions::default()).unwrap();
+        assert!(unrouted.is_empty());
+        apply_patch(&mut state, &ops).unwrap();
+
+        let points = path(&state, &state.connections[0]);
+        // Up out of a, over the wall and down into b
+        assert_eq!(points.len(), 4, "{:?}", points);
+        let wall = Bounds::of(&state.diagram[1]);
+        for pair in points.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            assert!(a.x == b.x || a.y == b.y, "{:?} -> {:?} is diagonal", a, b);
+            let (left, right) = (a.x.min(b.x), a.x.max(b.x));
+            let (top, bottom) = (a.y.min(b.y), a.y.max(b.y));
+            let crosses = left < wall.x + wall.width && right > wall.x && top < wall.y + wall.height && bottom > wall.y;
+            assert!(!crosses, "{:?} -> {:?} cuts through the wall", a, b);
+        }
+
+        // Moving an end drops the route, and undoing the move brings it back
+        let routed = state.connections[0].waypoints.clone();
+        let moved = vec![DiagramOp::MoveElement {
+            id: "b".into(),
+            position: Position { x: 400.0, y: 300.0 },
+        }];
+        let inverse = apply_patch(&mut state, &moved).unwrap();
+        assert!(state.connections[0].waypoints.is_empty());
+        apply_patch(&mut state, &inverse).unwrap();
+        assert_eq!(state.connections[0].waypoints, routed);
+    }
+
+    #[test]
+    fn moving_a_container_drops_routes_to_its_children() {
+        let mut group = element_at("group", -50.0, -50.0);
+        (group.width, group.height) = (300.0, 200.0);
+        let mut inner = element_at("inner", 0.0, 0.0);
+        inner.parent_id = Some("group".into());
+        let mut state = diagram_state(
+            vec![group, inner, element_at("out", 600.0, 400.0), element_at("other", 600.0, 0.0)],
+            vec![connection("in-out", "inner", "out"), connection("out-other", "out", "other")],
+        );
+        let bend = vec![Position { x: 50.0, y: 450.0 }];
+        state.connections[0].waypoints = bend.clone();
+        state.connections[1].waypoints = bend.clone();
+        let moved = vec![DiagramOp::MoveElement {
+            id: "group".into(),
+            position: Position { x: 0.0, y: 0.0 },
+        }];
+        apply_patch(&mut state, &moved).unwrap();
+        assert!(state.connections[0].waypoints.is_empty());
+        assert_eq!(state.connections[1].waypoints, bend);
+    }
+
+    #[test]
+    fn reports_connections_without_a_path() {
+        let mut boxed = vec![element_at("a", 0.0, 0.0), element_at("b", 1000.0, 0.0)];
+        // Four long walls closing b in
+        for (id, x, y, w, h) in [
+            ("n", 800.0, -200.0, 500.0, 50.0),
+            ("s", 800.0, 200.0, 500.0, 50.0),
+            ("w", 800.0, -200.0, 50.0, 450.0),
+            ("e", 1250.0, -200.0, 50.0, 450.0),
+        ] {
+            let mut wall = element_at(id, x, y);
+            (wall.width, wall.height) = (w, h);
+            boxed.push(wall);
+        }
+        let state = diagram_state(boxed, vec![connection("ab", "a", "b"), connection("loop", "a", "a")]);
+        let (ops, unrouted) = route_connections(&state, None, &RouteOptions::default()).unwrap();
+        assert!(ops.is_empty());
+        assert_eq!(unrouted, ["ab", "loop"]);
+
+        assert!(route_connections(&state, Some(&["nope".to_string()]), &RouteOptions::default()).is_err());
+    }
+
+    #[test]
+    fn each_connection_routes_as_if_alone() {
+        let mut state = diagram_state(
+            vec![
+                element_at("a", 0.0, 0.0),
+                element_at("wall", 200.0, -100.0),
+                element_at("b", 400.0, 0.0),
+                element_at("c", 0.0, 400.0),
+                element_at("d", 400.0, 400.0),
+            ],
+            vec![connection("ab", "a", "b"), connection("cd", "c", "d"), connection("ad", "a", "d")],
+        );
+        state.diagram[1].height = 250.0;
+        let options = RouteOptions::default();
+        let (together, _) = route_connections(&state, None, &options).unwrap();
+        for op in &together {
+            let DiagramOp::UpdateConnection { id, .. } = op else {
+                panic!("unexpected op {:?}", op);
+            };
+            let (alone, _) = route_connections(&state, Some(std::slice::from_ref(id)), &options).unwrap();
+            assert_eq!(format!("{:?}", alone), format!("{:?}", [op]));
+        }
+        assert!(!together.is_empty());
+    }
+}
@@ -0,0 +1,594 @@
+// Full-text search across projects, components, diagram elements and transcripts
+//
+// Elements are indexed from every diagram of a project; their hits name the diagram they're on.
+//
+// Backed by an in-memory SQLite FTS5 table. The index is brought up to date lazily on every
+// query: only projects whose edit stamp moved are copied out, and of those only the ones whose
+// searchable text changed, by fingerprint, are reindexed.
+
+use crate::diagrams::{Diagram, MAIN_DIAGRAM_ID};
+use crate::state::{read_entry, ProjectStore};
+use crate::{ApiError, DiagramElement, OperationNames, Project, ProjectTranscript};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{Executor, Row};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use tauri::State;
+
+const SCHEMA: &str = "
+CREATE VIRTUAL TABLE IF NOT EXISTS search_documents USING fts5(
+    entity_type UNINDEXED,
+    entity_id UNINDEXED,
+    project_id UNINDEXED,
+    project_name UNINDEXED,
+    diagram_id UNINDEXED,
+    title,
+    body,
+    tokenize = 'porter unicode61'
+);
+CREATE TABLE IF NOT EXISTS indexed_projects (
+    project_id TEXT PRIMARY KEY NOT NULL,
+    fingerprint TEXT NOT NULL
+);
+";
+
+const DEFAULT_LIMIT: u32 = 20;
+const MAX_LIMIT: u32 = 100;
+const TITLE_MAX_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SearchEntityType {
+    Project,
+    Component,
+    DiagramElement,
+    Transcript,
+}
+
+impl SearchEntityType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SearchEntityType::Project => "Project",
+            SearchEntityType::Component => "Component",
+            SearchEntityType::DiagramElement => "DiagramElement",
+            SearchEntityType::Transcript => "Transcript",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "Project" => Some(SearchEntityType::Project),
+            "Component" => Some(SearchEntityType::Component),
+            "DiagramElement" => Some(SearchEntityType::DiagramElement),
+            "Transcript" => Some(SearchEntityType::Transcript),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFilters {
+    pub entity_types: Option<Vec<SearchEntityType>>,
+    pub project_id: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub entity_type: SearchEntityType,
+    pub entity_id: String,
+    pub project_id: String,
+    pub project_name: String,
+    /// Diagram holding the element, for diagram element hits
+    pub diagram_id: Option<String>,
+    pub title: String,
+    /// Best matching fragment with hits wrapped in `<mark>` tags
+    pub snippet: String,
+    /// Higher is more relevant
+    pub score: f64,
+}
+
+/// Searchable state of one project, copied out of the stores before indexing
+#[derive(Debug, Clone)]
+pub struct ProjectSnapshot {
+    pub project: Project,
+    pub diagram_elements: Vec<DiagramElement>,
+    /// Diagrams besides the main one
+    pub views: Vec<Diagram>,
+    pub transcripts: Vec<ProjectTranscript>,
+    pub edit_stamp: u64,
+}
+
+#[derive(Debug, Clone, Hash)]
+struct SearchDocument {
+    entity_type: SearchEntityType,
+    entity_id: String,
+    diagram_id: Option<String>,
+    title: String,
+    body: String,
+}
+
+fn search_error(context: &str, err: sqlx::Error) -> ApiError {
+    ApiError::SearchError {
+        details: format!("{}: {}", context, err),
+        source: Some(Box::new(err)),
+    }
+}
+
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((cut, _)) => format!("{}…", text[..cut].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+// Key/value maps are flattened in key order so fingerprints stay stable
+fn flatten_properties(properties: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<_> = properties.iter().collect();
+    pairs.sort();
+    pairs
+        .into_iter()
+        .map(|(key, value)| format!("{} {}", key, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn project_documents(snapshot: &ProjectSnapshot) -> Vec<SearchDocument> {
+    let project = &snapshot.project;
+    let mut documents = vec![SearchDocument {
+        entity_type: SearchEntityType::Project,
+        entity_id: project.id.clone(),
+        diagram_id: None,
+        title: project.name.clone(),
+        body: project.description.clone(),
+    }];
+
+    documents.extend(project.components.iter().map(|component| SearchDocument {
+        entity_type: SearchEntityType::Component,
+        entity_id: component.id.clone(),
+        diagram_id: None,
+        title: component.name.clone(),
+        body: format!(
+            "{}\n{:?}\n{}",
+            component.description,
+            component.component_type,
+            flatten_properties(&component.metadata)
+        ),
+    }));
+
+    let diagrams = std::iter::once((MAIN_DIAGRAM_ID, &snapshot.diagram_elements))
+        .chain(snapshot.views.iter().map(|view| (view.id.as_str(), &view.elements)));
+    for (diagram_id, elements) in diagrams {
+        documents.extend(elements.iter().map(|element| SearchDocument {
+            entity_type: SearchEntityType::DiagramElement,
+            entity_id: element.id.clone(),
+            diagram_id: Some(diagram_id.to_string()),
+            title: element.label().to_string(),
+            body: format!("{}\n{}", element.element_type, flatten_properties(&element.properties)),
+        }));
+    }
+
+    documents.extend(snapshot.transcripts.iter().map(|transcript| SearchDocument {
+        entity_type: SearchEntityType::Transcript,
+        entity_id: transcript.id.clone(),
+        diagram_id: None,
+        title: truncate_chars(&transcript.text, TITLE_MAX_CHARS),
+        body: transcript.text.clone(),
+    }));
+
+    documents
+}
+
+fn fingerprint(project_name: &str, documents: &[SearchDocument]) -> String {
+    let mut hasher = DefaultHasher::new();
+    project_name.hash(&mut hasher);
+    documents.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Turns free text into an FTS5 query: every term is quoted so user input can never
+/// be read as query syntax, and the last term matches as a prefix for search-as-you-type.
+fn to_match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"", term.to_lowercase()))
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", terms.join(" ")))
+}
+
+pub struct SearchIndex {
+    pool: SqlitePool,
+    // Edit stamp of each project as last indexed. Also serializes refreshes so concurrent
+    // queries don't reindex the same project twice
+    stamps: tokio::sync::Mutex<HashMap<String, u64>>,
+}
+
+impl SearchIndex {
+    /// Creates an index backed by a private in-memory database; the connection is opened on first use
+    pub fn in_memory() -> Self {
+        let options = SqliteConnectOptions::new().in_memory(true);
+        // A single connection that is never recycled, otherwise the in-memory database would be lost
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(0)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .after_connect(|conn, _meta| {
+                Box::pin(async move {
+                    conn.execute(SCHEMA).await?;
+                    Ok(())
+                })
+            })
+            .connect_lazy_with(options);
+
+        Self {
+            pool,
+            stamps: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reindexes projects edited since they were last indexed and drops projects that no longer exist
+    pub async fn refresh(&self, projects: &ProjectStore) -> Result<(), ApiError> {
+        let mut stamps = self.stamps.lock().await;
+        let mut live = HashSet::new();
+        let mut edited = Vec::new();
+        for entry in projects.entries()? {
+            let state = read_entry(&entry)?;
+            live.insert(state.project.id.clone());
+            if stamps.get(&state.project.id) == Some(&state.edit_stamp) {
+                continue;
+            }
+            edited.push(ProjectSnapshot {
+                project: state.project.clone(),
+                diagram_elements: state.diagram.clone(),
+                views: state.views.clone(),
+                transcripts: state.transcripts.clone(),
+                edit_stamp: state.edit_stamp,
+            });
+        }
+        self.sync(&mut stamps, &edited, &live).await
+    }
+
+    // Reindexes the snapshots whose searchable text changed and drops projects not in `live`
+    async fn sync(
+        &self,
+        stamps: &mut HashMap<String, u64>,
+        snapshots: &[ProjectSnapshot],
+        live: &HashSet<String>,
+    ) -> Result<(), ApiError> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| search_error("Failed to open search index", e))?;
+
+        let indexed: HashMap<String, String> = sqlx::query("SELECT project_id, fingerprint FROM indexed_projects")
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| search_error("Failed to read index state", e))?
+            .into_iter()
+            .map(|row| (row.get::<String, _>(0), row.get::<String, _>(1)))
+            .collect();
+
+        let mut reindexed = 0;
+        for snapshot in snapshots {
+            let project = &snapshot.project;
+
+            let documents = project_documents(snapshot);
+            let current = fingerprint(&project.name, &documents);
+            if indexed.get(&project.id) == Some(&current) {
+                continue;
+            }
+
+            sqlx::query("DELETE FROM search_documents WHERE project_id = ?")
+                .bind(&project.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| search_error("Failed to clear stale documents", e))?;
+            for document in &documents {
+                sqlx::query(
+                    "INSERT INTO search_documents \
+                     (entity_type, entity_id, project_id, project_name, diagram_id, title, body) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(document.entity_type.as_str())
+                .bind(&document.entity_id)
+                .bind(&project.id)
+                .bind(&project.name)
+                .bind(&document.diagram_id)
+                .bind(&document.title)
+                .bind(&document.body)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| search_error("Failed to index document", e))?;
+            }
+            sqlx::query("INSERT OR REPLACE INTO indexed_projects (project_id, fingerprint) VALUES (?, ?)")
+                .bind(&project.id)
+                .bind(&current)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| search_error("Failed to record index state", e))?;
+            reindexed += 1;
+        }
+
+        for project_id in indexed.keys().filter(|id| !live.contains(*id)) {
+            sqlx::query("DELETE FROM search_documents WHERE project_id = ?")
+                .bind(project_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| search_error("Failed to drop deleted project", e))?;
+            sqlx::query("DELETE FROM indexed_projects WHERE project_id = ?")
+                .bind(project_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| search_error("Failed to drop deleted project", e))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| search_error("Failed to commit search index", e))?;
+        for snapshot in snapshots {
+            stamps.insert(snapshot.project.id.clone(), snapshot.edit_stamp);
+        }
+        stamps.retain(|id, _| live.contains(id));
+        if reindexed > 0 {
+            log::debug!("Search index refreshed: {} projects reindexed", reindexed);
+        }
+        Ok(())
+    }
+
+    pub async fn search(&self, query: &str, filters: &SearchFilters) -> Result<Vec<SearchHit>, ApiError> {
+        let Some(expression) = to_match_expression(query) else {
+            return Ok(Vec::new());
+        };
+
+        // Title matches count ten times as much as body matches
+        let mut sql = String::from(
+            "SELECT entity_type, entity_id, project_id, project_name, title, \
+                    snippet(search_documents, -1, '<mark>', '</mark>', '…', 12), \
+                    bm25(search_documents, 0.0, 0.0, 0.0, 0.0, 0.0, 10.0, 1.0) AS rank, diagram_id \
+             FROM search_documents WHERE search_documents MATCH ?",
+        );
+        if filters.project_id.is_some() {
+            sql.push_str(" AND project_id = ?");
+        }
+        let entity_types = filters.entity_types.clone().unwrap_or_default();
+        if !entity_types.is_empty() {
+            let placeholders = vec!["?"; entity_types.len()].join(", ");
+            sql.push_str(&format!(" AND entity_type IN ({})", placeholders));
+        }
+        sql.push_str(" ORDER BY rank LIMIT ?");
+
+        let mut statement = sqlx::query(&sql).bind(expression);
+        if let Some(project_id) = &filters.project_id {
+            statement = statement.bind(project_id);
+        }
+        for entity_type in &entity_types {
+            statement = statement.bind(entity_type.as_str());
+        }
+        let limit = filters.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        statement = statement.bind(limit as i64);
+
+        let rows = statement
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| search_error("Search query failed", e))?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let entity_type = SearchEntityType::parse(&row.get::<String, _>(0))?;
+                Some(SearchHit {
+                    entity_type,
+                    entity_id: row.get(1),
+                    project_id: row.get(2),
+                    project_name: row.get(3),
+                    diagram_id: row.get(7),
+                    title: row.get(4),
+                    snippet: row.get(5),
+                    score: -row.get::<f64, _>(6),
+                })
+            })
+            .collect())
+    }
+}
+
+#[tauri::command]
+pub async fn search(
+    query: String,
+    filters: Option<SearchFilters>,
+    projects: State<'_, ProjectStore>,
+    index: State<'_, SearchIndex>,
+) -> Result<Vec<SearchHit>, ApiError> {
+    index.refresh(&projects).await?;
+    let filters = filters.unwrap_or_default();
+    let hits = index.search(&query, &filters).await.map_err(|e| {
+        log::error!("{} failed: {}", OperationNames::SEARCH, e);
+        e
+    })?;
+
+    log::debug!("Search for '{}' returned {} hits", query, hits.len());
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ProjectState;
+    use crate::test_support::{self, strings};
+    use crate::Component;
+    use chrono::Utc;
+
+    fn snapshot(id: &str, name: &str, component: (&str, &str, &str)) -> ProjectSnapshot {
+        ProjectSnapshot {
+            project: Project {
+                id: id.into(),
+                name: name.into(),
+                description: format!("{} description", name),
+                components: vec![Component {
+                    name: component.1.into(),
+                    description: component.2.into(),
+                    metadata: strings(&[("broker", "kafka")]),
+                    ..test_support::component(component.0)
+                }],
+                ..test_support::project()
+            },
+            diagram_elements: vec![DiagramElement {
+                element_type: "queue".into(),
+                properties: strings(&[("label", "Order events topic")]),
+                ..test_support::element(&format!("{}-el", id))
+            }],
+            views: vec![],
+            transcripts: vec![],
+            edit_stamp: 0,
+        }
+    }
+
+    // Indexes exactly these projects
+    async fn sync(index: &SearchIndex, snapshots: &[ProjectSnapshot]) {
+        let live = snapshots.iter().map(|s| s.project.id.clone()).collect();
+        let mut stamps = index.stamps.lock().await;
+        index.sync(&mut stamps, snapshots, &live).await.unwrap();
+    }
+
+    fn corpus() -> Vec<ProjectSnapshot> {
+        let mut outbox = snapshot("p1", "Order Pipeline", ("c1", "Outbox Relay", "Relays the transactional outbox to Kafka"));
+        outbox.transcripts.push(ProjectTranscript {
+            id: "t1".into(),
+            text: "We agreed the relay polls the outbox table every second".into(),
+            segments: vec![],
+            audio_path: None,
+            created_at: Utc::now(),
+        });
+        vec![
+            outbox,
+            snapshot("p2", "Chat App", ("c2", "Presence Service", "Tracks who is online")),
+        ]
+    }
+
+    #[tokio::test]
+    async fn ranks_title_matches_first_with_snippets() {
+        let index = SearchIndex::in_memory();
+        sync(&index, &corpus()).await;
+
+        let hits = index.search("outbox", &SearchFilters::default()).await.unwrap();
+        assert!(hits.len() >= 2);
+        assert_eq!(hits[0].entity_id, "c1");
+        assert_eq!(hits[0].project_name, "Order Pipeline");
+        assert!(hits[0].snippet.contains("<mark>"));
+        assert!(hits.iter().all(|hit| hit.project_id == "p1"));
+    }
+
+    #[tokio::test]
+    async fn filters_by_entity_type_and_project() {
+        let index = SearchIndex::in_memory();
+        sync(&index, &corpus()).await;
+
+        let filters = SearchFilters {
+            entity_types: Some(vec![SearchEntityType::Transcript]),
+            ..Default::default()
+        };
+        let hits = index.search("outbox", &filters).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entity_type, SearchEntityType::Transcript);
+
+        // Component metadata is searchable, scoped here to one project
+        let filters = SearchFilters {
+            project_id: Some("p2".into()),
+            ..Default::default()
+        };
+        let hits = index.search("kafka", &filters).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entity_id, "c2");
+    }
+
+    #[tokio::test]
+    async fn reindexes_changed_and_deleted_projects() {
+        let index = SearchIndex::in_memory();
+        let mut projects = corpus();
+        sync(&index, &projects).await;
+
+        projects[1].project.components[0].name = "Typing Indicator".into();
+        sync(&index, &projects).await;
+        assert!(index.search("presence", &SearchFilters::default()).await.unwrap().iter().all(|h| h.entity_id != "c2"));
+        assert_eq!(index.search("typing", &SearchFilters::default()).await.unwrap().len(), 1);
+
+        projects.remove(0);
+        sync(&index, &projects).await;
+        assert!(index.search("outbox", &SearchFilters::default()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refreshes_only_edited_projects() {
+        let index = SearchIndex::in_memory();
+        let projects = ProjectStore::default();
+        for snapshot in corpus() {
+            projects.insert(ProjectState::new(snapshot.project)).unwrap();
+        }
+        index.refresh(&projects).await.unwrap();
+        let stamp = |id: &str| projects.read(id, |state| state.edit_stamp).unwrap().unwrap();
+        assert_eq!(index.stamps.lock().await.get("p2"), Some(&stamp("p2")));
+
+        projects
+            .write("p2", |state| {
+                state.project.components[0].name = "Typing Indicator".into();
+                Ok(())
+            })
+            .unwrap();
+        index.refresh(&projects).await.unwrap();
+        assert_eq!(index.stamps.lock().await.get("p2"), Some(&stamp("p2")));
+        assert_eq!(index.search("typing", &SearchFilters::default()).await.unwrap().len(), 1);
+
+        projects.remove("p1").unwrap();
+        index.refresh(&projects).await.unwrap();
+        assert!(index.search("outbox", &SearchFilters::default()).await.unwrap().is_empty());
+        assert!(!index.stamps.lock().await.contains_key("p1"));
+    }
+
+    #[tokio::test]
+    async fn treats_query_syntax_as_plain_text() {
+        let index = SearchIndex::in_memory();
+        sync(&index, &corpus()).await;
+
+        assert!(index.search("\"", &SearchFilters::default()).await.unwrap().is_empty());
+        let hits = index.search("order AND NOT) events*", &SearchFilters::default()).await.unwrap();
+        assert!(hits.is_empty());
+        let hits = index.search("ord", &SearchFilters::default()).await.unwrap();
+        assert!(hits.iter().any(|hit| hit.entity_type == SearchEntityType::DiagramElement));
+    }
+
+    #[tokio::test]
+    async fn finds_elements_on_every_diagram() {
+        let index = SearchIndex::in_memory();
+        let mut projects = corpus();
+        projects[1].views.push(Diagram {
+            id: "ops".into(),
+            name: "Operations".into(),
+            kind: Default::default(),
+            elements: vec![DiagramElement {
+                properties: HashMap::from([("label".to_string(), "Presence heartbeat monitor".to_string())]),
+                ..test_support::element("monitor")
+            }],
+            connections: vec![],
+            diagram_revision: 0,
+        });
+        sync(&index, &projects).await;
+
+        let filters = SearchFilters {
+            entity_types: Some(vec![SearchEntityType::DiagramElement]),
+            ..Default::default()
+        };
+        let hits = index.search("heartbeat", &filters).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].entity_id.as_str(), hits[0].diagram_id.as_deref()), ("monitor", Some("ops")));
+        let hits = index.search("topic", &filters).await.unwrap();
+        assert!(hits.iter().all(|hit| hit.diagram_id.as_deref() == Some(MAIN_DIAGRAM_ID)));
+        let hits = index.search("presence", &SearchFilters::default()).await.unwrap();
+        assert!(hits.iter().filter(|hit| hit.entity_type != SearchEntityType::DiagramElement).all(|hit| hit.diagram_id.is_none()));
+    }
+}
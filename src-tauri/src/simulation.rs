@@ -0,0 +1,701 @@
+// Discrete-event traffic simulation
+//
+// Requests arrive at entry nodes as a Poisson process. Every node is a queue with a number of
+// servers (`concurrency`), exponentially distributed service times around `service_time`, and
+// an optional `queue_limit` past which arrivals are dropped. When a node finishes a job it
+// calls downstream: connections without a `probability` property are always called, while
+// those with one form a routing choice where at most one is taken. A connection's p50 latency
+// is added as network delay. A request completes when every call it caused has finished.
+//
+// Runs are reproducible: the same project, config and seed give the same report.
+
+use crate::latency::ConnectionLatency;
+use crate::lint::LintContext;
+use crate::state::{ProjectState, ProjectStore};
+use crate::system_graph::SystemNode;
+use crate::units::{Measure, TimeSpan};
+use crate::ApiError;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use tauri::State;
+
+const DEFAULT_SAMPLES: f64 = 100.0;
+const DEFAULT_MAX_EVENTS: u64 = 5_000_000;
+// Calls a single request may make before it's assumed to be looping
+const MAX_HOPS: u32 = 64;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeSettings {
+    pub service_time: Option<TimeSpan>,
+    pub concurrency: Option<u32>,
+    pub queue_limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimulationConfig {
+    #[serde(default)]
+    pub seed: u64,
+    /// Simulated time; arrivals stop afterwards and unfinished requests count as in flight
+    pub duration: TimeSpan,
+    /// Requests per second arriving at each entry node
+    pub arrivals: HashMap<String, f64>,
+    /// Per-node overrides for the `service_time`, `concurrency` and `queue_limit` attributes
+    #[serde(default)]
+    pub nodes: HashMap<String, NodeSettings>,
+    /// Spacing of queue length samples; defaults to a hundredth of the duration
+    #[serde(default)]
+    pub sample_interval: Option<TimeSpan>,
+    #[serde(default)]
+    pub max_events: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Percentiles {
+    pub p50: Measure,
+    pub p90: Measure,
+    pub p99: Measure,
+    pub max: Measure,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct QueueSample {
+    pub time: f64,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStats {
+    pub node: SystemNode,
+    /// Busy server time over available server time
+    pub utilization: f64,
+    pub arrivals: u64,
+    pub served: u64,
+    pub dropped: u64,
+    pub max_queue: usize,
+    pub queue: Vec<QueueSample>,
+    /// Time from arriving at the node to finishing there, queueing included
+    pub latency: Option<Percentiles>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    pub project_id: String,
+    pub seed: u64,
+    pub duration: Measure,
+    /// Simulated time actually covered; short of `duration` when the run hit `max_events`
+    pub simulated: Measure,
+    pub generated: u64,
+    pub completed: u64,
+    pub dropped: u64,
+    pub in_flight: u64,
+    /// End-to-end latency of completed requests
+    pub latency: Option<Percentiles>,
+    pub nodes: Vec<NodeStats>,
+    pub warnings: Vec<String>,
+}
+
+/// SplitMix64: tiny, fast and stable across releases, which matters more here than quality
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn exponential(&mut self, mean: f64) -> f64 {
+        if mean <= 0.0 {
+            return 0.0;
+        }
+        -mean * (1.0 - self.next_f64()).ln()
+    }
+}
+
+struct Route {
+    to: usize,
+    probability: Option<f64>,
+    delay: f64,
+}
+
+struct NodeModel {
+    node: SystemNode,
+    service_time: f64,
+    concurrency: u32,
+    queue_limit: Option<u32>,
+    routes: Vec<Route>,
+}
+
+/// Everything the run needs, detached from the project so the lock isn't held while it runs
+pub struct Model {
+    project_id: String,
+    nodes: Vec<NodeModel>,
+    entries: Vec<(usize, f64)>,
+    warnings: Vec<String>,
+}
+
+fn invalid(details: String) -> ApiError {
+    ApiError::InvalidProjectData { details, source: None }
+}
+
+pub fn build_model(state: &ProjectState, config: &SimulationConfig) -> Result<Model, ApiError> {
+    if config.duration.0 <= 0.0 {
+        return Err(invalid("Simulation duration must be positive".to_string()));
+    }
+    let ctx = LintContext::new(state);
+    let index: HashMap<&str, usize> = ctx.graph.nodes().iter().enumerate().map(|(i, n)| (n.id.as_str(), i)).collect();
+    // Linked elements stand for their component's node
+    let resolve = |id: &str| ctx.graph.node(id).and_then(|n| index.get(n.id.as_str())).copied();
+    let mut warnings = Vec::new();
+
+    let mut nodes = Vec::new();
+    for node in ctx.graph.nodes() {
+        let overrides = config.nodes.get(&node.id).cloned().unwrap_or_default();
+        let attribute = |key: &str| ctx.attributes(&node.id).and_then(|a| a.get(key)).map(|v| v.trim());
+        let service_time = match overrides.service_time {
+            Some(t) => t,
+            None => attribute("service_time")
+                .map(TimeSpan::parse)
+                .transpose()
+                .map_err(|e| invalid(format!("Invalid service_time on {}: {}", node.label, e)))?
+                .unwrap_or_default(),
+        };
+        let concurrency = overrides
+            .concurrency
+            .or_else(|| attribute("concurrency").and_then(|c| c.parse().ok()))
+            .unwrap_or(1);
+        if concurrency == 0 {
+            return Err(invalid(format!("Concurrency of {} must be at least 1", node.label)));
+        }
+        let queue_limit = overrides
+            .queue_limit
+            .or_else(|| attribute("queue_limit").and_then(|q| q.parse().ok()));
+        nodes.push(NodeModel {
+            node: node.clone(),
+            service_time: service_time.0,
+            concurrency,
+            queue_limit,
+            routes: Vec::new(),
+        });
+    }
+
+    for connection in &state.connections {
+        let (Some(from), Some(to)) = (resolve(&connection.source_id), resolve(&connection.target_id)) else {
+            continue;
+        };
+        if from == to {
+            continue;
+        }
+        let probability = match connection.properties.get("probability") {
+            Some(p) => match p.trim().parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Some(p),
+                _ => return Err(invalid(format!("Connection {} has an invalid probability '{}'", connection.id, p))),
+            },
+            None => None,
+        };
+        let delay = connection.latency.map_or(0.0, |l: ConnectionLatency| l.p50.0);
+        nodes[from].routes.push(Route { to, probability, delay });
+    }
+    for node in &nodes {
+        let total: f64 = node.routes.iter().filter_map(|r| r.probability).sum();
+        if total > 1.0 + 1e-9 {
+            warnings.push(format!(
+                "Routing probabilities out of {} add up to {:.2}; later routes are taken less often than set",
+                node.node.label, total
+            ));
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut entry_ids: Vec<&String> = config.arrivals.keys().collect();
+    // Map order isn't stable; the seed should be all that decides the outcome
+    entry_ids.sort();
+    for id in entry_ids {
+        let rate = config.arrivals[id];
+        let node = resolve(id).ok_or_else(|| invalid(format!("Arrival entry {} is not an element of the project", id)))?;
+        if !rate.is_finite() || rate < 0.0 {
+            return Err(invalid(format!("Arrival rate at {} must be a non-negative number", id)));
+        }
+        if rate > 0.0 {
+            entries.push((node, rate));
+        }
+    }
+
+    Ok(Model {
+        project_id: state.project.id.clone(),
+        nodes,
+        entries,
+        warnings,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EventKind {
+    // Next external arrival at an entry (index into `entries`)
+    Arrival(usize),
+    // A call reaches a node
+    Enqueue { node: usize, request: usize, hops: u32 },
+    Finish { node: usize, request: usize, hops: u32, arrived: f64 },
+    Sample,
+}
+
+#[derive(Debug, PartialEq)]
+struct Event {
+    time: f64,
+    seq: u64,
+    kind: EventKind,
+}
+
+impl Eq for Event {}
+
+impl Ord for Event {
+    // Reversed so the max-heap pops the earliest event; ties go to whichever was scheduled first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.time.total_cmp(&self.time).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+struct Job {
+    request: usize,
+    hops: u32,
+    arrived: f64,
+}
+
+#[derive(Default)]
+struct NodeRun {
+    busy: u32,
+    queue: VecDeque<Job>,
+    busy_time: f64,
+    last_change: f64,
+    arrivals: u64,
+    served: u64,
+    dropped: u64,
+    max_queue: usize,
+    samples: Vec<QueueSample>,
+    latencies: Vec<f64>,
+}
+
+impl NodeRun {
+    fn account(&mut self, now: f64) {
+        self.busy_time += self.busy as f64 * (now - self.last_change);
+        self.last_change = now;
+    }
+}
+
+struct RequestRun {
+    started: f64,
+    outstanding: u32,
+    dropped: bool,
+}
+
+struct Engine<'a> {
+    model: &'a Model,
+    rng: Rng,
+    events: BinaryHeap<Event>,
+    seq: u64,
+    nodes: Vec<NodeRun>,
+    requests: Vec<RequestRun>,
+    latencies: Vec<f64>,
+    warnings: Vec<String>,
+}
+
+impl Engine<'_> {
+    fn schedule(&mut self, time: f64, kind: EventKind) {
+        self.seq += 1;
+        self.events.push(Event { time, seq: self.seq, kind });
+    }
+
+    fn start(&mut self, now: f64, node: usize, job: Job) {
+        let service = self.rng.exponential(self.model.nodes[node].service_time);
+        self.nodes[node].account(now);
+        self.nodes[node].busy += 1;
+        self.schedule(now + service, EventKind::Finish {
+            node,
+            request: job.request,
+            hops: job.hops,
+            arrived: job.arrived,
+        });
+    }
+
+    fn enqueue(&mut self, now: f64, node: usize, request: usize, hops: u32) {
+        let settings = &self.model.nodes[node];
+        let run = &mut self.nodes[node];
+        run.arrivals += 1;
+        let job = Job { request, hops, arrived: now };
+        if run.busy < settings.concurrency {
+            self.start(now, node, job);
+        } else if settings.queue_limit.is_some_and(|limit| run.queue.len() >= limit as usize) {
+            run.dropped += 1;
+            self.requests[request].dropped = true;
+            self.settle(now, request);
+        } else {
+            run.queue.push_back(job);
+            run.max_queue = run.max_queue.max(run.queue.len());
+        }
+    }
+
+    fn finish(&mut self, now: f64, node: usize, request: usize, hops: u32, arrived: f64) {
+        let run = &mut self.nodes[node];
+        run.account(now);
+        run.busy -= 1;
+        run.served += 1;
+        run.latencies.push(now - arrived);
+        if let Some(next) = run.queue.pop_front() {
+            self.start(now, node, next);
+        }
+
+        // Calls made on the request's behalf; it isn't done until they are
+        let mut choice = self.rng.next_f64();
+        let mut calls = Vec::new();
+        for route in &self.model.nodes[node].routes {
+            match route.probability {
+                None => calls.push(route),
+                Some(p) if choice >= 0.0 && choice < p => {
+                    calls.push(route);
+                    choice = -1.0;
+                }
+                Some(p) => choice -= p,
+            }
+        }
+        if !calls.is_empty() && hops >= MAX_HOPS {
+            let warning = format!(
+                "Requests were cut off after {} calls; check for loops through {}",
+                MAX_HOPS, self.model.nodes[node].node.label
+            );
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+            calls.clear();
+        }
+        for route in &calls {
+            self.requests[request].outstanding += 1;
+            self.schedule(now + route.delay, EventKind::Enqueue {
+                node: route.to,
+                request,
+                hops: hops + 1,
+            });
+        }
+        self.settle(now, request);
+    }
+
+    // One call of the request is over, by finishing or being dropped
+    fn settle(&mut self, now: f64, request: usize) {
+        let run = &mut self.requests[request];
+        run.outstanding -= 1;
+        if run.outstanding == 0 && !run.dropped {
+            self.latencies.push(now - run.started);
+        }
+    }
+
+    fn run(mut self, config: &SimulationConfig) -> SimulationReport {
+        let duration = config.duration.0;
+        let interval = config
+            .sample_interval
+            .map_or(duration / DEFAULT_SAMPLES, |i| i.0)
+            .max(duration / 10_000.0);
+        let max_events = config.max_events.unwrap_or(DEFAULT_MAX_EVENTS);
+
+        for (i, &(_, rate)) in self.model.entries.iter().enumerate() {
+            let first = self.rng.exponential(1.0 / rate);
+            self.schedule(first, EventKind::Arrival(i));
+        }
+        self.schedule(0.0, EventKind::Sample);
+
+        let mut processed = 0u64;
+        let mut end = duration;
+        let mut last = 0.0;
+        while let Some(event) = self.events.pop() {
+            if event.time > duration {
+                break;
+            }
+            processed += 1;
+            if processed > max_events {
+                self.warnings.push(format!(
+                    "Stopped at {} after {} events; shorten the run or lower arrival rates",
+                    TimeSpan(event.time),
+                    max_events
+                ));
+                end = last;
+                break;
+            }
+            let now = event.time;
+            last = now;
+            match event.kind {
+                EventKind::Arrival(entry) => {
+                    let (node, rate) = self.model.entries[entry];
+                    let request = self.requests.len();
+                    self.requests.push(RequestRun {
+                        started: now,
+                        outstanding: 1,
+                        dropped: false,
+                    });
+                    self.enqueue(now, node, request, 0);
+                    let next = now + self.rng.exponential(1.0 / rate);
+                    self.schedule(next, EventKind::Arrival(entry));
+                }
+                EventKind::Enqueue { node, request, hops } => self.enqueue(now, node, request, hops),
+                EventKind::Finish {
+                    node,
+                    request,
+                    hops,
+                    arrived,
+                } => self.finish(now, node, request, hops, arrived),
+                EventKind::Sample => {
+                    for run in &mut self.nodes {
+                        run.samples.push(QueueSample {
+                            time: now,
+                            length: run.queue.len(),
+                        });
+                    }
+                    self.schedule(now + interval, EventKind::Sample);
+                }
+            }
+        }
+
+        let nodes = self
+            .model
+            .nodes
+            .iter()
+            .zip(self.nodes.iter_mut())
+            .filter(|(_, run)| run.arrivals > 0)
+            .map(|(model, run)| {
+                run.account(end);
+                NodeStats {
+                    node: model.node.clone(),
+                    utilization: if end > 0.0 {
+                        run.busy_time / (model.concurrency as f64 * end)
+                    } else {
+                        0.0
+                    },
+                    arrivals: run.arrivals,
+                    served: run.served,
+                    dropped: run.dropped,
+                    max_queue: run.max_queue,
+                    queue: std::mem::take(&mut run.samples),
+                    latency: percentiles(&mut run.latencies),
+                }
+            })
+            .collect();
+
+        let dropped = self.requests.iter().filter(|r| r.dropped).count() as u64;
+        let completed = self.latencies.len() as u64;
+        let generated = self.requests.len() as u64;
+        let mut warnings = self.model.warnings.clone();
+        warnings.append(&mut self.warnings);
+        SimulationReport {
+            project_id: self.model.project_id.clone(),
+            seed: config.seed,
+            duration: Measure::duration(duration),
+            simulated: Measure::duration(end),
+            generated,
+            completed,
+            dropped,
+            in_flight: generated - completed - dropped,
+            latency: percentiles(&mut self.latencies),
+            nodes,
+            warnings,
+        }
+    }
+}
+
+fn percentiles(values: &mut [f64]) -> Option<Percentiles> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let rank = |q: f64| values[((q * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+    Some(Percentiles {
+        p50: Measure::duration(rank(0.5)),
+        p90: Measure::duration(rank(0.9)),
+        p99: Measure::duration(rank(0.99)),
+        max: Measure::duration(values[values.len() - 1]),
+    })
+}
+
+pub fn simulate(model: &Model, config: &SimulationConfig) -> SimulationReport {
+    let engine = Engine {
+        model,
+        rng: Rng::new(config.seed),
+        events: BinaryHeap::new(),
+        seq: 0,
+        nodes: model.nodes.iter().map(|_| NodeRun::default()).collect(),
+        requests: Vec::new(),
+        latencies: Vec::new(),
+        warnings: Vec::new(),
+    };
+    engine.run(config)
+}
+
+#[tauri::command]
+pub async fn simulate_traffic(
+    project_id: String,
+    config: SimulationConfig,
+    projects: State<'_, ProjectStore>,
+) -> Result<SimulationReport, ApiError> {
+    let model = projects
+        .read(&project_id, |state| build_model(state, &config))?
+        .ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.clone(),
+            source: None,
+        })??;
+    let report = tokio::task::spawn_blocking(move || simulate(&model, &config))
+        .await
+        .map_err(|e| ApiError::Internal {
+            details: format!("Traffic simulation failed: {}", e),
+            source: Some(Box::new(e)),
+        })?;
+    log::info!(
+        "Simulated {} of traffic in {} (seed {}): {} requests, {} completed, {} dropped",
+        report.duration.display,
+        project_id,
+        report.seed,
+        report.generated,
+        report.completed,
+        report.dropped
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, connection, diagram_state, strings};
+    use crate::{Component, Connection, DiagramElement};
+
+    fn state() -> ProjectState {
+        let element = |id: &str, attributes: &[(&str, &str)]| DiagramElement {
+            properties: strings(attributes),
+            ..test_support::element(id)
+        };
+        let choice = |id: &str, from: &str, to: &str, probability: &str| Connection {
+            properties: strings(&[("probability", probability)]),
+            ..connection(id, from, to)
+        };
+        diagram_state(
+            vec![
+                element("lb", &[("concurrency", "1000")]),
+                element("api", &[("service_time", "10ms"), ("concurrency", "4"), ("queue_limit", "20")]),
+                element("cache", &[("service_time", "1ms"), ("concurrency", "8")]),
+                element("db", &[("service_time", "20ms"), ("concurrency", "2")]),
+            ],
+            vec![
+                connection("lb-api", "lb", "api"),
+                choice("api-cache", "api", "cache", "0.8"),
+                choice("api-db", "api", "db", "0.2"),
+            ],
+        )
+    }
+
+    fn config(rate: f64, seed: u64) -> SimulationConfig {
+        SimulationConfig {
+            seed,
+            duration: TimeSpan(60.0),
+            arrivals: HashMap::from([("lb".to_string(), rate)]),
+            nodes: HashMap::new(),
+            sample_interval: None,
+            max_events: None,
+        }
+    }
+
+    fn run(state: &ProjectState, config: &SimulationConfig) -> SimulationReport {
+        simulate(&build_model(state, config).unwrap(), config)
+    }
+
+    fn stats<'a>(report: &'a SimulationReport, id: &str) -> &'a NodeStats {
+        report.nodes.iter().find(|n| n.node.id == id).unwrap()
+    }
+
+    #[test]
+    fn same_seed_gives_same_run() {
+        let state = state();
+        let a = run(&state, &config(100.0, 7));
+        let b = run(&state, &config(100.0, 7));
+        let c = run(&state, &config(100.0, 8));
+        assert_eq!(a.generated, b.generated);
+        assert_eq!(a.latency.as_ref().unwrap().p99.value, b.latency.as_ref().unwrap().p99.value);
+        assert_ne!(a.generated, c.generated);
+        assert_eq!(stats(&a, "api").queue.len(), 100);
+    }
+
+    #[test]
+    fn light_load_routes_by_probability() {
+        let report = run(&state(), &config(100.0, 1));
+        assert!(report.dropped == 0 && report.warnings.is_empty());
+        // 100 req/s over 10 ms on 4 servers is about 25% busy
+        let api = stats(&report, "api");
+        assert!((api.utilization - 0.25).abs() < 0.05, "{}", api.utilization);
+        let cache_share = stats(&report, "cache").arrivals as f64 / api.served as f64;
+        assert!((cache_share - 0.8).abs() < 0.03, "{}", cache_share);
+        assert!(report.latency.unwrap().p50.value < 0.05);
+    }
+
+    #[test]
+    fn overload_fills_queues_and_drops() {
+        // 4 servers at 10 ms top out around 400 req/s
+        let report = run(&state(), &config(600.0, 1));
+        let api = stats(&report, "api");
+        assert!(api.utilization > 0.95);
+        assert_eq!(api.max_queue, 20);
+        assert!(api.dropped > 0);
+        assert_eq!(report.dropped, api.dropped);
+        assert_eq!(report.generated, report.completed + report.dropped + report.in_flight);
+
+        // Cut short, busy time is only counted up to the last event handled
+        let mut short = config(600.0, 1);
+        short.max_events = Some(2_000);
+        let report = run(&state(), &short);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.simulated.value > 0.0 && report.simulated.value < 60.0);
+        assert!(stats(&report, "api").utilization > 0.95);
+        assert!(report.nodes.iter().all(|n| n.utilization <= 1.0));
+
+        let mut bad = config(1.0, 1);
+        bad.arrivals.insert("nowhere".into(), 1.0);
+        assert!(build_model(&state(), &bad).is_err());
+    }
+
+    #[test]
+    fn calls_between_linked_elements_reach_their_components() {
+        let mut state = ProjectState::new(test_support::project());
+        state.project.components = ["api", "db"]
+            .map(|id| Component {
+                metadata: HashMap::from([("service_time".to_string(), "5ms".to_string())]),
+                ..test_support::component(id)
+            })
+            .into();
+        state.diagram = ["api", "db"]
+            .map(|id| DiagramElement {
+                component_id: Some(id.into()),
+                ..test_support::element(&format!("{}-box", id))
+            })
+            .into();
+        state.connections = vec![connection("api-db", "api-box", "db-box")];
+
+        let mut config = config(10.0, 1);
+        config.arrivals = HashMap::from([("api-box".to_string(), 10.0)]);
+        let report = run(&state, &config);
+        assert_eq!(stats(&report, "api").arrivals, report.generated);
+        assert_eq!(stats(&report, "db").arrivals, stats(&report, "api").served);
+        assert!(report.generated > 0);
+    }
+}
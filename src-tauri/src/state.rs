@@ -0,0 +1,244 @@
+// Per-project application state
+//
+// Each project owns its diagrams, connections and transcripts behind a dedicated lock.
+// The registry lock only guards the id -> entry map and is released as soon as the
+// entry's Arc has been cloned, so work on one project never blocks another.
+// None of these std locks may be held across an `.await`.
+
+use crate::diagrams::{Diagram, DiagramMeta};
+use crate::{ApiError, Connection, DiagramElement, Project, ProjectTranscript};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+static EDIT_STAMPS: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone)]
+pub struct ProjectState {
+    pub project: Project,
+    /// The main diagram; other views are swapped in here while they're edited
+    pub diagram: Vec<DiagramElement>,
+    pub connections: Vec<Connection>,
+    pub transcripts: Vec<ProjectTranscript>,
+    /// Bumped by every write to the diagram or its connections
+    pub diagram_revision: u64,
+    pub main_diagram: DiagramMeta,
+    /// Further named diagrams of the project
+    pub views: Vec<Diagram>,
+    /// Unique across all projects and replaced whenever a write lock is taken, so a reader
+    /// that remembers it can tell when the state may have changed
+    pub edit_stamp: u64,
+}
+
+impl ProjectState {
+    pub fn new(project: Project) -> Self {
+        Self {
+            project,
+            diagram: Vec::new(),
+            connections: Vec::new(),
+            transcripts: Vec::new(),
+            diagram_revision: 0,
+            main_diagram: DiagramMeta::default(),
+            views: Vec::new(),
+            edit_stamp: EDIT_STAMPS.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+pub type ProjectEntry = Arc<RwLock<ProjectState>>;
+
+fn lock_error(resource: &str) -> ApiError {
+    ApiError::StateLockError {
+        resource: resource.to_string(),
+        source: None,
+    }
+}
+
+pub fn read_entry(entry: &ProjectEntry) -> Result<RwLockReadGuard<'_, ProjectState>, ApiError> {
+    entry.read().map_err(|_| lock_error("ProjectState"))
+}
+
+pub fn write_entry(entry: &ProjectEntry) -> Result<RwLockWriteGuard<'_, ProjectState>, ApiError> {
+    let mut state = entry.write().map_err(|_| lock_error("ProjectState"))?;
+    state.edit_stamp = EDIT_STAMPS.fetch_add(1, Ordering::Relaxed);
+    Ok(state)
+}
+
+#[derive(Debug, Default)]
+pub struct ProjectStore {
+    entries: RwLock<HashMap<String, ProjectEntry>>,
+}
+
+impl ProjectStore {
+    pub fn get(&self, project_id: &str) -> Result<Option<ProjectEntry>, ApiError> {
+        let entries = self.entries.read().map_err(|_| lock_error("ProjectStore"))?;
+        Ok(entries.get(project_id).cloned())
+    }
+
+    /// Like `get`, but a missing project is an error
+    pub fn require(&self, project_id: &str) -> Result<ProjectEntry, ApiError> {
+        self.get(project_id)?.ok_or_else(|| ApiError::ProjectNotFound {
+            project_id: project_id.to_string(),
+            source: None,
+        })
+    }
+
+    /// Adds a project, replacing any existing state stored under the same id
+    pub fn insert(&self, state: ProjectState) -> Result<ProjectEntry, ApiError> {
+        let project_id = state.project.id.clone();
+        let entry = Arc::new(RwLock::new(state));
+        let mut entries = self.entries.write().map_err(|_| lock_error("ProjectStore"))?;
+        entries.insert(project_id, entry.clone());
+        Ok(entry)
+    }
+
+    /// The entry stored under `project_id`, adding `make()` first if there is none. The check
+    /// and the insert happen under one registry lock; the flag says whether it was added
+    pub fn get_or_insert_with(
+        &self,
+        project_id: &str,
+        make: impl FnOnce() -> ProjectState,
+    ) -> Result<(ProjectEntry, bool), ApiError> {
+        let mut entries = self.entries.write().map_err(|_| lock_error("ProjectStore"))?;
+        if let Some(entry) = entries.get(project_id) {
+            return Ok((entry.clone(), false));
+        }
+        let entry = Arc::new(RwLock::new(make()));
+        entries.insert(project_id.to_string(), entry.clone());
+        Ok((entry, true))
+    }
+
+    pub fn remove(&self, project_id: &str) -> Result<Option<ProjectEntry>, ApiError> {
+        let mut entries = self.entries.write().map_err(|_| lock_error("ProjectStore"))?;
+        Ok(entries.remove(project_id))
+    }
+
+    /// All current entries; the registry lock is released before the caller touches any project
+    pub fn entries(&self) -> Result<Vec<ProjectEntry>, ApiError> {
+        let entries = self.entries.read().map_err(|_| lock_error("ProjectStore"))?;
+        Ok(entries.values().cloned().collect())
+    }
+
+    pub fn read<T>(&self, project_id: &str, f: impl FnOnce(&ProjectState) -> T) -> Result<Option<T>, ApiError> {
+        match self.get(project_id)? {
+            Some(entry) => Ok(Some(f(&*read_entry(&entry)?))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn write<T>(
+        &self,
+        project_id: &str,
+        f: impl FnOnce(&mut ProjectState) -> Result<T, ApiError>,
+    ) -> Result<Option<T>, ApiError> {
+        match self.get(project_id)? {
+            Some(entry) => f(&mut *write_entry(&entry)?).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::Component;
+    use std::thread;
+
+    fn project(id: &str) -> Project {
+        Project {
+            id: id.into(),
+            name: id.into(),
+            ..test_support::project()
+        }
+    }
+
+    fn component(id: String) -> Component {
+        Component {
+            name: "worker".into(),
+            ..test_support::component(&id)
+        }
+    }
+
+    #[test]
+    fn locked_project_does_not_block_others() {
+        let store = ProjectStore::default();
+        store.insert(ProjectState::new(project("a"))).unwrap();
+        store.insert(ProjectState::new(project("b"))).unwrap();
+
+        let a = store.require("a").unwrap();
+        let _held = write_entry(&a).unwrap();
+
+        let b = store.require("b").unwrap();
+        assert!(b.try_write().is_ok());
+        assert!(store.get("c").unwrap().is_none());
+        assert!(store.insert(ProjectState::new(project("c"))).is_ok());
+        assert!(matches!(store.require("missing"), Err(ApiError::ProjectNotFound { .. })));
+
+        let (entry, added) = store.get_or_insert_with("a", || unreachable!()).unwrap();
+        assert!(!added && Arc::ptr_eq(&entry, &a));
+        let (_, added) = store.get_or_insert_with("d", || ProjectState::new(project("d"))).unwrap();
+        assert!(added && store.get("d").unwrap().is_some());
+    }
+
+    #[test]
+    fn writes_replace_the_edit_stamp() {
+        let store = ProjectStore::default();
+        let entry = store.insert(ProjectState::new(project("a"))).unwrap();
+        let before = read_entry(&entry).unwrap().edit_stamp;
+        assert_eq!(read_entry(&entry).unwrap().edit_stamp, before);
+        store.write("a", |_| Ok(())).unwrap();
+        assert_ne!(read_entry(&entry).unwrap().edit_stamp, before);
+        assert_ne!(ProjectState::new(project("b")).edit_stamp, before);
+    }
+
+    #[test]
+    fn concurrent_mutations_stress() {
+        const PROJECTS: usize = 8;
+        const WRITERS_PER_PROJECT: usize = 4;
+        const OPS: usize = 250;
+
+        let store = Arc::new(ProjectStore::default());
+        for p in 0..PROJECTS {
+            store.insert(ProjectState::new(project(&format!("p{}", p)))).unwrap();
+        }
+
+        let mut handles = Vec::new();
+        for p in 0..PROJECTS {
+            for w in 0..WRITERS_PER_PROJECT {
+                let store = store.clone();
+                handles.push(thread::spawn(move || {
+                    let id = format!("p{}", p);
+                    for op in 0..OPS {
+                        store
+                            .write(&id, |state| {
+                                state.project.components.push(component(format!("{}-{}", w, op)));
+                                Ok(())
+                            })
+                            .unwrap()
+                            .unwrap();
+                        // Readers and registry churn interleave with the writers
+                        store.read(&id, |state| state.project.components.len()).unwrap().unwrap();
+                        if op % 50 == 0 {
+                            let scratch = format!("scratch-{}-{}-{}", p, w, op);
+                            store.insert(ProjectState::new(project(&scratch))).unwrap();
+                            assert!(store.remove(&scratch).unwrap().is_some());
+                        }
+                    }
+                }));
+            }
+        }
+        for handle in handles {
+            handle.join().expect("writer thread panicked");
+        }
+
+        assert_eq!(store.entries().unwrap().len(), PROJECTS);
+        for p in 0..PROJECTS {
+            let count = store
+                .read(&format!("p{}", p), |state| state.project.components.len())
+                .unwrap()
+                .unwrap();
+            assert_eq!(count, WRITERS_PER_PROJECT * OPS);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+// Combined view of a project's architecture as a directed graph
+//
+// Nodes are components and diagram elements. An element linked to an existing component is
+// not a node of its own: it stands for the component, so its id finds the component's node and
+// connections drawn to it count as the component's. An edge `from -> to` means `from` relies
+// on `to`: a component dependency, or a diagram connection from its source to its target.
+// Edges whose endpoints don't exist are skipped here and reported by the linter instead.
+//
+// Other views only add their connections between components: unlinked elements of a view are
+// local sketches and stay out of the graph, and a call already drawn elsewhere isn't doubled.
+
+use crate::state::ProjectState;
+use crate::diagrams::Diagram;
+use crate::{Component, Connection, DiagramElement};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum NodeKind {
+    Component,
+    DiagramElement,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemNode {
+    pub id: String,
+    pub kind: NodeKind,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind")]
+pub enum EdgeVia {
+    Dependency,
+    Connection { connection_id: String, connection_type: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemEdge {
+    pub from: String,
+    pub to: String,
+    pub via: EdgeVia,
+}
+
+/// A node reached by walking edges backwards from a root, with the shortest chain that reaches it
+#[derive(Debug, Clone, Serialize)]
+pub struct Reached {
+    pub node: SystemNode,
+    pub distance: usize,
+    /// Edges from the reached node down to the root, in walking order
+    pub path: Vec<SystemEdge>,
+}
+
+/// The connection behind an `EdgeVia::Connection`, from the main diagram or any view
+pub fn connection<'a>(state: &'a ProjectState, id: &str) -> Option<&'a Connection> {
+    state
+        .connections
+        .iter()
+        .chain(state.views.iter().flat_map(|view| &view.connections))
+        .find(|c| c.id == id)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SystemGraph {
+    nodes: Vec<SystemNode>,
+    index: HashMap<String, usize>,
+    /// Linked diagram elements, to the node of their component
+    linked: HashMap<String, usize>,
+    edges: Vec<SystemEdge>,
+    outgoing: Vec<Vec<usize>>,
+    incoming: Vec<Vec<usize>>,
+}
+
+impl SystemGraph {
+    pub fn from_state(state: &ProjectState) -> Self {
+        let mut graph = Self::default();
+        for component in &state.project.components {
+            graph.add_component(component);
+        }
+        for element in &state.diagram {
+            match element.component_id.as_ref().and_then(|c| graph.index.get(c)) {
+                Some(&node) => {
+                    graph.linked.insert(element.id.clone(), node);
+                }
+                None => graph.add_element(element),
+            }
+        }
+
+        for component in &state.project.components {
+            for dependency in &component.dependencies {
+                graph.add_edge(&component.id, dependency, EdgeVia::Dependency);
+            }
+        }
+        for connection in &state.connections {
+            graph.add_edge(&connection.source_id, &connection.target_id, Self::via(connection));
+        }
+        for view in &state.views {
+            graph.add_view(view);
+        }
+        graph
+    }
+
+    fn via(connection: &Connection) -> EdgeVia {
+        EdgeVia::Connection {
+            connection_id: connection.id.clone(),
+            connection_type: connection.connection_type.clone(),
+        }
+    }
+
+    fn add_view(&mut self, view: &Diagram) {
+        let components: HashMap<&str, usize> = view
+            .elements
+            .iter()
+            .filter_map(|e| Some((e.id.as_str(), *self.index.get(e.component_id.as_ref()?)?)))
+            .collect();
+        let resolve = |id: &str| {
+            components.get(id).copied().or_else(|| {
+                self.index
+                    .get(id)
+                    .copied()
+                    .filter(|&node| self.nodes[node].kind == NodeKind::Component)
+            })
+        };
+        let calls: Vec<_> = view
+            .connections
+            .iter()
+            .filter_map(|c| Some((resolve(&c.source_id)?, resolve(&c.target_id)?, c)))
+            .collect();
+        for (from, to, connection) in calls {
+            let drawn = self.outgoing[from]
+                .iter()
+                .any(|&e| self.edges[e].to == self.nodes[to].id && matches!(self.edges[e].via, EdgeVia::Connection { .. }));
+            if !drawn {
+                let (from, to) = (self.nodes[from].id.clone(), self.nodes[to].id.clone());
+                self.add_edge(&from, &to, Self::via(connection));
+            }
+        }
+    }
+
+    fn add_node(&mut self, node: SystemNode) {
+        // Components win if a diagram element happens to reuse a component id
+        if self.index.contains_key(&node.id) {
+            return;
+        }
+        self.index.insert(node.id.clone(), self.nodes.len());
+        self.nodes.push(node);
+        self.outgoing.push(Vec::new());
+        self.incoming.push(Vec::new());
+    }
+
+    fn add_component(&mut self, component: &Component) {
+        self.add_node(SystemNode {
+            id: component.id.clone(),
+            kind: NodeKind::Component,
+            label: component.name.clone(),
+        });
+    }
+
+    fn add_element(&mut self, element: &DiagramElement) {
+        self.add_node(SystemNode {
+            id: element.id.clone(),
+            kind: NodeKind::DiagramElement,
+            label: element.label().to_string(),
+        });
+    }
+
+    fn find(&self, id: &str) -> Option<usize> {
+        self.index.get(id).or_else(|| self.linked.get(id)).copied()
+    }
+
+    fn add_edge(&mut self, from: &str, to: &str, via: EdgeVia) {
+        let (Some(f), Some(t)) = (self.find(from), self.find(to)) else {
+            return;
+        };
+        if f == t {
+            return;
+        }
+        let edge = self.edges.len();
+        self.edges.push(SystemEdge {
+            from: self.nodes[f].id.clone(),
+            to: self.nodes[t].id.clone(),
+            via,
+        });
+        self.outgoing[f].push(edge);
+        self.incoming[t].push(edge);
+    }
+
+    /// The node for `id`; a linked element's id gives its component's node
+    pub fn node(&self, id: &str) -> Option<&SystemNode> {
+        self.find(id).map(|i| &self.nodes[i])
+    }
+
+    pub fn nodes(&self) -> &[SystemNode] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[SystemEdge] {
+        &self.edges
+    }
+
+    /// Edges leaving `id`, i.e. what it relies on
+    pub fn outgoing(&self, id: &str) -> impl Iterator<Item = &SystemEdge> {
+        self.find(id)
+            .into_iter()
+            .flat_map(move |i| self.outgoing[i].iter().map(move |&e| &self.edges[e]))
+    }
+
+    /// Edges arriving at `id`, i.e. what relies on it
+    pub fn incoming(&self, id: &str) -> impl Iterator<Item = &SystemEdge> {
+        self.find(id)
+            .into_iter()
+            .flat_map(move |i| self.incoming[i].iter().map(move |&e| &self.edges[e]))
+    }
+
+    /// Whether following one or more edges from `from` arrives at `to`; `reaches(x, x)` means x is on a cycle
+    pub fn reaches(&self, from: &str, to: &str) -> bool {
+        let (Some(start), Some(target)) = (self.find(from), self.find(to)) else {
+            return false;
+        };
+        let mut seen = vec![false; self.nodes.len()];
+        let mut queue = VecDeque::from([start]);
+        while let Some(n) = queue.pop_front() {
+            for &e in &self.outgoing[n] {
+                let next = self.index[&self.edges[e].to];
+                if next == target {
+                    return true;
+                }
+                if !seen[next] {
+                    seen[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        false
+    }
+
+    /// Everything that directly or transitively relies on `root`, nearest first.
+    /// Breadth-first, so each node's path is a shortest one.
+    pub fn dependents(&self, root: &str) -> Vec<Reached> {
+        let Some(start) = self.find(root) else {
+            return Vec::new();
+        };
+
+        // via[n] is the edge used to first reach n
+        let mut via: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut distance: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        distance[start] = Some(0);
+        let mut order = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(n) = queue.pop_front() {
+            for &e in &self.incoming[n] {
+                let dependent = self.index[&self.edges[e].from];
+                if distance[dependent].is_none() {
+                    distance[dependent] = distance[n].map(|d| d + 1);
+                    via[dependent] = Some(e);
+                    order.push(dependent);
+                    queue.push_back(dependent);
+                }
+            }
+        }
+
+        order
+            .into_iter()
+            .map(|n| {
+                let mut path = Vec::new();
+                let mut current = n;
+                while let Some(e) = via[current] {
+                    path.push(self.edges[e].clone());
+                    current = self.index[&self.edges[e].to];
+                }
+                Reached {
+                    node: self.nodes[n].clone(),
+                    distance: distance[n].unwrap_or_default(),
+                    path,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, component_with, connection, state_with, strings};
+    use crate::ComponentType;
+
+    fn state() -> ProjectState {
+        let component = |id: &str, deps: &[&str]| Component {
+            name: id.to_uppercase(),
+            ..component_with(id, ComponentType::Service, deps, &[])
+        };
+        let mut state = state_with(vec![component("auth", &[]), component("api", &["auth"])]);
+        state.diagram = vec![DiagramElement {
+            element_type: "client".into(),
+            properties: strings(&[("label", "Web App")]),
+            ..test_support::element("web")
+        }];
+        state.connections = vec![connection("c1", "web", "api"), connection("c2", "web", "missing")];
+        state
+    }
+
+    #[test]
+    fn merges_dependencies_and_connections() {
+        let graph = SystemGraph::from_state(&state());
+        assert_eq!(graph.nodes.len(), 3);
+        // The connection to a missing element is dropped
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.node("web").unwrap().label, "Web App");
+        assert_eq!(graph.edges[0].via, EdgeVia::Dependency);
+        assert!(matches!(&graph.edges[1].via, EdgeVia::Connection { connection_id, .. } if connection_id == "c1"));
+    }
+
+    #[test]
+    fn dependents_carry_shortest_paths() {
+        let graph = SystemGraph::from_state(&state());
+        let reached = graph.dependents("auth");
+        assert_eq!(reached.iter().map(|r| (r.node.id.as_str(), r.distance)).collect::<Vec<_>>(), [("api", 1), ("web", 2)]);
+
+        let web = &reached[1];
+        assert_eq!(
+            web.path.iter().map(|e| (e.from.as_str(), e.to.as_str())).collect::<Vec<_>>(),
+            [("web", "api"), ("api", "auth")]
+        );
+        assert!(graph.dependents("web").is_empty());
+        assert!(graph.reaches("web", "auth"));
+        assert!(!graph.reaches("auth", "auth"));
+        assert!(graph.dependents("nope").is_empty());
+    }
+
+    #[test]
+    fn linked_elements_stand_for_their_component() {
+        let mut state = state();
+        state.diagram.push(DiagramElement {
+            component_id: Some("auth".into()),
+            ..test_support::element("auth-box")
+        });
+        state.diagram.push(DiagramElement {
+            component_id: Some("gone".into()),
+            ..test_support::element("stale")
+        });
+        state.connections.push(connection("c3", "web", "auth-box"));
+        let graph = SystemGraph::from_state(&state);
+
+        // The element linked to a missing component stays a node of its own
+        assert_eq!(graph.nodes.len(), 4);
+        assert!(graph.node("stale").is_some());
+        assert_eq!(graph.node("auth-box").unwrap().id, "auth");
+        assert_eq!((graph.edges[2].from.as_str(), graph.edges[2].to.as_str()), ("web", "auth"));
+        assert_eq!(graph.incoming("auth-box").count(), 2);
+        assert_eq!(graph.dependents("auth").iter().find(|r| r.node.id == "web").unwrap().distance, 1);
+    }
+
+    #[test]
+    fn views_add_calls_between_components() {
+        let mut state = state();
+        let linked = |id: &str, component: &str| DiagramElement {
+            component_id: Some(component.into()),
+            ..test_support::element(id)
+        };
+        state.diagram.push(linked("api-main", "api"));
+        state.connections.push(connection("main-call", "api-main", "auth"));
+        state.views.push(Diagram {
+            id: "v1".into(),
+            name: "Calls".into(),
+            kind: Default::default(),
+            elements: vec![linked("api-box", "api"), linked("auth-box", "auth"), test_support::element("note")],
+            connections: vec![
+                connection("v-call", "auth-box", "api-box"),
+                connection("v-dup", "api-box", "auth-box"),
+                connection("v-note", "note", "api-box"),
+            ],
+            diagram_revision: 1,
+        });
+        let graph = SystemGraph::from_state(&state);
+
+        // The view's unlinked note stays out, and api -> auth is already drawn on the main diagram
+        assert_eq!(graph.nodes.len(), 3);
+        let calls: Vec<_> = graph
+            .edges
+            .iter()
+            .filter_map(|e| match &e.via {
+                EdgeVia::Connection { connection_id, .. } => Some(connection_id.as_str()),
+                EdgeVia::Dependency => None,
+            })
+            .collect();
+        assert_eq!(calls, ["c1", "main-call", "v-call"]);
+        assert!(graph.reaches("auth", "auth"));
+    }
+}
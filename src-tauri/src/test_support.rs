@@ -0,0 +1,121 @@
+// Builders shared by the unit tests
+//
+// Each builder fills in every field, so tests override only what they care about with struct
+// update syntax and the models themselves need no `Default`.
+
+use crate::geometry::{DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use crate::state::ProjectState;
+use crate::{
+    Component, ComponentStatus, ComponentType, Connection, DiagramElement, Position, Project, ProjectSettings,
+    ProjectStatus,
+};
+use chrono::Utc;
+use std::collections::HashMap;
+
+/// An empty project with id `p`
+pub fn project() -> Project {
+    Project {
+        id: "p".into(),
+        name: "p".into(),
+        description: String::new(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        status: ProjectStatus::Planning,
+        components: vec![],
+        tags: vec![],
+        revision: 1,
+        settings: ProjectSettings::default(),
+    }
+}
+
+/// A project holding `components`
+pub fn project_with(components: Vec<Component>) -> Project {
+    Project { components, ..project() }
+}
+
+/// State for a project holding `components`, with an empty main diagram
+pub fn state_with(components: Vec<Component>) -> ProjectState {
+    ProjectState::new(project_with(components))
+}
+
+/// State whose main diagram holds `elements` and `connections`
+pub fn diagram_state(elements: Vec<DiagramElement>, connections: Vec<Connection>) -> ProjectState {
+    let mut state = ProjectState::new(project());
+    state.diagram = elements;
+    state.connections = connections;
+    state
+}
+
+/// A service named after its id
+pub fn component(id: &str) -> Component {
+    Component {
+        id: id.into(),
+        name: id.into(),
+        component_type: ComponentType::Service,
+        description: String::new(),
+        dependencies: vec![],
+        status: ComponentStatus::NotStarted,
+        metadata: HashMap::new(),
+        revision: 1,
+        capacity: None,
+        availability: None,
+    }
+}
+
+/// A component of the given type with its dependencies and metadata
+pub fn component_with(
+    id: &str,
+    component_type: ComponentType,
+    dependencies: &[&str],
+    metadata: &[(&str, &str)],
+) -> Component {
+    Component {
+        component_type,
+        dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+        metadata: strings(metadata),
+        ..component(id)
+    }
+}
+
+/// A default-sized service box at the origin
+pub fn element(id: &str) -> DiagramElement {
+    DiagramElement {
+        id: id.into(),
+        element_type: "service".into(),
+        position: Position { x: 0.0, y: 0.0 },
+        width: DEFAULT_WIDTH,
+        height: DEFAULT_HEIGHT,
+        rotation: 0.0,
+        z_index: 0,
+        parent_id: None,
+        component_id: None,
+        properties: HashMap::new(),
+    }
+}
+
+/// A 100 x 50 service box with its top-left corner at (x, y)
+pub fn element_at(id: &str, x: f64, y: f64) -> DiagramElement {
+    DiagramElement {
+        position: Position { x, y },
+        width: 100.0,
+        height: 50.0,
+        ..element(id)
+    }
+}
+
+pub fn connection(id: &str, source_id: &str, target_id: &str) -> Connection {
+    Connection {
+        id: id.into(),
+        source_id: source_id.into(),
+        target_id: target_id.into(),
+        connection_type: "http".into(),
+        properties: HashMap::new(),
+        latency: None,
+        waypoints: vec![],
+    }
+}
+
+/// Metadata or properties from `(key, value)` pairs
+pub fn strings(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
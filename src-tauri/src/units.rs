@@ -0,0 +1,218 @@
+// Units for design estimates
+//
+// Sizes are stored in bytes and durations in seconds. Inputs accept either a plain number
+// in those base units or a string with a unit suffix, e.g. "2 KB", "1.5GiB", "250ms", "90 d".
+// Decimal prefixes (KB = 1000 B) are used for display, matching back-of-envelope practice.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+pub const SECONDS_PER_DAY: f64 = 86_400.0;
+
+const SIZE_UNITS: &[(&str, f64)] = &[
+    ("b", 1.0),
+    ("kb", 1e3),
+    ("mb", 1e6),
+    ("gb", 1e9),
+    ("tb", 1e12),
+    ("pb", 1e15),
+    ("kib", 1024.0),
+    ("mib", 1_048_576.0),
+    ("gib", 1_073_741_824.0),
+    ("tib", 1_099_511_627_776.0),
+];
+
+const TIME_UNITS: &[(&str, f64)] = &[
+    ("ms", 0.001),
+    ("s", 1.0),
+    ("sec", 1.0),
+    ("m", 60.0),
+    ("min", 60.0),
+    ("h", 3_600.0),
+    ("d", SECONDS_PER_DAY),
+    ("w", 7.0 * SECONDS_PER_DAY),
+    ("mo", 30.0 * SECONDS_PER_DAY),
+    ("y", 365.0 * SECONDS_PER_DAY),
+];
+
+fn parse_quantity(input: &str, units: &[(&str, f64)], default_unit: &str) -> Result<f64, String> {
+    let input = input.trim();
+    // The longest prefix that parses as a number, so "1e3 MB" and "2EB" both split correctly
+    let split = (1..=input.len())
+        .rev()
+        .filter(|&i| input.is_char_boundary(i))
+        .find(|&i| input[..i].trim().parse::<f64>().is_ok())
+        .ok_or_else(|| format!("Invalid number in '{}'", input))?;
+    let (number, unit) = input.split_at(split);
+    let value: f64 = number.trim().parse().map_err(|_| format!("Invalid number in '{}'", input))?;
+    let unit = match unit.trim().to_lowercase() {
+        u if u.is_empty() => default_unit.to_string(),
+        u => u,
+    };
+    let factor = units
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, factor)| *factor)
+        .ok_or_else(|| format!("Unknown unit '{}' in '{}'", unit, input))?;
+
+    let value = value * factor;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("'{}' must be a non-negative amount", input));
+    }
+    Ok(value)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawQuantity {
+    Number(f64),
+    Text(String),
+}
+
+macro_rules! quantity_type {
+    ($name:ident, $units:expr, $default_unit:expr, $doc:literal) => {
+        #[doc = $doc]
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+        pub struct $name(pub f64);
+
+        impl $name {
+            pub fn parse(input: &str) -> Result<Self, String> {
+                parse_quantity(input, $units, $default_unit).map($name)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_f64(self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                match RawQuantity::deserialize(deserializer)? {
+                    RawQuantity::Number(n) if n.is_finite() && n >= 0.0 => Ok($name(n)),
+                    RawQuantity::Number(n) => Err(serde::de::Error::custom(format!("{} must be non-negative", n))),
+                    RawQuantity::Text(s) => $name::parse(&s).map_err(serde::de::Error::custom),
+                }
+            }
+        }
+    };
+}
+
+quantity_type!(DataSize, SIZE_UNITS, "b", "A size in bytes");
+quantity_type!(TimeSpan, TIME_UNITS, "s", "A duration in seconds");
+
+impl fmt::Display for DataSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_scaled(self.0, &["B", "KB", "MB", "GB", "TB", "PB", "EB"]))
+    }
+}
+
+impl fmt::Display for TimeSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0;
+        if seconds < 1.0 {
+            write!(f, "{} ms", trim_number(seconds * 1000.0))
+        } else if seconds < 60.0 {
+            write!(f, "{} s", trim_number(seconds))
+        } else if seconds < 3_600.0 {
+            write!(f, "{} min", trim_number(seconds / 60.0))
+        } else if seconds < SECONDS_PER_DAY {
+            write!(f, "{} h", trim_number(seconds / 3_600.0))
+        } else {
+            write!(f, "{} d", trim_number(seconds / SECONDS_PER_DAY))
+        }
+    }
+}
+
+// Three significant figures is plenty for an estimate
+fn trim_number(value: f64) -> String {
+    let rounded = if value >= 100.0 {
+        format!("{:.0}", value)
+    } else if value >= 10.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{:.2}", value)
+    };
+    if rounded.contains('.') {
+        rounded.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        rounded
+    }
+}
+
+fn format_scaled(value: f64, units: &[&str]) -> String {
+    let mut scaled = value;
+    let mut unit = 0;
+    while scaled >= 1000.0 && unit + 1 < units.len() {
+        scaled /= 1000.0;
+        unit += 1;
+    }
+    format!("{} {}", trim_number(scaled), units[unit])
+}
+
+fn format_rate(per_second: f64) -> String {
+    format_scaled(per_second, &["req/s", "K req/s", "M req/s", "B req/s"])
+}
+
+fn format_throughput(bytes_per_second: f64) -> String {
+    format!("{}/s", DataSize(bytes_per_second))
+}
+
+/// A value in base units with a human-readable rendering
+#[derive(Debug, Clone, Serialize)]
+pub struct Measure {
+    pub value: f64,
+    pub display: String,
+}
+
+impl Measure {
+    pub fn rate(per_second: f64) -> Self {
+        Self { value: per_second, display: format_rate(per_second) }
+    }
+
+    pub fn throughput(bytes_per_second: f64) -> Self {
+        Self { value: bytes_per_second, display: format_throughput(bytes_per_second) }
+    }
+
+    pub fn size(bytes: f64) -> Self {
+        Self { value: bytes, display: DataSize(bytes).to_string() }
+    }
+
+    pub fn duration(seconds: f64) -> Self {
+        Self { value: seconds, display: TimeSpan(seconds).to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_and_unit_strings() {
+        assert_eq!(DataSize::parse("2 KB").unwrap(), DataSize(2000.0));
+        assert_eq!(DataSize::parse("1.5GiB").unwrap(), DataSize(1.5 * 1_073_741_824.0));
+        assert_eq!(DataSize::parse("512").unwrap(), DataSize(512.0));
+        assert_eq!(DataSize::parse("1e3 mb").unwrap(), DataSize(1e9));
+        assert_eq!(TimeSpan::parse("250ms").unwrap(), TimeSpan(0.25));
+        assert_eq!(TimeSpan::parse("5 y").unwrap(), TimeSpan(5.0 * 365.0 * SECONDS_PER_DAY));
+        assert!(DataSize::parse("12 parsecs").is_err());
+        assert!(TimeSpan::parse("-3 d").is_err());
+
+        let size: DataSize = serde_json::from_str("\"10 MB\"").unwrap();
+        assert_eq!(size, DataSize(1e7));
+        let span: TimeSpan = serde_json::from_str("30").unwrap();
+        assert_eq!(span, TimeSpan(30.0));
+        assert!(serde_json::from_str::<DataSize>("-1").is_err());
+    }
+
+    #[test]
+    fn formats_with_decimal_prefixes() {
+        assert_eq!(DataSize(1_536.0).to_string(), "1.54 KB");
+        assert_eq!(DataSize(3.2e12).to_string(), "3.2 TB");
+        assert_eq!(format_rate(11_574.07), "11.6 K req/s");
+        assert_eq!(format_throughput(250.0), "250 B/s");
+        assert_eq!(TimeSpan(0.045).to_string(), "45 ms");
+        assert_eq!(TimeSpan(7_200.0).to_string(), "2 h");
+    }
+}